@@ -0,0 +1,11 @@
+//! `cargo bench` entry point, running every scenario of the library under
+//! criterion's own argument handling.
+
+use criterion::{criterion_group, criterion_main};
+use tx_bench::alloc::CountingAllocator;
+
+#[global_allocator]
+static GLOBAL: CountingAllocator = CountingAllocator;
+
+criterion_group!(benches, tx_bench::benchmark);
+criterion_main!(benches);
@@ -0,0 +1,169 @@
+//! A counting global allocator, so that benchmarks can report heap usage
+//! alongside the criterion timings.
+//!
+//! The counters are process-wide, so they count the allocations of every
+//! thread. An async operation is only measured on its own if it runs on
+//! the benchmark's thread, e.g. on the [`runtime`] made for it rather than
+//! on a multi-threaded one.
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+
+use tokio::runtime::{Builder, Runtime};
+
+/// Bytes currently live on the heap
+static LIVE: AtomicUsize = AtomicUsize::new(0);
+/// High-water mark of `LIVE` since the last `Region::start`
+static PEAK: AtomicUsize = AtomicUsize::new(0);
+/// Number of allocations performed
+static COUNT: AtomicU64 = AtomicU64::new(0);
+/// Total number of bytes handed out, regardless of frees
+static BYTES: AtomicU64 = AtomicU64::new(0);
+
+/// Wraps the system allocator and keeps track of every allocation made
+/// through it.
+pub struct CountingAllocator;
+
+impl CountingAllocator {
+    fn on_alloc(size: usize) {
+        let live = LIVE.fetch_add(size, Ordering::Relaxed) + size;
+        PEAK.fetch_max(live, Ordering::Relaxed);
+        COUNT.fetch_add(1, Ordering::Relaxed);
+        BYTES.fetch_add(size as u64, Ordering::Relaxed);
+    }
+
+    fn on_dealloc(size: usize) {
+        LIVE.fetch_sub(size, Ordering::Relaxed);
+    }
+}
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let ptr = System.alloc(layout);
+        if !ptr.is_null() {
+            Self::on_alloc(layout.size());
+        }
+        ptr
+    }
+
+    unsafe fn alloc_zeroed(&self, layout: Layout) -> *mut u8 {
+        let ptr = System.alloc_zeroed(layout);
+        if !ptr.is_null() {
+            Self::on_alloc(layout.size());
+        }
+        ptr
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout);
+        Self::on_dealloc(layout.size());
+    }
+
+    unsafe fn realloc(
+        &self,
+        ptr: *mut u8,
+        layout: Layout,
+        new_size: usize,
+    ) -> *mut u8 {
+        let new_ptr = System.realloc(ptr, layout, new_size);
+        if !new_ptr.is_null() {
+            // A realloc is accounted as freeing the old block and allocating
+            // a new one
+            Self::on_dealloc(layout.size());
+            Self::on_alloc(new_size);
+        }
+        new_ptr
+    }
+}
+
+/// Heap usage of a single benchmarked operation
+#[derive(Debug, Clone, Copy, Default)]
+pub struct AllocStats {
+    /// Peak heap size above the live heap at the start of the operation
+    pub peak: usize,
+    /// Number of allocations
+    pub count: u64,
+    /// Bytes allocated in total
+    pub bytes: u64,
+}
+
+/// A measured region of execution. The counters are global, so regions
+/// must not overlap, and allocations on other threads count towards them.
+pub struct Region {
+    live: usize,
+    count: u64,
+    bytes: u64,
+}
+
+impl Region {
+    /// Start measuring, resetting the peak to the current live heap
+    pub fn start() -> Self {
+        let live = LIVE.load(Ordering::Relaxed);
+        PEAK.store(live, Ordering::Relaxed);
+        Self {
+            live,
+            count: COUNT.load(Ordering::Relaxed),
+            bytes: BYTES.load(Ordering::Relaxed),
+        }
+    }
+
+    /// Stop measuring and return what was allocated since `start`
+    pub fn finish(self) -> AllocStats {
+        AllocStats {
+            peak: PEAK.load(Ordering::Relaxed).saturating_sub(self.live),
+            count: COUNT.load(Ordering::Relaxed) - self.count,
+            bytes: BYTES.load(Ordering::Relaxed) - self.bytes,
+        }
+    }
+}
+
+/// A runtime that runs the measured futures on the benchmark's own thread,
+/// so that their regions don't count the allocations of worker threads
+pub fn runtime() -> Runtime {
+    Builder::new_current_thread()
+        .enable_all()
+        .build()
+        .expect("Unable to build the runtime")
+}
+
+/// Collects the `AllocStats` of every iteration of a benchmark. Cheap to
+/// clone, so it can be moved into the async benchmark routine.
+#[derive(Debug, Clone, Default)]
+pub struct Samples(Arc<Mutex<Vec<AllocStats>>>);
+
+impl Samples {
+    pub fn push(&self, stats: AllocStats) {
+        self.0.lock().unwrap().push(stats);
+    }
+
+    /// Print a summary line for the benchmark `id`, meant to sit right
+    /// below criterion's own timing output
+    pub fn report(&self, id: &str) {
+        let samples = self.0.lock().unwrap();
+        if samples.is_empty() {
+            return;
+        }
+        let n = samples.len() as u64;
+        let peak = samples.iter().map(|s| s.peak).max().unwrap_or_default();
+        let count = samples.iter().map(|s| s.count).sum::<u64>() / n;
+        let bytes = samples.iter().map(|s| s.bytes).sum::<u64>() / n;
+        println!(
+            "{id:<40} heap: peak {}, {count} allocs, {} allocated (mean of \
+             {n} iterations)",
+            human_bytes(peak as u64),
+            human_bytes(bytes),
+        );
+    }
+}
+
+fn human_bytes(bytes: u64) -> String {
+    const UNITS: [&str; 4] = ["B", "KiB", "MiB", "GiB"];
+    let mut value = bytes as f64;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+    format!("{value:.1} {}", UNITS[unit])
+}
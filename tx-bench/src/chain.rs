@@ -0,0 +1,327 @@
+//! A connection to the benchmarked chain for scenarios that need more
+//! state than the genesis has, e.g. bonds, validators or proposals. The
+//! txs are built by hand and submitted as fee-less wrappers, waiting for
+//! each one to be committed, unless they're only broadcasted to be waited
+//! on together.
+
+use std::collections::HashMap;
+use std::str::FromStr;
+use std::time::{Duration, Instant};
+
+use borsh::BorshSerialize;
+use namada::ledger::pos::{self as pos_storage, PosParams};
+use namada::ledger::rpc;
+use namada::proto::Tx;
+use namada::types::address::Address;
+use namada::types::chain::ChainId;
+use namada::types::key::common::{PublicKey, SecretKey};
+use namada::types::key::dkg_session_keys::DkgKeypair;
+use namada::types::key::{ed25519, RefTo, SigScheme};
+use namada::types::storage::Epoch;
+use namada::types::token;
+use namada::types::transaction::{
+    pos, EllipticCurve, Fee, GasLimit, InitValidator,
+};
+use rust_decimal::Decimal;
+use tendermint_config::net::Address as TendermintAddress;
+use tendermint_rpc::{Client, HttpClient};
+
+use crate::keys::Keyring;
+use crate::submit::{self, Committed};
+use crate::tx::{self, ledger_address, NATIVE_TOKEN};
+use crate::wasm;
+
+/// How often to poll the node for a new epoch
+const EPOCH_POLL_INTERVAL: Duration = Duration::from_millis(200);
+
+/// An account whose key we hold
+#[derive(Debug, Clone)]
+pub struct Account {
+    pub address: Address,
+    pub key: SecretKey,
+}
+
+impl Account {
+    /// A new implicit account, which exists on chain once it's funded
+    pub fn implicit() -> Self {
+        let key = SecretKey::Ed25519(ed25519::SigScheme::generate(
+            &mut rand::rngs::OsRng,
+        ));
+        Self {
+            address: (&key.ref_to()).into(),
+            key,
+        }
+    }
+
+    pub fn public_key(&self) -> PublicKey {
+        self.key.ref_to()
+    }
+}
+
+/// The chain the benchmarks run against
+pub struct Chain {
+    pub client: HttpClient,
+    pub chain_id: ChainId,
+    pub faucet: Account,
+    pub native_token: Address,
+    /// The wasm artifacts read so far, by name
+    wasm: HashMap<String, Vec<u8>>,
+    /// The wallet every signing key is taken out of before signing
+    keyring: Keyring,
+}
+
+impl Chain {
+    /// Connect to the ledger the benchmarks are configured with
+    pub async fn connect() -> Self {
+        Self::connect_to(ledger_address()).await
+    }
+
+    pub async fn connect_to(address: TendermintAddress) -> Self {
+        let client = HttpClient::new(address).unwrap();
+        let chain_id = submit::chain_id(&client).await;
+        let (address, key) = tx::faucet();
+        let faucet = Account { address, key };
+        let mut keyring = Keyring::configured();
+        keyring.register(&faucet.key);
+        Self {
+            client,
+            chain_id,
+            faucet,
+            native_token: Address::from_str(NATIVE_TOKEN)
+                .expect("Unable to construct native token"),
+            wasm: HashMap::new(),
+            keyring,
+        }
+    }
+
+    /// The artifact of the wasm `name` in the wasm dir
+    pub fn wasm(&mut self, name: &str) -> Vec<u8> {
+        self.wasm
+            .entry(name.to_string())
+            .or_insert_with(|| wasm::read(wasm::wasm_dir(), name))
+            .clone()
+    }
+
+    pub async fn epoch(&self) -> Epoch {
+        rpc::query_epoch(&self.client).await
+    }
+
+    pub async fn pos_params(&self) -> PosParams {
+        rpc::query_storage_value(&self.client, &pos_storage::params_key())
+            .await
+            .expect("Unable to query the PoS parameters")
+    }
+
+    /// Block until the chain has reached `epoch`
+    pub async fn wait_for_epoch(&self, epoch: Epoch) {
+        while self.epoch().await < epoch {
+            tokio::time::sleep(EPOCH_POLL_INTERVAL).await;
+        }
+    }
+
+    /// Run the tx `code` (e.g. `tx_bond`) with `data`, signed by `signer`
+    /// if any, and wait for it to be committed. The signing key is taken
+    /// out of the keyring first, as a wallet user would.
+    pub async fn submit(
+        &mut self,
+        code: &str,
+        data: Vec<u8>,
+        signer: Option<&SecretKey>,
+    ) -> Option<Committed> {
+        let tx = self.wrap_signed(code, data, signer).await;
+        submit::broadcast_and_wait(&self.client, tx).await
+    }
+
+    /// Like `submit`, but only broadcast the tx, as in
+    /// `SubmitMode::BroadcastOnly`. Returns the hash its commit is reported
+    /// with and when it was broadcasted, for `submit::wait_for_commits`,
+    /// unless the mempool rejected it.
+    pub async fn broadcast(
+        &mut self,
+        code: &str,
+        data: Vec<u8>,
+        signer: Option<&SecretKey>,
+    ) -> Option<(String, Instant)> {
+        let tx = self.wrap_signed(code, data, signer).await;
+        let hash = submit::applied_hash(&tx);
+        let broadcasted = Instant::now();
+        let response = self
+            .client
+            .broadcast_tx_sync(tx.into())
+            .await
+            .expect("Unable to broadcast the tx");
+        response.code.is_ok().then_some((hash, broadcasted))
+    }
+
+    /// The tx `code` with `data`, signed by `signer` if any and wrapped,
+    /// ready to be broadcasted
+    async fn wrap_signed(
+        &mut self,
+        code: &str,
+        data: Vec<u8>,
+        signer: Option<&SecretKey>,
+    ) -> Vec<u8> {
+        let tx =
+            Tx::new(self.wasm(code), Some(data), self.chain_id.clone(), None);
+        let signer = signer.map(|signer| self.keyring.decrypt(signer));
+        let tx = match &signer {
+            Some(signer) => tx.sign(signer),
+            None => tx,
+        };
+        let wrapper_signer = match signer {
+            Some(signer) => signer,
+            None => self.keyring.decrypt(&self.faucet.key),
+        };
+        submit::wrap(
+            tx,
+            &wrapper_signer,
+            &self.chain_id,
+            self.epoch().await,
+            &self.native_token,
+        )
+    }
+
+    /// Like `submit`, with the wrapper signed by the faucet key, which pays
+    /// the `fee` for it, and with the given gas limit
+    pub async fn submit_with_fee(
+        &mut self,
+        code: &str,
+        data: Vec<u8>,
+        signer: Option<&SecretKey>,
+        fee: Fee,
+        gas_limit: GasLimit,
+    ) -> Option<Committed> {
+        let tx =
+            Tx::new(self.wasm(code), Some(data), self.chain_id.clone(), None);
+        let tx = match signer {
+            Some(signer) => tx.sign(&self.keyring.decrypt(signer)),
+            None => tx,
+        };
+        let fee_payer = self.keyring.decrypt(&self.faucet.key);
+        let tx = submit::wrap_with_fee(
+            tx,
+            &fee_payer,
+            &self.chain_id,
+            self.epoch().await,
+            fee,
+            gas_limit,
+        );
+        submit::broadcast_and_wait(&self.client, tx).await
+    }
+
+    /// Like `submit`, for setup txs that the benchmark can't do without
+    pub async fn apply(
+        &mut self,
+        code: &str,
+        data: Vec<u8>,
+        signer: Option<&SecretKey>,
+    ) -> Committed {
+        let committed = self.submit(code, data, signer).await;
+        match committed {
+            Some(committed) if committed.code == "0" => committed,
+            _ => panic!("Unable to apply {code}: {committed:?}"),
+        }
+    }
+
+    /// Transfer `amount` native tokens from the faucet to `target`
+    pub async fn fund(&mut self, target: &Address, amount: token::Amount) {
+        let transfer = token::Transfer {
+            source: self.faucet.address.clone(),
+            target: target.clone(),
+            token: self.native_token.clone(),
+            sub_prefix: None,
+            amount,
+            key: None,
+            shielded: None,
+        };
+        let faucet_key = self.faucet.key.clone();
+        self.apply(
+            "tx_transfer",
+            transfer.try_to_vec().unwrap(),
+            Some(&faucet_key),
+        )
+        .await;
+    }
+
+    /// Create a funded implicit account with its public key revealed
+    pub async fn new_account(&mut self, funds: token::Amount) -> Account {
+        let account = Account::implicit();
+        self.keyring.register(&account.key);
+        self.fund(&account.address, funds).await;
+        self.apply(
+            "tx_reveal_pk",
+            account.public_key().try_to_vec().unwrap(),
+            None,
+        )
+        .await;
+        account
+    }
+
+    /// Bond `amount` from `source` to `validator`, which is a self-bond if
+    /// the source is the validator
+    pub async fn bond(
+        &mut self,
+        source: &Account,
+        validator: &Address,
+        amount: token::Amount,
+    ) {
+        let bond = pos::Bond {
+            validator: validator.clone(),
+            amount,
+            source: (&source.address != validator)
+                .then(|| source.address.clone()),
+        };
+        self.apply("tx_bond", bond.try_to_vec().unwrap(), Some(&source.key))
+            .await;
+    }
+
+    /// Register a new validator with fresh keys, paid for by the faucet.
+    /// It has no stake until it's bonded to.
+    pub async fn init_validator(&mut self) -> Account {
+        let (key, data) = self.init_validator_data();
+        let faucet_key = self.faucet.key.clone();
+        let committed = self
+            .apply(
+                "tx_init_validator",
+                data.try_to_vec().unwrap(),
+                Some(&faucet_key),
+            )
+            .await;
+        let address = committed
+            .initialized_accounts
+            .first()
+            .expect("The validator must have been initialized");
+        Account {
+            address: Address::from_str(address)
+                .expect("Invalid validator address"),
+            key,
+        }
+    }
+
+    /// The data of `tx_init_validator` for fresh keys, and the validator's
+    /// account key
+    pub fn init_validator_data(&mut self) -> (SecretKey, InitValidator) {
+        let key = || {
+            SecretKey::Ed25519(ed25519::SigScheme::generate(
+                &mut rand::rngs::OsRng,
+            ))
+        };
+        let account_key = key();
+        self.keyring.register(&account_key);
+        let dkg_key = DkgKeypair::from(
+            ferveo_common::Keypair::<EllipticCurve>::new(
+                &mut rand::rngs::OsRng,
+            ),
+        );
+        let data = InitValidator {
+            account_key: account_key.ref_to(),
+            consensus_key: key().ref_to(),
+            protocol_key: key().ref_to(),
+            dkg_key: dkg_key.public(),
+            commission_rate: Decimal::new(5, 2),
+            max_commission_rate_change: Decimal::new(1, 2),
+            validator_vp_code: self.wasm("vp_validator"),
+        };
+        (account_key, data)
+    }
+}
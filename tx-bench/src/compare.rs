@@ -0,0 +1,214 @@
+//! Comparing two criterion baselines saved with `run --save-baseline`.
+//! Criterion keeps the estimates of every benchmark and baseline in
+//! `<criterion-dir>/<benchmark-id>/<baseline>/estimates.json`.
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::devnet::Devnet;
+use crate::results::Table;
+use crate::{wasm, Scenario};
+
+/// Where criterion puts its results, unless overridden by `CRITERION_HOME`
+/// or `CARGO_TARGET_DIR`
+const CRITERION_DIR: &str = "target/criterion";
+
+/// The directory criterion writes its results to
+pub fn criterion_dir() -> PathBuf {
+    if let Ok(home) = std::env::var("CRITERION_HOME") {
+        return PathBuf::from(home);
+    }
+    match std::env::var("CARGO_TARGET_DIR") {
+        Ok(target) => Path::new(&target).join("criterion"),
+        Err(_) => PathBuf::from(CRITERION_DIR),
+    }
+}
+
+/// A criterion estimate with its confidence interval, in nanoseconds
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Estimate {
+    pub point: f64,
+    pub lower: f64,
+    pub upper: f64,
+}
+
+/// The median time of every benchmark in a saved baseline by benchmark ID
+pub fn baseline(dir: &Path, name: &str) -> BTreeMap<String, Estimate> {
+    let mut medians = BTreeMap::new();
+    collect(dir, dir, name, &mut medians);
+    medians
+}
+
+fn collect(
+    root: &Path,
+    dir: &Path,
+    name: &str,
+    medians: &mut BTreeMap<String, Estimate>,
+) {
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(_) => return,
+    };
+    for path in entries.filter_map(|entry| entry.ok().map(|e| e.path())) {
+        if !path.is_dir() {
+            continue;
+        }
+        let estimates = path.join("estimates.json");
+        if path.file_name().and_then(|n| n.to_str()) == Some(name)
+            && estimates.exists()
+        {
+            let id = dir
+                .strip_prefix(root)
+                .unwrap_or(dir)
+                .to_string_lossy()
+                .to_string();
+            medians.insert(id, median(&estimates));
+        } else {
+            collect(root, &path, name, medians);
+        }
+    }
+}
+
+/// Read the median out of a criterion `estimates.json`
+fn median(path: &Path) -> Estimate {
+    let estimates = fs::read_to_string(path).unwrap_or_else(|err| {
+        panic!("Unable to read {}: {err}", path.display())
+    });
+    let estimates: serde_json::Value =
+        serde_json::from_str(&estimates).expect("Invalid estimates.json");
+    let median = &estimates["median"];
+    let value = |value: &serde_json::Value| {
+        value
+            .as_f64()
+            .unwrap_or_else(|| panic!("No median in {}", path.display()))
+    };
+    Estimate {
+        point: value(&median["point_estimate"]),
+        lower: value(&median["confidence_interval"]["lower_bound"]),
+        upper: value(&median["confidence_interval"]["upper_bound"]),
+    }
+}
+
+/// The medians of one benchmark in both baselines
+#[derive(Debug, Clone)]
+pub struct Change {
+    pub id: String,
+    pub before: Option<Estimate>,
+    pub after: Option<Estimate>,
+}
+
+impl Change {
+    /// Relative change of the median, in percent
+    pub fn percent(&self) -> Option<f64> {
+        match (self.before, self.after) {
+            (Some(before), Some(after)) if before.point > 0.0 => {
+                Some(100.0 * (after.point - before.point) / before.point)
+            }
+            _ => None,
+        }
+    }
+
+    /// Bounds of the relative change, in percent, from the confidence
+    /// intervals of both medians. Taking the extremes of both intervals
+    /// makes this wider than the interval criterion itself would report for
+    /// the change, so a change outside of it can be trusted.
+    pub fn interval(&self) -> Option<(f64, f64)> {
+        match (self.before, self.after) {
+            (Some(before), Some(after)) if before.point > 0.0 => {
+                let percent = |delta: f64| 100.0 * delta / before.point;
+                Some((
+                    percent(after.lower - before.upper),
+                    percent(after.upper - before.lower),
+                ))
+            }
+            _ => None,
+        }
+    }
+
+    /// Whether the change is clear of the noise, and in which direction
+    pub fn verdict(&self) -> Option<&'static str> {
+        let (lower, upper) = self.interval()?;
+        Some(if upper < 0.0 {
+            "faster"
+        } else if lower > 0.0 {
+            "slower"
+        } else {
+            "no change"
+        })
+    }
+}
+
+/// Pair up the benchmarks of the baselines `before` and `after`
+pub fn compare(dir: &Path, before: &str, after: &str) -> Vec<Change> {
+    let mut before = baseline(dir, before);
+    let mut after = baseline(dir, after);
+    let mut ids: Vec<String> =
+        before.keys().chain(after.keys()).cloned().collect();
+    ids.sort();
+    ids.dedup();
+    ids.into_iter()
+        .map(|id| Change {
+            before: before.remove(&id),
+            after: after.remove(&id),
+            id,
+        })
+        .collect()
+}
+
+/// One row per benchmark, with the medians in nanoseconds
+pub fn table(changes: &[Change]) -> Table {
+    let mut table = Table::new(&[
+        "id",
+        "before_ns",
+        "after_ns",
+        "change_percent",
+        "change_lower_percent",
+        "change_upper_percent",
+        "verdict",
+    ]);
+    let round = |percent: f64| (percent * 100.0).round() / 100.0;
+    for change in changes {
+        let interval = change.interval();
+        table.push(vec![
+            change.id.clone().into(),
+            change.before.map(|before| before.point.round()).into(),
+            change.after.map(|after| after.point.round()).into(),
+            change.percent().map(round).into(),
+            interval.map(|(lower, _)| round(lower)).into(),
+            interval.map(|(_, upper)| round(upper)).into(),
+            change.verdict().into(),
+        ]);
+    }
+    table
+}
+
+/// Baseline the runs against the first set of wasm artifacts are saved as
+const AB_BEFORE: &str = "ab-before";
+/// Baseline the runs against the second set of wasm artifacts are saved as
+const AB_AFTER: &str = "ab-after";
+
+/// Run the same scenarios with the wasm artifacts of `before` and then of
+/// `after` and compare the two runs. With a `devnet` genesis and validator
+/// count, every run gets a devnet of its own initialized with its
+/// artifacts, so that changes to the VPs are measured too. Otherwise only
+/// the tx code sent along with the txs differs between the runs.
+pub fn ab(
+    before: &Path,
+    after: &Path,
+    scenarios: &[&Scenario],
+    filter: Option<String>,
+    devnet: Option<(&Path, u16)>,
+) -> Vec<Change> {
+    for (wasm_dir, baseline) in [(before, AB_BEFORE), (after, AB_AFTER)] {
+        println!("Running against the wasm in {}", wasm_dir.display());
+        std::env::set_var(wasm::ENV_VAR_WASM_DIR, wasm_dir);
+        let _devnet = devnet.map(|(genesis, validators)| {
+            let devnet = Devnet::start(genesis, wasm_dir, validators);
+            devnet.export_env();
+            devnet
+        });
+        crate::run(scenarios, filter.clone(), Some(baseline.to_string()));
+    }
+    compare(&criterion_dir(), AB_BEFORE, AB_AFTER)
+}
@@ -0,0 +1,422 @@
+//! A pre-built corpus of signed transactions. Building a shielded transfer
+//! takes seconds, so the txs of every kind are built and signed once and
+//! written to disk. Replaying the corpus then submits the ready wrapper txs
+//! to a node, or to a ledger run in this process with `--in-process`, which
+//! measures validation and execution without any client-side construction.
+//!
+//! The corpus only has the kinds of txs that the genesis state is enough
+//! for, as it's signed in one go before any of it is applied. These are
+//! left out:
+//!
+//! - `tx_vote_proposal`, as a proposal can only be voted on once its voting
+//!   period has started, epochs after it's been created
+//! - `tx_withdraw`, as an unbond only matures epochs after it's been
+//!   applied
+//! - `tx_ibc` messages other than creating a client, as they need the
+//!   client, connection or channel that an earlier message creates
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+use std::str::FromStr;
+use std::time::Duration;
+
+use borsh::{BorshDeserialize, BorshSerialize};
+use namada::ledger::rpc;
+use namada::proto::Tx;
+use namada::types::address::{self, Address};
+use namada::types::chain::ChainId;
+use namada::types::key::common::SecretKey;
+use namada::types::key::{ed25519, RefTo, SigScheme};
+use namada::types::masp::TransferTarget;
+use namada::types::storage::Epoch;
+use namada::types::token;
+use namada::types::transaction::governance::{
+    InitProposalData, ProposalType,
+};
+use namada::types::transaction::{pos, InitAccount, UpdateVp};
+use tendermint_rpc::{Client, HttpClient};
+
+use crate::chain::Chain;
+use crate::governance;
+use crate::ibc;
+use crate::metrics;
+use crate::submit::{self, SubmitMode};
+use crate::tx::{
+    self, ledger_address, shielded, transfer_setup, TransferKind,
+    NATIVE_TOKEN,
+};
+use crate::wasm;
+
+/// Leading bytes of every corpus file
+const MAGIC: [u8; 8] = *b"TXBCORPS";
+/// Version of the corpus file format. Bump this whenever `Corpus` or the
+/// encoding of the txs in it changes.
+pub const CORPUS_VERSION: u32 = 1;
+
+/// A set of signed wrapper txs for one chain
+#[derive(Debug, BorshSerialize, BorshDeserialize)]
+pub struct Corpus {
+    /// The chain the txs were signed for
+    pub chain_id: String,
+    /// The epoch the wrappers were built in
+    pub epoch: u64,
+    pub entries: Vec<Entry>,
+}
+
+/// A single signed tx
+#[derive(Debug, BorshSerialize, BorshDeserialize)]
+pub struct Entry {
+    /// The kind of tx, e.g. `tx_transfer/transparent`
+    pub kind: String,
+    /// The signed wrapper tx, ready to be broadcasted
+    pub tx: Vec<u8>,
+}
+
+impl Corpus {
+    /// Write the corpus to `path`, prefixed by its format version
+    pub fn save(&self, path: &Path) -> std::io::Result<()> {
+        let mut bytes = MAGIC.to_vec();
+        CORPUS_VERSION.serialize(&mut bytes)?;
+        self.serialize(&mut bytes)?;
+        fs::write(path, bytes)
+    }
+
+    /// Read a corpus from `path`, rejecting any other format version
+    pub fn load(path: &Path) -> std::io::Result<Self> {
+        let bytes = fs::read(path)?;
+        let invalid = |msg: String| {
+            std::io::Error::new(std::io::ErrorKind::InvalidData, msg)
+        };
+        let mut rest = bytes
+            .strip_prefix(&MAGIC[..])
+            .ok_or_else(|| invalid("Not a tx corpus file".to_string()))?;
+        let version = u32::deserialize(&mut rest)?;
+        if version != CORPUS_VERSION {
+            return Err(invalid(format!(
+                "Unsupported corpus version {version}, expected \
+                 {CORPUS_VERSION}"
+            )));
+        }
+        Self::deserialize(&mut rest)
+    }
+}
+
+/// Signs the txs of the corpus
+struct Builder {
+    chain_id: ChainId,
+    epoch: Epoch,
+    native_token: Address,
+    faucet: Address,
+    faucet_key: SecretKey,
+    validator: Address,
+    entries: Vec<Entry>,
+}
+
+impl Builder {
+    /// Sign `data` as the inner tx running `code` and wrap it into a signed
+    /// fee-less wrapper tx. Unsigned inner txs, such as `tx_reveal_pk`, are
+    /// passed through as they are.
+    fn push(&mut self, kind: &str, code: Vec<u8>, data: Vec<u8>, sign: bool) {
+        let tx = Tx::new(code, Some(data), self.chain_id.clone(), None);
+        let tx = if sign { tx.sign(&self.faucet_key) } else { tx };
+        let tx = submit::wrap(
+            tx,
+            &self.faucet_key,
+            &self.chain_id,
+            self.epoch,
+            &self.native_token,
+        );
+        self.entries.push(Entry {
+            kind: kind.to_string(),
+            tx,
+        });
+    }
+}
+
+/// Build `count` txs of every kind we can build without any prior chain
+/// state beyond the genesis, all paid for and signed by the faucet
+pub async fn build(count: usize, wasm_dir: &Path) -> Corpus {
+    let client = HttpClient::new(ledger_address()).unwrap();
+    let mut chain = Chain::connect().await;
+    let status = client.status().await.expect("Unable to query the node");
+    let chain_id = status.node_info.network.to_string();
+    let epoch = rpc::query_epoch(&client).await;
+    let (faucet, faucet_key) = tx::faucet();
+    let mut builder = Builder {
+        chain_id: ChainId::from_str(&chain_id).expect("Invalid chain ID"),
+        epoch,
+        native_token: Address::from_str(NATIVE_TOKEN)
+            .expect("Unable to construct native token"),
+        faucet,
+        validator: tx::validator(),
+        faucet_key,
+        entries: vec![],
+    };
+
+    let tx_transfer = wasm::read(wasm_dir, "tx_transfer");
+    let tx_reveal_pk = wasm::read(wasm_dir, "tx_reveal_pk");
+    let tx_init_account = wasm::read(wasm_dir, "tx_init_account");
+    let tx_update_vp = wasm::read(wasm_dir, "tx_update_vp");
+    let tx_bond = wasm::read(wasm_dir, "tx_bond");
+    let tx_unbond = wasm::read(wasm_dir, "tx_unbond");
+    let tx_init_validator = wasm::read(wasm_dir, "tx_init_validator");
+    let tx_init_proposal = wasm::read(wasm_dir, "tx_init_proposal");
+    let tx_ibc = wasm::read(wasm_dir, "tx_ibc");
+    let vp_user = wasm::read(wasm_dir, "vp_user");
+    let vp_testnet_faucet = wasm::read(wasm_dir, "vp_testnet_faucet");
+    let vp_validator = wasm::read(wasm_dir, "vp_validator");
+
+    for _ in 0..count {
+        for kind in TransferKind::ALL {
+            // Let the shielded context build the MASP part, if any
+            let (mut shielded_ctx, args, client, _wallet) =
+                transfer_setup(kind, SubmitMode::Inclusion);
+            let masp_tx = shielded(&mut shielded_ctx, &client, args.clone())
+                .await
+                .expect("Unable to build the shielded transfer")
+                .map(|(tx, _metadata)| tx);
+            let target = match &args.target {
+                TransferTarget::Address(target) => target.clone(),
+                TransferTarget::PaymentAddress(_) => address::masp(),
+            };
+            let transfer = token::Transfer {
+                source: builder.faucet.clone(),
+                target,
+                token: builder.native_token.clone(),
+                sub_prefix: None,
+                amount: args.amount,
+                key: None,
+                shielded: masp_tx,
+            };
+            builder.push(
+                &format!("tx_transfer/{kind}"),
+                tx_transfer.clone(),
+                transfer.try_to_vec().unwrap(),
+                true,
+            );
+        }
+
+        // A fresh key for every tx that introduces one
+        let new_pk = SecretKey::Ed25519(ed25519::SigScheme::generate(
+            &mut rand::rngs::OsRng,
+        ))
+        .ref_to();
+        builder.push(
+            "tx_reveal_pk",
+            tx_reveal_pk.clone(),
+            new_pk.try_to_vec().unwrap(),
+            false,
+        );
+        let init_account = InitAccount {
+            public_key: new_pk,
+            vp_code: vp_user.clone(),
+        };
+        builder.push(
+            "tx_init_account",
+            tx_init_account.clone(),
+            init_account.try_to_vec().unwrap(),
+            true,
+        );
+        let update_vp = UpdateVp {
+            addr: builder.faucet.clone(),
+            vp_code: vp_testnet_faucet.clone(),
+        };
+        builder.push(
+            "tx_update_vp",
+            tx_update_vp.clone(),
+            update_vp.try_to_vec().unwrap(),
+            true,
+        );
+
+        let bond = pos::Bond {
+            validator: builder.validator.clone(),
+            amount: token::Amount::whole(10),
+            source: Some(builder.faucet.clone()),
+        };
+        builder.push(
+            "tx_bond",
+            tx_bond.clone(),
+            bond.try_to_vec().unwrap(),
+            true,
+        );
+        let unbond = pos::Unbond {
+            validator: builder.validator.clone(),
+            amount: token::Amount::whole(1),
+            source: Some(builder.faucet.clone()),
+        };
+        builder.push(
+            "tx_unbond",
+            tx_unbond.clone(),
+            unbond.try_to_vec().unwrap(),
+            true,
+        );
+
+        let (_key, mut init_validator) = chain.init_validator_data();
+        init_validator.validator_vp_code = vp_validator.clone();
+        builder.push(
+            "tx_init_validator",
+            tx_init_validator.clone(),
+            init_validator.try_to_vec().unwrap(),
+            true,
+        );
+        let (start, end, grace) = governance::proposal_epochs(&chain).await;
+        let proposal = InitProposalData {
+            id: None,
+            content: b"tx-bench corpus proposal".to_vec(),
+            author: builder.faucet.clone(),
+            r#type: ProposalType::Default(None),
+            voting_start_epoch: start,
+            voting_end_epoch: end,
+            grace_epoch: grace,
+        };
+        builder.push(
+            "tx_init_proposal",
+            tx_init_proposal.clone(),
+            proposal.try_to_vec().unwrap(),
+            true,
+        );
+        builder.push(
+            "tx_ibc/create_client",
+            tx_ibc.clone(),
+            ibc::create_client_data(&chain),
+            true,
+        );
+    }
+
+    Corpus {
+        chain_id,
+        epoch: epoch.0,
+        entries: builder.entries,
+    }
+}
+
+/// The signed data of a shielding transfer, which `vp_masp`'s acceptance
+/// test validates with its proofs. The wasm tests can't build the proofs
+/// themselves, so this is pre-built against a ledger and written to
+/// `wasm/wasm_source/testdata/vp_masp_shielding.bin`, which isn't checked
+/// in. The test is ignored unless it's run with `--ignored`.
+#[derive(Debug, BorshSerialize, BorshDeserialize)]
+pub struct MaspFixture {
+    /// The epoch the shielded part was built in, which the VP has to run in
+    pub epoch: u64,
+    /// The inner tx's signed data, as the VP gets it
+    pub tx_data: Vec<u8>,
+}
+
+/// Build a shielding transfer from the faucet for the ledger's chain
+pub async fn masp_fixture() -> MaspFixture {
+    let (mut shielded_ctx, args, client, _wallet) =
+        transfer_setup(TransferKind::Shielding, SubmitMode::Inclusion);
+    let epoch = rpc::query_epoch(&client).await;
+    let masp_tx = shielded(&mut shielded_ctx, &client, args.clone())
+        .await
+        .expect("Unable to build the shielded transfer")
+        .map(|(tx, _metadata)| tx);
+    let (faucet, faucet_key) = tx::faucet();
+    let transfer = token::Transfer {
+        source: faucet,
+        target: address::masp(),
+        token: args.token.clone(),
+        sub_prefix: None,
+        amount: args.amount,
+        key: None,
+        shielded: masp_tx,
+    };
+    let tx = Tx::new(
+        args.tx_code_path,
+        Some(transfer.try_to_vec().unwrap()),
+        submit::chain_id(&client).await,
+        None,
+    )
+    .sign(&faucet_key);
+    MaspFixture {
+        epoch: epoch.0,
+        tx_data: tx.data.expect("A signed tx has data"),
+    }
+}
+
+/// What happened to the replayed txs of one kind
+#[derive(Debug, Clone, Default)]
+pub struct KindReplay {
+    /// Time to commit of every tx that was applied
+    pub times: Vec<Duration>,
+    /// Gas used by every tx that was applied
+    pub gas_used: Vec<u64>,
+    /// Txs that were rejected or timed out
+    pub failures: usize,
+}
+
+/// The outcome of replaying a corpus, by tx kind
+#[derive(Debug, Clone, Default)]
+pub struct Replay(pub BTreeMap<String, KindReplay>);
+
+impl Replay {
+    /// Print the time to commit by tx kind
+    pub fn report(&self) {
+        for (kind, replay) in &self.0 {
+            let mut times = replay.times.clone();
+            times.sort();
+            let median = times
+                .get(times.len() / 2)
+                .map(|median| format!("{median:?}"))
+                .unwrap_or_else(|| "-".to_string());
+            println!(
+                "replay/{kind:<36} commit: median {median}, {} ok, {} failed",
+                times.len(),
+                replay.failures,
+            );
+        }
+    }
+}
+
+/// Submit every tx of the corpus to the node, waiting for each one to be
+/// committed
+pub async fn replay(corpus: &Corpus) -> Replay {
+    let client = HttpClient::new(ledger_address()).unwrap();
+    let status = client.status().await.expect("Unable to query the node");
+    if status.node_info.network.to_string() != corpus.chain_id {
+        eprintln!(
+            "Warning: the corpus was built for chain {} but the node is on \
+             {}",
+            corpus.chain_id, status.node_info.network
+        );
+    }
+
+    let node_metrics = metrics::Snapshot::take();
+    let mut replay = Replay::default();
+    for entry in &corpus.entries {
+        let committed =
+            submit::broadcast_and_wait(&client, entry.tx.clone()).await;
+        let kind = replay.0.entry(entry.kind.clone()).or_default();
+        match committed {
+            Some(committed) if committed.code == "0" => {
+                kind.times.push(committed.time_to_commit);
+                kind.gas_used.push(committed.gas_used);
+            }
+            _ => kind.failures += 1,
+        }
+    }
+
+    if let Some(node_metrics) = node_metrics {
+        node_metrics.report("replay");
+    }
+    replay
+}
+
+/// The gas to charge for every wasm in the corpus: the most that any
+/// applied tx running it used, rounded up to a multiple of 10 like the
+/// hand-written `gas.json`
+pub fn calibrate_gas(replay: &Replay) -> BTreeMap<String, u64> {
+    let mut gas: BTreeMap<String, u64> = BTreeMap::new();
+    for (kind, kind_replay) in &replay.0 {
+        // Kinds are named after their wasm, e.g. `tx_transfer/shielded`
+        let name = kind.split('/').next().unwrap_or(kind);
+        let used = kind_replay.gas_used.iter().copied().max();
+        if let Some(used) = used {
+            let suggested = gas.entry(format!("{name}.wasm")).or_default();
+            *suggested = (*suggested).max((used + 9) / 10 * 10);
+        }
+    }
+    gas
+}
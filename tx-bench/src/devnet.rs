@@ -0,0 +1,463 @@
+//! Bootstraps a local devnet for the benchmarks, replacing the manual steps
+//! in `notes.org`: `init-network` with the e2e genesis, copying the wasm
+//! artifacts and the wallet into place and starting the ledger. The nodes run
+//! as child processes on free loopback ports and are torn down when the
+//! `Devnet` is dropped. The ledger of an existing base dir can also be run
+//! in this process with [`InProcessLedger`].
+
+use std::fs::{self, File};
+use std::net::TcpListener;
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command, Stdio};
+use std::str::FromStr;
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use namada::types::address::Address;
+use tempfile::TempDir;
+use tendermint_config::net::Address as TendermintAddress;
+use tendermint_rpc::{Client, HttpClient};
+use tokio::runtime::Runtime;
+
+use crate::metrics::ENV_VAR_METRICS_ADDRESS;
+use crate::tx::{ENV_VAR_LEDGER_ADDRESS, ENV_VAR_WALLET};
+use crate::wasm;
+
+/// Directory holding the `namada` and `namadac` binaries. If unset, they
+/// are looked up in `PATH`.
+pub const ENV_VAR_NAMADA_BIN_DIR: &str = "NAMADA_BIN_DIR";
+/// Chain ID prefix of the generated chain
+const CHAIN_PREFIX: &str = "e2e-test";
+/// How long to wait for the node to commit its first block
+const STARTUP_TIMEOUT: Duration = Duration::from_secs(120);
+/// Ports every validator needs: P2P, RPC, ledger and Prometheus
+const PORTS_PER_VALIDATOR: u16 = 4;
+
+/// A running local devnet
+pub struct Devnet {
+    base_dir: TempDir,
+    chain_id: String,
+    /// The ledger processes, by validator index
+    ledgers: Vec<Child>,
+    /// Tendermint RPC address of validator-0, the node the benchmarks talk
+    /// to
+    pub rpc_address: TendermintAddress,
+    /// Prometheus endpoint of validator-0
+    pub metrics_address: String,
+}
+
+impl Devnet {
+    /// Initialize a network of `validators` validators from the `genesis`
+    /// file and the artifacts in `wasm_dir`, as listed in its
+    /// `checksums.json`, and start their nodes. The genesis file is expected
+    /// to define `validator-0`, which is cloned for any further validators.
+    pub fn start(genesis: &Path, wasm_dir: &Path, validators: u16) -> Self {
+        assert!(validators > 0, "A devnet needs at least one validator");
+        let base_dir = tempfile::Builder::new()
+            .prefix("tx-bench-devnet")
+            .tempdir()
+            .expect("Unable to create the devnet base dir");
+        let base = base_dir.path();
+
+        let first_port = free_ports(validators * PORTS_PER_VALIDATOR);
+        let first_ports: Vec<u16> = (0..validators)
+            .map(|index| first_port + index * PORTS_PER_VALIDATOR)
+            .collect();
+        let genesis_path = base.join("genesis.toml");
+        fs::copy(genesis, &genesis_path).expect("Unable to copy the genesis");
+        set_validators(&genesis_path, &first_ports);
+
+        run(namada_bin("namadac")
+            .arg("--base-dir")
+            .arg(base)
+            .args(["utils", "init-network", "--genesis-path"])
+            .arg(&genesis_path)
+            .arg("--wasm-checksums-path")
+            .arg(wasm_dir.join("checksums.json"))
+            .args([
+                "--chain-prefix",
+                CHAIN_PREFIX,
+                "--unsafe-dont-encrypt",
+                "--localhost",
+                "--allow-duplicate-ip",
+                "--dont-archive",
+            ]));
+        let chain_id = default_chain_id(base);
+        let chain_dir = base.join(&chain_id);
+
+        // The client and every validator need the wasm artifacts
+        copy_wasm(wasm_dir, &chain_dir.join("wasm"));
+        fs::copy(
+            chain_dir.join("setup").join("other").join("wallet.toml"),
+            chain_dir.join("wallet.toml"),
+        )
+        .expect("Unable to copy the wallet");
+
+        let mut ledgers = vec![];
+        for (index, first_port) in first_ports.iter().enumerate() {
+            let validator_dir = validator_base_dir(base, &chain_id, index);
+            copy_wasm(wasm_dir, &validator_dir.join(&chain_id).join("wasm"));
+            set_ports(
+                &validator_dir.join(&chain_id).join("config.toml"),
+                *first_port,
+            );
+            let log = base.join(format!("validator-{index}.log"));
+            ledgers.push(spawn_ledger(&validator_dir, &log));
+        }
+
+        let rpc_address = format!("127.0.0.1:{}", first_ports[0] + 1);
+        let devnet = Self {
+            metrics_address: format!("127.0.0.1:{}", first_ports[0] + 3),
+            rpc_address: TendermintAddress::from_str(&rpc_address)
+                .expect("Invalid RPC address"),
+            base_dir,
+            chain_id,
+            ledgers,
+        };
+        devnet.wait_until_healthy();
+        devnet
+    }
+
+    /// Point the benchmarks of this process at this devnet and its wallet,
+    /// which has the faucet and the validators of its genesis
+    pub fn export_env(&self) {
+        let rpc_address = self.rpc_address.to_string();
+        std::env::set_var(ENV_VAR_LEDGER_ADDRESS, rpc_address);
+        std::env::set_var(ENV_VAR_METRICS_ADDRESS, &self.metrics_address);
+        std::env::set_var(ENV_VAR_WALLET, self.wallet_path());
+    }
+
+    /// The wallet `init-network` generated for the clients of the devnet
+    pub fn wallet_path(&self) -> PathBuf {
+        self.base_dir.path().join(&self.chain_id).join("wallet.toml")
+    }
+
+    /// The address of the validator with the given index
+    pub fn validator_address(&self, index: usize) -> Address {
+        let wallet = fs::read_to_string(self.wallet_path())
+            .expect("Unable to read the wallet");
+        let wallet: toml::Value =
+            toml::from_str(&wallet).expect("Invalid wallet");
+        let address = wallet["addresses"][format!("validator-{index}")]
+            .as_str()
+            .unwrap_or_else(|| panic!("No address for validator-{index}"));
+        Address::from_str(address).expect("Invalid validator address")
+    }
+
+    /// Start a second node with the keys of the validator with the given
+    /// index, without its state. Once it has caught up, both nodes sign
+    /// the blocks the validator proposes, so the validator is soon slashed
+    /// for double signing. The node runs until the devnet is dropped.
+    pub fn equivocate(&mut self, index: usize) {
+        let base = self.base_dir.path();
+        let validator_dir = validator_base_dir(base, &self.chain_id, index);
+        let twin_dir = base.join(format!("validator-{index}-twin"));
+        let chain_dir = Path::new(&self.chain_id);
+        let tendermint_dir = chain_dir.join("tendermint");
+        copy_dir(
+            &validator_dir,
+            &twin_dir,
+            &[
+                chain_dir.join("db"),
+                tendermint_dir.join("data"),
+                // A node key of its own, so that the peers accept both
+                tendermint_dir.join("config").join("node_key.json"),
+            ],
+        );
+        set_ports(
+            &twin_dir.join(&self.chain_id).join("config.toml"),
+            free_ports(PORTS_PER_VALIDATOR),
+        );
+        let log = base.join(format!("validator-{index}-twin.log"));
+        self.ledgers.push(spawn_ledger(&twin_dir, &log));
+    }
+
+    /// Block until the network has committed its first block. With more
+    /// than one validator, this needs two thirds of them to be connected.
+    fn wait_until_healthy(&self) {
+        wait_for_first_block(&self.rpc_address, || {
+            let log = self.base_dir.path().join("validator-0.log");
+            fs::read_to_string(log).unwrap_or_default()
+        });
+        println!(
+            "Devnet {} with {} validator(s) is up at {}",
+            self.chain_id,
+            self.ledgers.len(),
+            self.rpc_address
+        );
+    }
+}
+
+impl Drop for Devnet {
+    fn drop(&mut self) {
+        for ledger in &mut self.ledgers {
+            let _ = ledger.kill();
+            let _ = ledger.wait();
+        }
+    }
+}
+
+/// The ledger of a node's base dir, run on a thread of this process instead
+/// of as a child process. It needs the `tendermint` binary in `PATH`, which
+/// it starts itself. The state is kept in the base dir, so a corpus built
+/// against it can be replayed against it later.
+pub struct InProcessLedger {
+    ledger: Option<JoinHandle<()>>,
+    /// Tendermint RPC address of the node
+    pub rpc_address: TendermintAddress,
+    /// Prometheus endpoint of the node, if its config turns it on
+    pub metrics_address: Option<String>,
+}
+
+impl InProcessLedger {
+    /// Run the ledger of the default chain of `base_dir` in validator mode,
+    /// e.g. the base dir of a validator that `init-network` set up
+    pub fn start(base_dir: &Path) -> Self {
+        let chain_id = default_chain_id(base_dir);
+        let chain_dir = base_dir.join(&chain_id);
+        let config = fs::read_to_string(chain_dir.join("config.toml"))
+            .expect("Unable to read the ledger config");
+        let config: namada_apps::config::Config =
+            toml::from_str(&config).expect("Invalid ledger config");
+        let tendermint = &config.ledger.tendermint;
+        let rpc_address =
+            TendermintAddress::from_str(&tendermint.rpc_address.to_string())
+                .expect("Invalid RPC address");
+        let metrics_address = tendermint.instrumentation_prometheus.then(|| {
+            tendermint.instrumentation_prometheus_listen_addr.to_string()
+        });
+
+        let wasm_dir = chain_dir.join(&config.wasm_dir);
+        let ledger = thread::spawn(move || {
+            namada_apps::node::ledger::run(config.ledger, wasm_dir)
+        });
+        let in_process = Self {
+            ledger: Some(ledger),
+            rpc_address,
+            metrics_address,
+        };
+        wait_for_first_block(&in_process.rpc_address, String::new);
+        println!(
+            "In-process ledger of {chain_id} is up at {}",
+            in_process.rpc_address
+        );
+        in_process
+    }
+
+    /// Point the benchmarks of this process at this ledger
+    pub fn export_env(&self) {
+        let rpc_address = self.rpc_address.to_string();
+        std::env::set_var(ENV_VAR_LEDGER_ADDRESS, rpc_address);
+        if let Some(metrics_address) = &self.metrics_address {
+            std::env::set_var(ENV_VAR_METRICS_ADDRESS, metrics_address);
+        }
+    }
+}
+
+impl Drop for InProcessLedger {
+    fn drop(&mut self) {
+        // The ledger shuts down itself and its Tendermint node on SIGTERM,
+        // which it handles, so this doesn't terminate the process
+        unsafe {
+            libc::raise(libc::SIGTERM);
+        }
+        if let Some(ledger) = self.ledger.take() {
+            let _ = ledger.join();
+        }
+    }
+}
+
+/// Block until the node at `rpc_address` has committed its first block,
+/// panicking with its `log` if it doesn't in time
+fn wait_for_first_block(
+    rpc_address: &TendermintAddress,
+    log: impl FnOnce() -> String,
+) {
+    let client = HttpClient::new(rpc_address.clone()).unwrap();
+    let started = Instant::now();
+    Runtime::new().unwrap().block_on(async {
+        while started.elapsed() < STARTUP_TIMEOUT {
+            if let Ok(status) = client.status().await {
+                if status.sync_info.latest_block_height.value() > 0 {
+                    return;
+                }
+            }
+            tokio::time::sleep(Duration::from_millis(500)).await;
+        }
+        panic!(
+            "The node did not become healthy within {:?}, see the log:\n{}",
+            STARTUP_TIMEOUT,
+            log()
+        );
+    });
+}
+
+/// Give the genesis one validator per entry of `first_ports`, each listening
+/// for P2P connections on its first port. Additional validators are copies
+/// of `validator-0`.
+fn set_validators(genesis: &Path, first_ports: &[u16]) {
+    edit_config(genesis, |genesis| {
+        let validators = genesis["validator"]
+            .as_table_mut()
+            .expect("The genesis must have validators");
+        let template = validators
+            .get("validator-0")
+            .cloned()
+            .expect("The genesis must have validator-0");
+        validators.clear();
+        for (index, first_port) in first_ports.iter().enumerate() {
+            let mut validator = template.clone();
+            validator["net_address"] =
+                format!("127.0.0.1:{first_port}").into();
+            validators.insert(format!("validator-{index}"), validator);
+        }
+    });
+}
+
+/// Point the ledger config at `path` to consecutive ports from `first_port`:
+/// P2P, RPC, ledger and Prometheus
+fn set_ports(path: &Path, first_port: u16) {
+    edit_config(path, |config| {
+        let port = |offset: u16| format!("127.0.0.1:{}", first_port + offset);
+        config["ledger"]["shell"]["ledger_address"] = port(2).into();
+        // `init-network` has already pointed the validators at each other
+        // with `p2p_persistent_peers`, using the P2P addresses from the
+        // genesis
+        let tendermint = &mut config["ledger"]["tendermint"];
+        tendermint["p2p_address"] = port(0).into();
+        tendermint["rpc_address"] = port(1).into();
+        tendermint["p2p_allow_duplicate_ip"] = true.into();
+        tendermint["instrumentation_prometheus"] = true.into();
+        tendermint["instrumentation_prometheus_listen_addr"] = port(3).into();
+    });
+}
+
+/// Base directory of the validator with the given index, as set up by
+/// `init-network`
+pub fn validator_base_dir(
+    base: &Path,
+    chain_id: &str,
+    index: usize,
+) -> PathBuf {
+    base.join(chain_id)
+        .join("setup")
+        .join(format!("validator-{index}"))
+        .join(".namada")
+}
+
+/// Read the chain ID that `init-network` set up as the default
+fn default_chain_id(base: &Path) -> String {
+    let config = fs::read_to_string(base.join("global-config.toml"))
+        .expect("Unable to read the global config");
+    let config: toml::Value =
+        toml::from_str(&config).expect("Invalid global config");
+    config["default_chain_id"]
+        .as_str()
+        .expect("Missing default chain ID")
+        .to_string()
+}
+
+/// Copy the directory `src` to `dest`, except for the paths relative to
+/// `src` in `skip`
+fn copy_dir(src: &Path, dest: &Path, skip: &[PathBuf]) {
+    fn copy(root: &Path, dir: &Path, dest: &Path, skip: &[PathBuf]) {
+        let entries = fs::read_dir(dir).unwrap_or_else(|err| {
+            panic!("Unable to read {}: {err}", dir.display())
+        });
+        for path in entries.filter_map(|entry| entry.ok().map(|e| e.path())) {
+            let relative = path.strip_prefix(root).unwrap();
+            if skip.iter().any(|skip| skip == relative) {
+                continue;
+            }
+            let target = dest.join(relative);
+            if path.is_dir() {
+                fs::create_dir_all(&target)
+                    .expect("Unable to create a directory");
+                copy(root, &path, dest, skip);
+            } else {
+                fs::copy(&path, &target).unwrap_or_else(|err| {
+                    panic!("Unable to copy {}: {err}", path.display())
+                });
+            }
+        }
+    }
+    fs::create_dir_all(dest).expect("Unable to create a directory");
+    copy(src, src, dest, skip);
+}
+
+/// Copy the artifacts listed in `checksums.json` from `wasm_dir` to `dest`
+fn copy_wasm(wasm_dir: &Path, dest: &Path) {
+    fs::create_dir_all(dest).expect("Unable to create the wasm dir");
+    for (name, file_name) in wasm::checksums(wasm_dir) {
+        let src = wasm_dir.join(&file_name);
+        fs::copy(&src, dest.join(&file_name)).unwrap_or_else(|err| {
+            panic!(
+                "Unable to copy {name} from {}, is checksums.json up to \
+                 date? {err}",
+                src.display()
+            )
+        });
+    }
+}
+
+/// Read, modify and write back a TOML config file
+pub fn edit_config(path: &Path, edit: impl FnOnce(&mut toml::Value)) {
+    let config = fs::read_to_string(path).unwrap_or_else(|err| {
+        panic!("Unable to read {}: {err}", path.display())
+    });
+    let mut config: toml::Value =
+        toml::from_str(&config).expect("Invalid config");
+    edit(&mut config);
+    fs::write(path, toml::to_string(&config).unwrap()).unwrap_or_else(
+        |err| panic!("Unable to write {}: {err}", path.display()),
+    );
+}
+
+/// Find `count` consecutive loopback ports that are free right now and
+/// return the first of them
+pub fn free_ports(count: u16) -> u16 {
+    loop {
+        let listener = TcpListener::bind("127.0.0.1:0")
+            .expect("Unable to bind to a free port");
+        let first = listener.local_addr().unwrap().port();
+        let rest_free = (1..count).all(|offset| {
+            first
+                .checked_add(offset)
+                .map(|port| TcpListener::bind(("127.0.0.1", port)).is_ok())
+                .unwrap_or(false)
+        });
+        if rest_free {
+            return first;
+        }
+    }
+}
+
+/// A command for one of the namada binaries
+pub fn namada_bin(name: &str) -> Command {
+    match std::env::var(ENV_VAR_NAMADA_BIN_DIR) {
+        Ok(dir) => Command::new(Path::new(&dir).join(name)),
+        Err(_) => Command::new(name),
+    }
+}
+
+/// Run a command to completion, panicking if it fails
+pub fn run(command: &mut Command) {
+    let status = command.status().unwrap_or_else(|err| {
+        panic!("Unable to run {command:?}: {err}")
+    });
+    assert!(status.success(), "{command:?} failed with {status}");
+}
+
+/// Start the ledger of a validator, logging into `log`
+pub fn spawn_ledger(validator_dir: &Path, log: &Path) -> Child {
+    let log = File::create(log).expect("Unable to create the ledger log");
+    namada_bin("namada")
+        .args(["--mode", "validator", "--base-dir"])
+        .arg(validator_dir)
+        .arg("ledger")
+        .stdout(log.try_clone().unwrap())
+        .stderr(log)
+        .stdin(Stdio::null())
+        .spawn()
+        .expect("Unable to start the ledger")
+}
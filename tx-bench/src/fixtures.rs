@@ -0,0 +1,178 @@
+//! Generates and funds test accounts for benchmark runs. The accounts baked
+//! into `wallet.toml` are far too few for realistic load, so this creates
+//! `N` implicit and `N` established accounts, reveals their public keys and
+//! funds them from the faucet. The resulting wallet is written to a new
+//! directory to be used by later benchmark runs.
+
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use namada::ledger::args;
+use namada::ledger::masp;
+use namada::ledger::tx;
+use namada::ledger::wallet::{SdkWalletUtils, Store, Wallet};
+use namada::types::address::Address;
+use namada::types::key::common::{PublicKey, SecretKey};
+use namada::types::key::{RefTo, SchemeType};
+use namada::types::masp::{TransferSource, TransferTarget};
+use namada::types::token;
+use tendermint_rpc::HttpClient;
+
+use crate::tx::{self, ledger_address, FuzzerShieldedUtils, NATIVE_TOKEN};
+use crate::wasm;
+
+/// Where the generated wallet is written to by default
+pub const DEFAULT_OUT_DIR: &str = "fixtures";
+/// How much of the native token every account is funded with
+const FUNDING: u64 = 1_000_000;
+
+/// Everything the account setup txs need, loaded once
+struct Fixtures {
+    client: HttpClient,
+    wallet: Wallet<SdkWalletUtils<PathBuf>>,
+    shielded: masp::ShieldedContext<FuzzerShieldedUtils>,
+    native_token: Address,
+    faucet: Address,
+    faucet_key: SecretKey,
+    tx_transfer: Vec<u8>,
+    tx_reveal_pk: Vec<u8>,
+    tx_init_account: Vec<u8>,
+    vp_user: Vec<u8>,
+}
+
+/// Create, reveal and fund `count` implicit and `count` established
+/// accounts and save their keys and addresses into `out_dir`
+pub async fn generate(count: usize, out_dir: &Path, wasm_dir: &Path) {
+    std::fs::create_dir_all(out_dir).expect("Unable to create out dir");
+    let (faucet, faucet_key) = tx::faucet();
+    let mut fixtures = Fixtures {
+        client: HttpClient::new(ledger_address()).unwrap(),
+        wallet: Wallet::new(out_dir.to_path_buf(), Store::default()),
+        shielded: FuzzerShieldedUtils::new(Path::new("./").to_path_buf()),
+        native_token: Address::from_str(NATIVE_TOKEN)
+            .expect("Unable to construct native token"),
+        faucet,
+        faucet_key,
+        tx_transfer: wasm::read(wasm_dir, "tx_transfer"),
+        tx_reveal_pk: wasm::read(wasm_dir, "tx_reveal_pk"),
+        tx_init_account: wasm::read(wasm_dir, "tx_init_account"),
+        vp_user: wasm::read(wasm_dir, "vp_user"),
+    };
+
+    for i in 0..count {
+        let alias = format!("implicit-{i}");
+        let (_, sk) = fixtures.wallet.gen_key(
+            SchemeType::Ed25519,
+            Some(alias.clone()),
+            None,
+        );
+        let pk = sk.ref_to();
+        let address: Address = (&pk).into();
+        fixtures.fund(&address).await;
+        fixtures.reveal_pk(pk).await;
+        println!("Created implicit account {alias}: {address}");
+    }
+
+    for i in 0..count {
+        let alias = format!("established-{i}");
+        let (_, sk) = fixtures.wallet.gen_key(
+            SchemeType::Ed25519,
+            Some(format!("{alias}-key")),
+            None,
+        );
+        let address = fixtures.init_account(&alias, sk.ref_to()).await;
+        fixtures.fund(&address).await;
+        println!("Created established account {alias}: {address}");
+    }
+
+    fixtures.wallet.save().expect("Unable to save the wallet");
+    println!("Wrote {} accounts to {}", 2 * count, out_dir.display());
+}
+
+impl Fixtures {
+    /// Tx args for a fee-less tx that's waited on until it's applied
+    fn tx_args(&self, signing_key: Option<SecretKey>) -> args::Tx {
+        args::Tx {
+            broadcast_only: false,
+            dry_run: false,
+            fee_amount: 0.into(),
+            fee_token: self.native_token.clone(),
+            force: false,
+            gas_limit: 0.into(),
+            initialized_account_alias: None,
+            ledger_address: (),
+            password: None,
+            signer: None,
+            signing_key,
+            tx_code_path: self.tx_reveal_pk.clone(),
+        }
+    }
+
+    /// Transfer `FUNDING` native tokens from the faucet to `target`
+    async fn fund(&mut self, target: &Address) {
+        let args = args::TxTransfer {
+            amount: token::Amount::whole(FUNDING),
+            native_token: self.native_token.clone(),
+            source: TransferSource::Address(self.faucet.clone()),
+            target: TransferTarget::Address(target.clone()),
+            token: self.native_token.clone(),
+            sub_prefix: None,
+            tx_code_path: self.tx_transfer.clone(),
+            tx: self.tx_args(Some(self.faucet_key.clone())),
+        };
+        tx::submit_transfer::<HttpClient, SdkWalletUtils<PathBuf>, _>(
+            &self.client,
+            &mut self.wallet,
+            &mut self.shielded,
+            args,
+        )
+        .await
+        .unwrap_or_else(|err| panic!("Unable to fund {target}: {err:?}"));
+    }
+
+    /// Reveal the public key of an implicit account
+    async fn reveal_pk(&mut self, public_key: PublicKey) {
+        let args = args::RevealPk {
+            tx: self.tx_args(None),
+            public_key: public_key.clone(),
+        };
+        tx::submit_reveal_pk::<HttpClient, SdkWalletUtils<PathBuf>>(
+            &self.client,
+            &mut self.wallet,
+            args,
+        )
+        .await
+        .unwrap_or_else(|err| {
+            panic!("Unable to reveal {public_key}: {err:?}")
+        });
+    }
+
+    /// Initialize an established account with `vp_user`, paid for by the
+    /// faucet. The SDK saves the new address into the wallet under `alias`.
+    async fn init_account(
+        &mut self,
+        alias: &str,
+        public_key: PublicKey,
+    ) -> Address {
+        let mut tx = self.tx_args(Some(self.faucet_key.clone()));
+        tx.initialized_account_alias = Some(alias.to_string());
+        let args = args::TxInitAccount {
+            tx,
+            source: self.faucet.clone(),
+            vp_code_path: self.vp_user.clone(),
+            tx_code_path: self.tx_init_account.clone(),
+            public_key,
+        };
+        tx::submit_init_account::<HttpClient, SdkWalletUtils<PathBuf>>(
+            &self.client,
+            &mut self.wallet,
+            args,
+        )
+        .await
+        .unwrap_or_else(|err| panic!("Unable to init {alias}: {err:?}"));
+        self.wallet
+            .find_address(alias)
+            .cloned()
+            .unwrap_or_else(|| panic!("Account {alias} was not initialized"))
+    }
+}
@@ -0,0 +1,303 @@
+//! The governance lifecycle: a proposal is created, voted on by delegators
+//! and validators and tallied at the end of its voting period. A vote can
+//! only be cast once a proposal's voting period has started and the tally
+//! only happens at its end, so unlike the other scenarios this isn't
+//! repeated by criterion. Every step is timed as it happens instead, once
+//! per voter count, and the samples go into the run's results.
+
+use std::collections::BTreeMap;
+use std::time::{Duration, Instant};
+
+use borsh::BorshSerialize;
+use criterion::Criterion;
+use namada::ledger::governance::storage as gov_storage;
+use namada::ledger::rpc;
+use namada::types::governance::{ProposalVote, VoteType};
+use namada::types::storage::Epoch;
+use namada::types::token;
+use namada::types::transaction::governance::{
+    InitProposalData, ProposalType, VoteProposalData,
+};
+use tendermint_rpc::{Client, HttpClient};
+use tokio::runtime::Runtime;
+
+use crate::chain::{Account, Chain};
+use crate::results;
+use crate::submit::{self, Committed};
+use crate::tx;
+
+/// Numbers of voters to run the lifecycle with
+const VOTER_COUNTS: [usize; 3] = [1, 10, 100];
+/// One in this many voters is a validator, the others delegate to the
+/// genesis validator
+const VOTERS_PER_VALIDATOR: usize = 10;
+/// How much every delegator bonds, and every new validator self-bonds
+const BOND: u64 = 100;
+/// How long to wait for a proposal to be tallied after its voting period
+const TALLY_TIMEOUT: Duration = Duration::from_secs(300);
+/// How many votes are expected to be committed per epoch, to make the
+/// voting period long enough for every voter
+const VOTES_PER_EPOCH: u64 = 25;
+
+/// The voters, which are added to as the voter count grows, as they can
+/// vote on every new proposal
+#[derive(Default)]
+struct Voters {
+    delegators: Vec<Account>,
+    validators: Vec<Account>,
+}
+
+impl Voters {
+    /// Make sure there are `count` voters
+    async fn grow(&mut self, chain: &mut Chain, count: usize) {
+        let validators = count / VOTERS_PER_VALIDATOR;
+        let bond = token::Amount::whole(BOND);
+        let genesis_validator = tx::validator();
+        while self.validators.len() < validators {
+            let validator = chain.init_validator().await;
+            chain
+                .fund(&validator.address, token::Amount::whole(2 * BOND))
+                .await;
+            chain.bond(&validator, &validator.address, bond).await;
+            self.validators.push(validator);
+        }
+        while self.delegators.len() < count - validators {
+            let delegator =
+                chain.new_account(token::Amount::whole(2 * BOND)).await;
+            chain.bond(&delegator, &genesis_validator, bond).await;
+            self.delegators.push(delegator);
+        }
+    }
+
+    /// The first `count` voters, validators first
+    fn take(&self, count: usize) -> impl Iterator<Item = &Account> {
+        let validators = count / VOTERS_PER_VALIDATOR;
+        self.validators[..validators]
+            .iter()
+            .chain(&self.delegators[..count - validators])
+    }
+}
+
+/// The timings of one lifecycle
+struct Lifecycle {
+    voters: usize,
+    propose: Option<Committed>,
+    votes: Vec<Option<Committed>>,
+    /// Time from the block before the tally to the block with the tally
+    tally_block: Option<Duration>,
+    /// Median interval between the blocks while the votes were cast
+    block_interval: Option<Duration>,
+}
+
+impl Lifecycle {
+    fn report(&self) {
+        let voters = self.voters;
+        let mut votes: Vec<Duration> = self
+            .votes
+            .iter()
+            .flatten()
+            .filter(|committed| committed.code == "0")
+            .map(|committed| committed.time_to_commit)
+            .collect();
+        votes.sort();
+        let failed = self.votes.len() - votes.len();
+        let vote_id = format!("governance/vote/{voters}");
+        results::record_wasm(&vote_id, "tx_vote_proposal");
+        results::record_samples(&vote_id, votes.iter().copied());
+        results::record_errors(&vote_id, failed);
+        let median = |times: &[Duration]| {
+            times
+                .get(times.len() / 2)
+                .map(|median| format!("{median:?}"))
+                .unwrap_or_else(|| "-".to_string())
+        };
+        println!(
+            "{vote_id:<40} commit: median {}, {} ok, {failed} failed",
+            median(&votes),
+            votes.len(),
+        );
+
+        if let Some(propose) = &self.propose {
+            let propose_id = format!("governance/propose/{voters}");
+            results::record_wasm(&propose_id, "tx_init_proposal");
+            results::record_samples(&propose_id, [propose.time_to_commit]);
+        }
+        let tally_id = format!("governance/tally/{voters}");
+        match self.tally_block {
+            Some(tally_block) => {
+                results::record_samples(&tally_id, [tally_block]);
+                println!(
+                    "{tally_id:<40} tally block: {tally_block:?}, usual block \
+                     interval {}",
+                    self.block_interval
+                        .map(|interval| format!("{interval:?}"))
+                        .unwrap_or_else(|| "-".to_string()),
+                );
+            }
+            None => {
+                results::record_errors(&tally_id, 1);
+                println!("{tally_id:<40} the proposal was never tallied");
+            }
+        }
+    }
+}
+
+/// The epochs of a proposal that can be voted on as soon as possible, once
+/// the validators bonded before now have their stake
+pub async fn proposal_epochs(chain: &Chain) -> (Epoch, Epoch, Epoch) {
+    voting_epochs(chain, 1).await
+}
+
+/// Like `proposal_epochs`, with a voting period long enough for `voters`
+/// votes, up to the maximum period
+async fn voting_epochs(chain: &Chain, voters: usize) -> (Epoch, Epoch, Epoch) {
+    let gov_params = rpc::get_governance_parameters(&chain.client).await;
+    let pos_params = chain.pos_params().await;
+    let min_period = gov_params.min_proposal_period;
+    let earliest = chain.epoch().await.0 + pos_params.pipeline_len + 1;
+    // The voting period has to start on a multiple of the minimum period
+    let start = (earliest + min_period - 1) / min_period * min_period;
+    // and last a multiple of it
+    let voting_epochs = (voters as u64 + VOTES_PER_EPOCH - 1) / VOTES_PER_EPOCH;
+    let periods = ((voting_epochs + min_period - 1) / min_period)
+        .min(gov_params.max_proposal_period / min_period)
+        .max(1);
+    let end = start + periods * min_period;
+    let grace = end + gov_params.min_proposal_grace_epochs;
+    (Epoch(start), Epoch(end), Epoch(grace))
+}
+
+/// Create a proposal, have `count` voters vote yay on it and wait for it to
+/// be tallied
+async fn lifecycle(
+    chain: &mut Chain,
+    voters: &mut Voters,
+    count: usize,
+) -> Lifecycle {
+    voters.grow(chain, count).await;
+
+    let id: u64 =
+        rpc::query_storage_value(&chain.client, &gov_storage::get_counter_key())
+            .await
+            .expect("Unable to query the proposal counter");
+    let (start, end, grace) = voting_epochs(chain, count).await;
+    let proposal = InitProposalData {
+        id: None,
+        content: format!("tx-bench proposal with {count} voters").into_bytes(),
+        author: chain.faucet.address.clone(),
+        r#type: ProposalType::Default(None),
+        voting_start_epoch: start,
+        voting_end_epoch: end,
+        grace_epoch: grace,
+    };
+    let faucet_key = chain.faucet.key.clone();
+    let propose = chain
+        .submit(
+            "tx_init_proposal",
+            proposal.try_to_vec().unwrap(),
+            Some(&faucet_key),
+        )
+        .await;
+
+    // The votes are ready before the voting period starts, and are all
+    // broadcasted before waiting for their commits, so that they make it
+    // into the period
+    let mut ballots = vec![];
+    for voter in voters.take(count) {
+        let delegations =
+            rpc::get_delegators_delegation(&chain.client, &voter.address)
+                .await
+                .into_iter()
+                .collect();
+        let vote = VoteProposalData {
+            id,
+            vote: ProposalVote::Yay(VoteType::Default),
+            voter: voter.address.clone(),
+            delegations,
+        };
+        ballots.push((vote.try_to_vec().unwrap(), voter.key.clone()));
+    }
+    chain.wait_for_epoch(start).await;
+    let first_vote_height = submit::latest_height(&chain.client).await;
+    let mut broadcasted = vec![];
+    for (vote, key) in ballots {
+        broadcasted
+            .push(chain.broadcast("tx_vote_proposal", vote, Some(&key)).await);
+    }
+    let accepted: Vec<(String, Instant)> =
+        broadcasted.iter().flatten().cloned().collect();
+    let mut commits =
+        submit::wait_for_commits(&chain.client, first_vote_height, &accepted)
+            .await
+            .into_iter();
+    // The votes the mempool rejected are never committed
+    let votes = broadcasted
+        .iter()
+        .map(|broadcasted| {
+            broadcasted.as_ref().and_then(|_| commits.next().flatten())
+        })
+        .collect();
+    let last_vote_height = submit::latest_height(&chain.client).await;
+
+    let tally = submit::wait_for_event(
+        &chain.client,
+        last_vote_height,
+        Instant::now() + TALLY_TIMEOUT,
+        |event_type, attrs| {
+            event_type == "proposal"
+                && attrs.get("proposal_id") == Some(&id.to_string())
+        },
+    )
+    .await;
+    let tally_block = match tally {
+        Some((height, _)) => Some(block_interval(&chain.client, height).await),
+        None => None,
+    };
+    let mut intervals = vec![];
+    for height in (first_vote_height + 1)..=last_vote_height {
+        intervals.push(block_interval(&chain.client, height).await);
+    }
+    intervals.sort();
+
+    Lifecycle {
+        voters: count,
+        propose,
+        votes,
+        tally_block,
+        block_interval: intervals.get(intervals.len() / 2).copied(),
+    }
+}
+
+/// Time between the block at `height` and the one before it
+async fn block_interval(client: &HttpClient, height: u64) -> Duration {
+    let time = |height: u64| async move {
+        client
+            .block(height.try_into().unwrap())
+            .await
+            .expect("Unable to query the block")
+            .block
+            .header
+            .time
+    };
+    time(height)
+        .await
+        .duration_since(time(height - 1).await)
+        .unwrap_or_default()
+}
+
+/// Run the governance lifecycle with a growing number of voters
+pub fn lifecycles(_c: &mut Criterion) {
+    let runtime = Runtime::new().unwrap();
+    runtime.block_on(async {
+        let mut chain = Chain::connect().await;
+        let mut voters = Voters::default();
+        let mut lifecycles = BTreeMap::new();
+        for count in VOTER_COUNTS {
+            lifecycles
+                .insert(count, lifecycle(&mut chain, &mut voters, count).await);
+        }
+        for lifecycle in lifecycles.values() {
+            lifecycle.report();
+        }
+    });
+}
@@ -0,0 +1,483 @@
+//! Benchmarks for the IBC messages handled by `tx_ibc`. The counterparty is
+//! a mock chain living in this process: it hands out mock client states,
+//! headers and proofs, which the ledger's mock light client accepts without
+//! any relayer or second network. This needs a ledger built with the
+//! `ibc-mocks` feature, as the e2e test builds are.
+//!
+//! Every message is measured on its own, from building it until its commit.
+//! The handshake steps it depends on are submitted untimed before it, so
+//! that each iteration runs against a fresh client, connection or channel.
+
+use std::fmt;
+use std::time::{Duration, Instant};
+
+use criterion::{BenchmarkId, Criterion};
+use namada::ibc::applications::ics20_fungible_token_transfer::msgs::transfer::MsgTransfer;
+use namada::ibc::core::ics02_client::client_type::ClientType;
+use namada::ibc::core::ics02_client::header::AnyHeader;
+use namada::ibc::core::ics02_client::height::Height;
+use namada::ibc::core::ics02_client::msgs::{
+    create_client::MsgCreateAnyClient, update_client::MsgUpdateAnyClient,
+};
+use namada::ibc::core::ics03_connection::connection::Counterparty as ConnCounterparty;
+use namada::ibc::core::ics03_connection::msgs::{
+    conn_open_ack::MsgConnectionOpenAck,
+    conn_open_confirm::MsgConnectionOpenConfirm,
+    conn_open_init::MsgConnectionOpenInit, conn_open_try::MsgConnectionOpenTry,
+};
+use namada::ibc::core::ics03_connection::version::Version as ConnVersion;
+use namada::ibc::core::ics04_channel::channel::{
+    ChannelEnd, Counterparty as ChanCounterparty, Order, State as ChanState,
+};
+use namada::ibc::core::ics04_channel::msgs::{
+    chan_open_ack::MsgChannelOpenAck, chan_open_confirm::MsgChannelOpenConfirm,
+    chan_open_init::MsgChannelOpenInit, chan_open_try::MsgChannelOpenTry,
+    recv_packet::MsgRecvPacket,
+};
+use namada::ibc::core::ics04_channel::packet::{Packet, Sequence};
+use namada::ibc::core::ics04_channel::Version as ChanVersion;
+use namada::ibc::core::ics23_commitment::commitment::{
+    CommitmentPrefix, CommitmentProofBytes,
+};
+use namada::ibc::core::ics24_host::identifier::{
+    ChannelId, ClientId, ConnectionId, PortId,
+};
+use namada::ibc::mock::{
+    client_state::MockClientState, consensus_state::MockConsensusState,
+    header::MockHeader,
+};
+use namada::ibc::proofs::Proofs;
+use namada::ibc::signer::Signer;
+use namada::ibc::timestamp::Timestamp;
+use namada::ibc::tx_msg::Msg;
+use namada::ibc_proto::cosmos::base::v1beta1::Coin;
+use namada::ibc_proto::google::protobuf::Any;
+use namada::ledger::ibc::storage as ibc_storage;
+use namada::ledger::rpc;
+use namada::types::storage;
+use prost::Message as _;
+use tendermint_rpc::HttpClient;
+use tokio::runtime::Runtime;
+
+use crate::chain::Chain;
+use crate::submit::{CommitSamples, Committed};
+
+/// Commitment prefix of both chains' IBC stores
+const COMMITMENT_PREFIX: &[u8] = b"ibc";
+/// Any proof will do for the mock light client, as long as it's not empty
+const MOCK_PROOF: &[u8] = b"mock-proof";
+/// How much of the native token `transfer` sends
+const TRANSFER_AMOUNT: u64 = 100;
+/// The denomination of the tokens received from the counterparty
+const COUNTERPARTY_DENOM: &str = "umock";
+
+/// The IBC messages we benchmark
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Message {
+    CreateClient,
+    UpdateClient,
+    ConnOpenInit,
+    ConnOpenTry,
+    ConnOpenAck,
+    ConnOpenConfirm,
+    ChanOpenInit,
+    ChanOpenTry,
+    ChanOpenAck,
+    ChanOpenConfirm,
+    /// An ICS-20 transfer of the native token to the counterparty
+    Transfer,
+    /// An ICS-20 transfer from the counterparty
+    RecvPacket,
+}
+
+impl Message {
+    pub const ALL: [Self; 12] = [
+        Self::CreateClient,
+        Self::UpdateClient,
+        Self::ConnOpenInit,
+        Self::ConnOpenTry,
+        Self::ConnOpenAck,
+        Self::ConnOpenConfirm,
+        Self::ChanOpenInit,
+        Self::ChanOpenTry,
+        Self::ChanOpenAck,
+        Self::ChanOpenConfirm,
+        Self::Transfer,
+        Self::RecvPacket,
+    ];
+
+    /// The messages that have to be applied before this one, in order
+    fn prerequisites(&self) -> &'static [Self] {
+        use Message::*;
+        match self {
+            CreateClient => &[],
+            UpdateClient | ConnOpenInit | ConnOpenTry => &[CreateClient],
+            ConnOpenAck => &[CreateClient, ConnOpenInit],
+            ConnOpenConfirm => &[CreateClient, ConnOpenTry],
+            ChanOpenInit | ChanOpenTry => {
+                &[CreateClient, ConnOpenInit, ConnOpenAck]
+            }
+            ChanOpenAck => {
+                &[CreateClient, ConnOpenInit, ConnOpenAck, ChanOpenInit]
+            }
+            ChanOpenConfirm => {
+                &[CreateClient, ConnOpenInit, ConnOpenAck, ChanOpenTry]
+            }
+            Transfer | RecvPacket => &[
+                CreateClient,
+                ConnOpenInit,
+                ConnOpenAck,
+                ChanOpenInit,
+                ChanOpenAck,
+            ],
+        }
+    }
+}
+
+impl fmt::Display for Message {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Self::CreateClient => "create_client",
+            Self::UpdateClient => "update_client",
+            Self::ConnOpenInit => "conn_open_init",
+            Self::ConnOpenTry => "conn_open_try",
+            Self::ConnOpenAck => "conn_open_ack",
+            Self::ConnOpenConfirm => "conn_open_confirm",
+            Self::ChanOpenInit => "chan_open_init",
+            Self::ChanOpenTry => "chan_open_try",
+            Self::ChanOpenAck => "chan_open_ack",
+            Self::ChanOpenConfirm => "chan_open_confirm",
+            Self::Transfer => "transfer",
+            Self::RecvPacket => "recv_packet",
+        };
+        write!(f, "{name}")
+    }
+}
+
+/// The counterparty chain, which only ever moves forward in height
+#[derive(Debug, Default)]
+struct MockChain {
+    height: u64,
+}
+
+impl MockChain {
+    /// Produce the header of the next block
+    fn next_header(&mut self) -> MockHeader {
+        self.height += 1;
+        MockHeader {
+            height: Height::new(0, self.height),
+            timestamp: Timestamp::now(),
+        }
+    }
+
+    /// Proofs of the counterparty's state at its latest height, which is
+    /// the height of the last header given to our client
+    fn proofs(&self) -> Proofs {
+        let proof = CommitmentProofBytes::try_from(MOCK_PROOF.to_vec())
+            .expect("Invalid mock proof");
+        Proofs::new(proof, None, None, None, Height::new(0, self.height))
+            .expect("Invalid mock proofs")
+    }
+}
+
+/// The identifiers created on our side by the handshakes so far
+#[derive(Debug, Default)]
+struct Path {
+    client_id: Option<ClientId>,
+    connection_id: Option<ConnectionId>,
+    channel_id: Option<ChannelId>,
+    packet_sequence: u64,
+}
+
+impl Path {
+    fn client_id(&self) -> ClientId {
+        self.client_id.clone().expect("The client must be created first")
+    }
+
+    fn connection_id(&self) -> ConnectionId {
+        self.connection_id
+            .clone()
+            .expect("The connection must be opened first")
+    }
+
+    fn channel_id(&self) -> ChannelId {
+        self.channel_id
+            .clone()
+            .expect("The channel must be opened first")
+    }
+}
+
+/// The message creating a client of the counterparty from its `header`
+fn create_client(header: MockHeader, signer: Signer) -> Any {
+    MsgCreateAnyClient {
+        client_state: MockClientState::new(header).wrap_any(),
+        consensus_state: MockConsensusState::new(header).wrap_any(),
+        signer,
+    }
+    .to_any()
+}
+
+/// The encoded `tx_ibc` data creating a client of a fresh mock
+/// counterparty, signed for by the faucet
+pub fn create_client_data(chain: &Chain) -> Vec<u8> {
+    let header = MockChain::default().next_header();
+    let signer = Signer::new(chain.faucet.address.to_string());
+    let mut data = vec![];
+    create_client(header, signer)
+        .encode(&mut data)
+        .expect("Unable to encode the message");
+    data
+}
+
+/// Submits IBC messages for the faucet
+struct Relayer {
+    chain: Chain,
+    counterparty: MockChain,
+}
+
+impl Relayer {
+    fn signer(&self) -> Signer {
+        Signer::new(self.chain.faucet.address.to_string())
+    }
+
+    /// The value of one of the IBC identifier counters, which is the
+    /// number of the next identifier
+    async fn counter(&self, key: storage::Key) -> u64 {
+        rpc::query_storage_value::<HttpClient, u64>(&self.chain.client, &key)
+            .await
+            .unwrap_or_default()
+    }
+
+    /// Build the message, wrap it into a signed `tx_ibc` tx and submit it,
+    /// recording the identifier it creates in the `path`
+    async fn submit(
+        &mut self,
+        message: Message,
+        path: &mut Path,
+    ) -> Option<Committed> {
+        let any = self.build(message, path).await;
+        let mut data = vec![];
+        any.encode(&mut data).expect("Unable to encode the message");
+        let faucet_key = self.chain.faucet.key.clone();
+        self.chain.submit("tx_ibc", data, Some(&faucet_key)).await
+    }
+
+    /// Build the message for the current state of the `path`
+    async fn build(&mut self, message: Message, path: &mut Path) -> Any {
+        let prefix = CommitmentPrefix::try_from(COMMITMENT_PREFIX.to_vec())
+            .expect("Invalid commitment prefix");
+        // The counterparty's side of the path mirrors ours
+        let counterparty_client = ClientId::new(ClientType::Mock, 0).unwrap();
+        let counterparty_connection = ConnectionId::new(0);
+        let counterparty_channel = ChannelId::new(0);
+        let port_id = PortId::transfer();
+        let signer = self.signer();
+        match message {
+            Message::CreateClient => {
+                let counter =
+                    self.counter(ibc_storage::client_counter_key()).await;
+                path.client_id =
+                    Some(ClientId::new(ClientType::Mock, counter).unwrap());
+                create_client(self.counterparty.next_header(), signer)
+            }
+            Message::UpdateClient => MsgUpdateAnyClient {
+                client_id: path.client_id(),
+                header: AnyHeader::Mock(self.counterparty.next_header()),
+                signer,
+            }
+            .to_any(),
+            Message::ConnOpenInit | Message::ConnOpenTry => {
+                let counter =
+                    self.counter(ibc_storage::connection_counter_key()).await;
+                path.connection_id = Some(ConnectionId::new(counter));
+                if message == Message::ConnOpenInit {
+                    MsgConnectionOpenInit {
+                        client_id: path.client_id(),
+                        counterparty: ConnCounterparty::new(
+                            counterparty_client,
+                            None,
+                            prefix,
+                        ),
+                        version: Some(ConnVersion::default()),
+                        delay_period: Duration::ZERO,
+                        signer,
+                    }
+                    .to_any()
+                } else {
+                    MsgConnectionOpenTry {
+                        previous_connection_id: None,
+                        client_id: path.client_id(),
+                        client_state: None,
+                        counterparty: ConnCounterparty::new(
+                            counterparty_client,
+                            Some(counterparty_connection),
+                            prefix,
+                        ),
+                        counterparty_versions: vec![ConnVersion::default()],
+                        proofs: self.counterparty.proofs(),
+                        delay_period: Duration::ZERO,
+                        signer,
+                    }
+                    .to_any()
+                }
+            }
+            Message::ConnOpenAck => MsgConnectionOpenAck {
+                connection_id: path.connection_id(),
+                counterparty_connection_id: counterparty_connection,
+                client_state: None,
+                proofs: self.counterparty.proofs(),
+                version: ConnVersion::default(),
+                signer,
+            }
+            .to_any(),
+            Message::ConnOpenConfirm => MsgConnectionOpenConfirm {
+                connection_id: path.connection_id(),
+                proofs: self.counterparty.proofs(),
+                signer,
+            }
+            .to_any(),
+            Message::ChanOpenInit | Message::ChanOpenTry => {
+                let counter =
+                    self.counter(ibc_storage::channel_counter_key()).await;
+                path.channel_id = Some(ChannelId::new(counter));
+                let (state, counterparty_channel) =
+                    if message == Message::ChanOpenInit {
+                        (ChanState::Init, None)
+                    } else {
+                        (ChanState::TryOpen, Some(counterparty_channel))
+                    };
+                let channel = ChannelEnd::new(
+                    state,
+                    Order::Unordered,
+                    ChanCounterparty::new(
+                        port_id.clone(),
+                        counterparty_channel,
+                    ),
+                    vec![path.connection_id()],
+                    ChanVersion::ics20(),
+                );
+                if message == Message::ChanOpenInit {
+                    MsgChannelOpenInit {
+                        port_id,
+                        channel,
+                        signer,
+                    }
+                    .to_any()
+                } else {
+                    MsgChannelOpenTry {
+                        port_id,
+                        previous_channel_id: None,
+                        channel,
+                        counterparty_version: ChanVersion::ics20(),
+                        proofs: self.counterparty.proofs(),
+                        signer,
+                    }
+                    .to_any()
+                }
+            }
+            Message::ChanOpenAck => MsgChannelOpenAck {
+                port_id,
+                channel_id: path.channel_id(),
+                counterparty_channel_id: counterparty_channel,
+                counterparty_version: ChanVersion::ics20(),
+                proofs: self.counterparty.proofs(),
+                signer,
+            }
+            .to_any(),
+            Message::ChanOpenConfirm => MsgChannelOpenConfirm {
+                port_id,
+                channel_id: path.channel_id(),
+                proofs: self.counterparty.proofs(),
+                signer,
+            }
+            .to_any(),
+            Message::Transfer => MsgTransfer {
+                source_port: port_id,
+                source_channel: path.channel_id(),
+                token: Some(Coin {
+                    denom: self.chain.native_token.to_string(),
+                    amount: TRANSFER_AMOUNT.to_string(),
+                }),
+                sender: signer,
+                receiver: Signer::new("mock-receiver"),
+                timeout_height: Height::zero(),
+                timeout_timestamp: Timestamp::none(),
+            }
+            .to_any(),
+            Message::RecvPacket => {
+                path.packet_sequence += 1;
+                let data = serde_json::json!({
+                    "denom": COUNTERPARTY_DENOM,
+                    "amount": TRANSFER_AMOUNT.to_string(),
+                    "sender": "mock-sender",
+                    "receiver": self.chain.faucet.address.to_string(),
+                });
+                let packet = Packet {
+                    sequence: Sequence::from(path.packet_sequence),
+                    source_port: port_id.clone(),
+                    source_channel: counterparty_channel,
+                    destination_port: port_id,
+                    destination_channel: path.channel_id(),
+                    data: data.to_string().into_bytes(),
+                    timeout_height: Height::zero(),
+                    timeout_timestamp: Timestamp::none(),
+                };
+                MsgRecvPacket {
+                    packet,
+                    proofs: self.counterparty.proofs(),
+                    signer,
+                }
+                .to_any()
+            }
+        }
+    }
+
+    /// Apply the prerequisites of the message to a fresh path
+    async fn prepare(&mut self, message: Message) -> Path {
+        let mut path = Path::default();
+        for prerequisite in message.prerequisites() {
+            let committed = self.submit(*prerequisite, &mut path).await;
+            assert!(
+                matches!(&committed, Some(committed) if committed.code == "0"),
+                "Unable to apply {prerequisite} before {message}: \
+                 {committed:?}"
+            );
+        }
+        path
+    }
+}
+
+/// Measure the time to commit of every IBC message kind
+pub fn messages(c: &mut Criterion) {
+    let mut group = c.benchmark_group("ibc");
+    group.sample_size(10);
+    let runtime = Runtime::new().unwrap();
+    let mut relayer = Relayer {
+        chain: runtime.block_on(Chain::connect()),
+        counterparty: MockChain::default(),
+    };
+
+    for message in Message::ALL {
+        let commits = CommitSamples::default();
+        let samples = commits.clone();
+        group.bench_function(BenchmarkId::new("tx_ibc", message), |b| {
+            b.iter_custom(|iters| {
+                runtime.block_on(async {
+                    let mut total = Duration::ZERO;
+                    for _ in 0..iters {
+                        let mut path = relayer.prepare(message).await;
+                        let start = Instant::now();
+                        let committed =
+                            relayer.submit(message, &mut path).await;
+                        total += start.elapsed();
+                        samples.push(committed);
+                    }
+                    total
+                })
+            })
+        });
+        commits.report(&format!("ibc/tx_ibc/{message}"));
+    }
+
+    group.finish();
+}
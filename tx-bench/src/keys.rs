@@ -0,0 +1,219 @@
+//! Getting signing keys out of the wallet. Production wallets keep their
+//! keys encrypted, so every signed tx pays for decrypting the key first
+//! unless it's kept around in memory. The txs of the scenarios are signed
+//! with keys from a [`Keyring`] stored as `TX_BENCH_KEY_STORAGE` says, and
+//! cached if `TX_BENCH_CACHE_KEYS` is set. The `signing` scenario measures
+//! the decryption on its own.
+
+use std::collections::HashMap;
+use std::env;
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+use criterion::{BenchmarkId, Criterion};
+use namada::ledger::wallet::{SdkWalletUtils, Store, StoredKeypair, Wallet};
+use namada::proto::Tx;
+use namada::types::chain::ChainId;
+use namada::types::key::common::SecretKey;
+use namada::types::key::{PublicKeyHash, RefTo};
+
+use crate::alloc;
+use crate::tx;
+
+/// Environment variable with how the signing keys are stored, either
+/// `unencrypted` (the default) or `encrypted`
+pub const ENV_VAR_KEY_STORAGE: &str = "TX_BENCH_KEY_STORAGE";
+/// Environment variable that, if set, keeps the decrypted signing keys in
+/// memory after their first use
+pub const ENV_VAR_CACHE_KEYS: &str = "TX_BENCH_CACHE_KEYS";
+/// Alias of the faucet key in the wallet
+pub const KEY_ALIAS: &str = "faucet-key";
+/// Password the keys are encrypted with
+const KEY_PASSWORD: &str = "tx-bench";
+/// Number of decryptions whose heap usage is measured, apart from the timed
+/// iterations
+const HEAP_ITERATIONS: usize = 100;
+
+/// How the signing keys are stored
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum KeyStorage {
+    /// In the clear, as in the checked-in `wallet.toml`
+    #[default]
+    Unencrypted,
+    /// Password protected and decrypted on every use, unless cached
+    Encrypted,
+}
+
+impl KeyStorage {
+    pub const ALL: [Self; 2] = [Self::Unencrypted, Self::Encrypted];
+
+    /// The key storage set in `TX_BENCH_KEY_STORAGE`
+    pub fn configured() -> Self {
+        match env::var(ENV_VAR_KEY_STORAGE).as_deref() {
+            Ok("encrypted") => Self::Encrypted,
+            Ok("unencrypted") | Err(_) => Self::Unencrypted,
+            Ok(other) => panic!("Invalid {ENV_VAR_KEY_STORAGE}: {other}"),
+        }
+    }
+
+    fn password(&self) -> Option<String> {
+        match self {
+            Self::Unencrypted => None,
+            Self::Encrypted => Some(KEY_PASSWORD.to_string()),
+        }
+    }
+}
+
+impl fmt::Display for KeyStorage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Unencrypted => write!(f, "unencrypted"),
+            Self::Encrypted => write!(f, "encrypted"),
+        }
+    }
+}
+
+/// Whether `TX_BENCH_CACHE_KEYS` is set
+pub fn cache_configured() -> bool {
+    env::var_os(ENV_VAR_CACHE_KEYS).is_some()
+}
+
+/// A wallet holding keys stored as its `KeyStorage` says, which hands out
+/// the decrypted keys
+pub struct Keyring {
+    wallet: Wallet<SdkWalletUtils<PathBuf>>,
+    storage: KeyStorage,
+    /// Decrypted keys by alias, when caching is enabled
+    cache: Option<HashMap<String, SecretKey>>,
+}
+
+impl Keyring {
+    /// An empty keyring, which keeps the decrypted keys in memory if
+    /// `cache` is set
+    pub fn new(storage: KeyStorage, cache: bool) -> Self {
+        let wallet: Wallet<SdkWalletUtils<PathBuf>> = Wallet::new(
+            Path::new("wallet.toml").to_path_buf(),
+            Store::default(),
+        );
+        Self {
+            wallet,
+            storage,
+            cache: cache.then(HashMap::new),
+        }
+    }
+
+    /// An empty keyring as configured in the environment
+    pub fn configured() -> Self {
+        Self::new(KeyStorage::configured(), cache_configured())
+    }
+
+    /// How the keys are stored, e.g. for benchmark IDs
+    pub fn label(&self) -> String {
+        match (&self.storage, &self.cache) {
+            (KeyStorage::Encrypted, Some(_)) => "encrypted_cached".to_string(),
+            (storage, _) => storage.to_string(),
+        }
+    }
+
+    /// Store `key` under `alias`
+    pub fn insert(&mut self, alias: &str, key: SecretKey) {
+        let pkh = PublicKeyHash::from(&key.ref_to());
+        let (stored, _) = StoredKeypair::new(key, self.storage.password());
+        self.wallet
+            .insert_keypair(alias.to_string(), stored, pkh)
+            .expect("Unable to insert the key");
+    }
+
+    /// Store `key` under the hash of its public key, unless it's already
+    /// there. Registering keys up front keeps their encryption out of the
+    /// timings of the txs they sign.
+    pub fn register(&mut self, key: &SecretKey) -> String {
+        let alias = PublicKeyHash::from(&key.ref_to()).to_string();
+        if self.wallet.store().find_key(&alias).is_none() {
+            self.insert(&alias, key.clone());
+        }
+        alias
+    }
+
+    /// Get the decrypted key with the given alias
+    pub fn key(&mut self, alias: &str) -> SecretKey {
+        if let Some(key) =
+            self.cache.as_ref().and_then(|cache| cache.get(alias))
+        {
+            return key.clone();
+        }
+        let key = self
+            .wallet
+            .store()
+            .find_key(alias)
+            .expect("Key must be in the wallet")
+            .get(true, self.storage.password())
+            .expect("Unable to decrypt the key");
+        if let Some(cache) = self.cache.as_mut() {
+            cache.insert(alias.to_string(), key.clone());
+        }
+        key
+    }
+
+    /// Get `key` out of the keyring the way a wallet user would, paying for
+    /// its decryption. The key is registered first if it's new.
+    pub fn decrypt(&mut self, key: &SecretKey) -> SecretKey {
+        let alias = self.register(key);
+        self.key(&alias)
+    }
+}
+
+/// Measure decrypting the signing key on its own, and together with signing
+/// a tx with it
+pub fn signing(c: &mut Criterion) {
+    let mut group = c.benchmark_group("signing");
+
+    for storage in KeyStorage::ALL {
+        for cache in [false, true] {
+            // Caching only makes a difference to encrypted keys
+            if cache && storage == KeyStorage::Unencrypted {
+                continue;
+            }
+            let heap = alloc::Samples::default();
+            // A cached keyring must outlive the iterations to be of any use
+            let mut keyring = Keyring::new(storage, cache);
+            keyring.insert(KEY_ALIAS, tx::faucet().1);
+            let label = keyring.label();
+            group.bench_with_input(
+                BenchmarkId::new("decrypt", &label),
+                &storage,
+                |b, _| b.iter(|| keyring.key(KEY_ALIAS)),
+            );
+            // The decryption is too quick for the heap to be measured in
+            // the timed iterations, so it gets an untimed pass of its own
+            for _ in 0..HEAP_ITERATIONS {
+                let region = alloc::Region::start();
+                let _key = keyring.key(KEY_ALIAS);
+                heap.push(region.finish());
+            }
+            heap.report(&format!("signing/decrypt/{label}"));
+
+            let tx_data = vec![0; 1024];
+            group.bench_with_input(
+                BenchmarkId::new("decrypt_and_sign", &label),
+                &storage,
+                |b, _| {
+                    b.iter_batched(
+                        || {
+                            Tx::new(
+                                vec![],
+                                Some(tx_data.clone()),
+                                ChainId::default(),
+                                None,
+                            )
+                        },
+                        |tx| tx.sign(&keyring.key(KEY_ALIAS)),
+                        criterion::BatchSize::SmallInput,
+                    )
+                },
+            );
+        }
+    }
+
+    group.finish();
+}
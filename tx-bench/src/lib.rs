@@ -0,0 +1,123 @@
+//! Benchmarks of Namada transactions against a running ledger. The
+//! scenarios here are driven by the `tx-bench` binary and by the criterion
+//! bench harness alike.
+
+use criterion::Criterion;
+
+pub mod alloc;
+pub mod chain;
+pub mod compare;
+pub mod corpus;
+pub mod devnet;
+pub mod fixtures;
+pub mod governance;
+pub mod ibc;
+pub mod keys;
+pub mod metrics;
+pub mod pos;
+pub mod results;
+pub mod submit;
+pub mod sweep;
+pub mod tx;
+pub mod validator_set;
+pub mod wasm;
+
+/// A named group of benchmarks that can be run on its own
+pub struct Scenario {
+    pub name: &'static str,
+    pub description: &'static str,
+    /// The wasm the scenario's txs run, if they all run the same one. The
+    /// others record the wasm of each benchmark with `results::record_wasm`.
+    pub wasm: Option<&'static str>,
+    pub run: fn(&mut Criterion),
+}
+
+/// Every scenario, in the order they are run
+pub const SCENARIOS: &[Scenario] = &[
+    Scenario {
+        name: "transfer",
+        description: "Shielding transfer from the faucet, in every submit \
+                      mode and with every key storage",
+        wasm: Some("tx_transfer"),
+        run: tx::transfer,
+    },
+    Scenario {
+        name: "shielded_context",
+        description: "Saving the shielded context to disk",
+        wasm: None,
+        run: tx::shielded_context,
+    },
+    Scenario {
+        name: "fee_sweep",
+        description: "Transparent transfers across fee tokens, fee amounts \
+                      and gas limits",
+        wasm: None,
+        run: sweep::fee_sweep,
+    },
+    Scenario {
+        name: "ibc",
+        description: "Every IBC message kind through tx_ibc, against a mock \
+                      counterparty",
+        wasm: Some("tx_ibc"),
+        run: ibc::messages,
+    },
+    Scenario {
+        name: "governance",
+        description: "Proposing, voting on and tallying a proposal with a \
+                      growing number of voters",
+        wasm: None,
+        run: governance::lifecycles,
+    },
+    Scenario {
+        name: "pos",
+        description: "Bonding, unbonding and withdrawing with a growing \
+                      number of bonds, from honest and slashed validators",
+        wasm: None,
+        run: pos::lifecycles,
+    },
+    Scenario {
+        name: "validator_set",
+        description: "Initializing validators and moving them between the \
+                      validator sets, with 10, 100 and 1000 validators",
+        wasm: None,
+        run: validator_set::scaling,
+    },
+    Scenario {
+        name: "signing",
+        description: "Decrypting the signing key and signing a tx with it",
+        wasm: None,
+        run: keys::signing,
+    },
+];
+
+/// Look up a scenario by name
+pub fn scenario(name: &str) -> Option<&'static Scenario> {
+    SCENARIOS.iter().find(|scenario| scenario.name == name)
+}
+
+/// Run every scenario
+pub fn benchmark(c: &mut Criterion) {
+    for scenario in SCENARIOS {
+        (scenario.run)(c);
+    }
+}
+
+/// Run the scenarios under criterion, only the benchmarks matching the
+/// `filter` regex if any, and save their results as the given baseline
+pub fn run(
+    scenarios: &[&Scenario],
+    filter: Option<String>,
+    baseline: Option<String>,
+) {
+    let mut c = Criterion::default();
+    if let Some(filter) = filter {
+        c = c.with_filter(filter);
+    }
+    if let Some(baseline) = baseline {
+        c = c.save_baseline(baseline);
+    }
+    for scenario in scenarios {
+        (scenario.run)(&mut c);
+    }
+    c.final_summary();
+}
@@ -1,9 +1,372 @@
-use criterion::{criterion_group, criterion_main, Criterion};
-mod tx;
+//! The `tx-bench` command line
 
-pub fn benchmark(c: &mut Criterion) {
-    tx::transfer(c);
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process;
+use std::time::SystemTime;
+
+use borsh::BorshSerialize;
+use clap::{Args, Parser, Subcommand};
+use tokio::runtime::Runtime;
+use tx_bench::alloc::CountingAllocator;
+use tx_bench::compare;
+use tx_bench::corpus::{self, Corpus};
+use tx_bench::devnet::{Devnet, InProcessLedger};
+use tx_bench::keys::{KeyStorage, ENV_VAR_CACHE_KEYS, ENV_VAR_KEY_STORAGE};
+use tx_bench::results::{self, Format, Table};
+use tx_bench::tx::{ENV_VAR_LEDGER_ADDRESS, ENV_VAR_WALLET};
+use tx_bench::wasm::{self, ENV_VAR_WASM_DIR};
+use tx_bench::{fixtures, Scenario, SCENARIOS};
+
+#[global_allocator]
+static GLOBAL: CountingAllocator = CountingAllocator;
+
+/// Benchmarks of Namada transactions
+#[derive(Debug, Parser)]
+#[clap(name = "tx-bench")]
+struct Cli {
+    #[clap(flatten)]
+    global: GlobalArgs,
+    #[clap(subcommand)]
+    command: Command,
+}
+
+#[derive(Debug, Args)]
+struct GlobalArgs {
+    /// Tendermint RPC address of the ledger to benchmark against
+    #[clap(long, global = true, env = ENV_VAR_LEDGER_ADDRESS)]
+    ledger_address: Option<String>,
+    /// A `wallet.toml` to start from, e.g. one written by `fixtures`
+    #[clap(long, global = true, env = ENV_VAR_WALLET)]
+    wallet: Option<PathBuf>,
+    /// Directory with the wasm artifacts and their `checksums.json`
+    #[clap(long, global = true, env = ENV_VAR_WASM_DIR)]
+    wasm_dir: Option<PathBuf>,
+    /// How the scenarios store the keys they sign with
+    #[clap(long, global = true, value_enum, env = ENV_VAR_KEY_STORAGE)]
+    key_storage: Option<KeyStorage>,
+    /// Keep decrypted signing keys in memory after their first use
+    #[clap(long, global = true)]
+    cache_keys: bool,
+    /// Format of the tables printed by every command
+    #[clap(long, global = true, value_enum, default_value_t)]
+    format: Format,
+}
+
+impl GlobalArgs {
+    /// Pass the flags on to the library, which reads them from the
+    /// environment
+    fn export_env(&self) {
+        if let Some(ledger_address) = &self.ledger_address {
+            std::env::set_var(ENV_VAR_LEDGER_ADDRESS, ledger_address);
+        }
+        if let Some(wallet) = &self.wallet {
+            std::env::set_var(ENV_VAR_WALLET, wallet);
+        }
+        if let Some(wasm_dir) = &self.wasm_dir {
+            std::env::set_var(ENV_VAR_WASM_DIR, wasm_dir);
+        }
+        if let Some(key_storage) = &self.key_storage {
+            std::env::set_var(ENV_VAR_KEY_STORAGE, key_storage.to_string());
+        }
+        if self.cache_keys {
+            std::env::set_var(ENV_VAR_CACHE_KEYS, "1");
+        }
+    }
+}
+
+#[derive(Debug, Subcommand)]
+enum Command {
+    /// Run benchmark scenarios against the ledger
+    Run(RunArgs),
+    /// List the benchmark scenarios
+    List,
+    /// Compare the medians of two baselines saved by `run --save-baseline`
+    Compare {
+        /// The baseline to compare against
+        before: String,
+        /// The baseline to compare
+        after: String,
+    },
+    /// Run the same scenarios with two sets of wasm artifacts and report
+    /// the change of every benchmark between them
+    Ab {
+        /// Directory with the wasm artifacts to compare against
+        before: PathBuf,
+        /// Directory with the wasm artifacts to compare
+        after: PathBuf,
+        /// The scenarios to run, all of them if none are given
+        scenarios: Vec<String>,
+        /// Only run the benchmarks whose ID matches this regex
+        #[clap(long)]
+        filter: Option<String>,
+        /// Start a devnet from this genesis for each set of artifacts,
+        /// which is needed to measure changes to the VPs
+        #[clap(long)]
+        genesis: Option<PathBuf>,
+        /// How many validators the devnets run
+        #[clap(long, default_value_t = 1)]
+        validators: u16,
+    },
+    /// Create and fund test accounts and write them to a new wallet
+    Fixtures {
+        /// How many implicit and how many established accounts to create
+        count: usize,
+        /// Where to write the wallet to
+        #[clap(long, default_value = fixtures::DEFAULT_OUT_DIR)]
+        out_dir: PathBuf,
+    },
+    /// Build or replay a corpus of signed txs
+    #[clap(subcommand)]
+    Corpus(CorpusCommand),
+    /// Start a local devnet, run benchmark scenarios against it and tear it
+    /// down. Use 4 or 7 validators to see consensus and gossip costs in the
+    /// end-to-end latency.
+    Devnet {
+        /// The genesis to initialize the network from
+        #[clap(long)]
+        genesis: PathBuf,
+        /// How many validators to run
+        #[clap(long, default_value_t = 1)]
+        validators: u16,
+        #[clap(flatten)]
+        run: RunArgs,
+    },
+    /// Replay a corpus and suggest the gas of every wasm in it from what
+    /// the ledger charged, in the format of `gas.json`
+    CalibrateGas {
+        /// The corpus to replay
+        corpus: PathBuf,
+        /// Write the suggested gas to this file instead of printing it
+        #[clap(long)]
+        out: Option<PathBuf>,
+        #[clap(flatten)]
+        ledger: LedgerArgs,
+    },
+}
+
+#[derive(Debug, Subcommand)]
+enum CorpusCommand {
+    /// Build and sign txs of every kind for the ledger's chain
+    Build {
+        /// Where to write the corpus to
+        out: PathBuf,
+        /// How many txs of every kind to build
+        #[clap(long, default_value_t = 1)]
+        count: usize,
+        #[clap(flatten)]
+        ledger: LedgerArgs,
+    },
+    /// Submit the txs of a corpus and report their time to commit
+    Replay {
+        /// The corpus to replay
+        corpus: PathBuf,
+        #[clap(flatten)]
+        ledger: LedgerArgs,
+    },
+    /// Build the shielding transfer of `vp_masp`'s acceptance test
+    MaspFixture {
+        /// Where to write the fixture to
+        out: PathBuf,
+        #[clap(flatten)]
+        ledger: LedgerArgs,
+    },
+}
+
+#[derive(Debug, Args)]
+struct LedgerArgs {
+    /// Run the ledger of this base dir in this process and use it instead of
+    /// the configured ledger, e.g. a validator's `.namada` dir
+    #[clap(long)]
+    in_process: Option<PathBuf>,
+}
+
+impl LedgerArgs {
+    /// Start the in-process ledger, if any, which runs until it's dropped
+    fn start(&self) -> Option<InProcessLedger> {
+        self.in_process.as_deref().map(|base_dir| {
+            let ledger = InProcessLedger::start(base_dir);
+            ledger.export_env();
+            ledger
+        })
+    }
 }
 
-criterion_group!(benches, benchmark);
-criterion_main!(benches);
+#[derive(Debug, Args)]
+struct RunArgs {
+    /// The scenarios to run, all of them if none are given
+    scenarios: Vec<String>,
+    /// Only run the benchmarks whose ID matches this regex
+    #[clap(long)]
+    filter: Option<String>,
+    /// Save the results as a criterion baseline with this name
+    #[clap(long)]
+    save_baseline: Option<String>,
+    /// Also write the results to this file, as JSON, CSV or Markdown by its
+    /// extension
+    #[clap(long)]
+    results: Option<PathBuf>,
+}
+
+fn main() {
+    let cli = Cli::parse();
+    cli.global.export_env();
+    let format = cli.global.format;
+    match cli.command {
+        Command::Run(args) => run(args, format),
+        Command::List => list(format),
+        Command::Compare { before, after } => {
+            let changes =
+                compare::compare(&compare::criterion_dir(), &before, &after);
+            print!("{}", compare::table(&changes).render(format));
+        }
+        Command::Ab {
+            before,
+            after,
+            scenarios,
+            filter,
+            genesis,
+            validators,
+        } => {
+            let changes = compare::ab(
+                &before,
+                &after,
+                &select(&scenarios),
+                filter,
+                genesis.as_deref().map(|genesis| (genesis, validators)),
+            );
+            print!("{}", compare::table(&changes).render(format));
+        }
+        Command::Fixtures { count, out_dir } => Runtime::new()
+            .unwrap()
+            .block_on(fixtures::generate(count, &out_dir, &wasm::wasm_dir())),
+        Command::Corpus(CorpusCommand::Build { out, count, ledger }) => {
+            let _ledger = ledger.start();
+            let corpus = Runtime::new()
+                .unwrap()
+                .block_on(corpus::build(count, &wasm::wasm_dir()));
+            corpus.save(&out).expect("Unable to write the corpus");
+            println!("Wrote {} txs to {}", corpus.entries.len(), out.display());
+        }
+        Command::Corpus(CorpusCommand::Replay { corpus, ledger }) => {
+            let _ledger = ledger.start();
+            let corpus = load_corpus(&corpus);
+            Runtime::new().unwrap().block_on(corpus::replay(&corpus)).report();
+        }
+        Command::Corpus(CorpusCommand::MaspFixture { out, ledger }) => {
+            let _ledger = ledger.start();
+            let fixture =
+                Runtime::new().unwrap().block_on(corpus::masp_fixture());
+            fs::write(&out, fixture.try_to_vec().unwrap())
+                .expect("Unable to write the fixture");
+            println!("Wrote the fixture to {}", out.display());
+        }
+        Command::Devnet {
+            genesis,
+            validators,
+            run: args,
+        } => {
+            let devnet = Devnet::start(&genesis, &wasm::wasm_dir(), validators);
+            devnet.export_env();
+            run(args, format);
+        }
+        Command::CalibrateGas {
+            corpus,
+            out,
+            ledger,
+        } => {
+            let _ledger = ledger.start();
+            let corpus = load_corpus(&corpus);
+            let replay =
+                Runtime::new().unwrap().block_on(corpus::replay(&corpus));
+            replay.report();
+            let gas = corpus::calibrate_gas(&replay);
+            match out {
+                Some(out) => {
+                    fs::write(&out, gas_json(&gas))
+                        .expect("Unable to write the gas");
+                    println!(
+                        "Wrote the gas of {} wasms to {}",
+                        gas.len(),
+                        out.display()
+                    );
+                }
+                None => print_gas(&gas, format),
+            }
+        }
+    }
+}
+
+/// Run the selected scenarios under criterion and print their results
+fn run(args: RunArgs, format: Format) {
+    let results_format = args.results.as_ref().map(|path| {
+        Format::from_path(path).unwrap_or_else(|| {
+            eprintln!("Unknown results format of {}", path.display());
+            process::exit(1);
+        })
+    });
+    let scenarios = select(&args.scenarios);
+    let started = SystemTime::now();
+    tx_bench::run(&scenarios, args.filter, args.save_baseline);
+
+    let results = results::collect(
+        &compare::criterion_dir(),
+        started,
+        &wasm::wasm_dir(),
+    );
+    print!("{}", results.render(format));
+    if let (Some(path), Some(results_format)) = (args.results, results_format)
+    {
+        fs::write(&path, results.render(results_format))
+            .expect("Unable to write the results");
+        println!("Wrote the results to {}", path.display());
+    }
+}
+
+/// Look up the scenarios by name, exiting on unknown ones
+fn select(names: &[String]) -> Vec<&'static Scenario> {
+    if names.is_empty() {
+        return SCENARIOS.iter().collect();
+    }
+    names
+        .iter()
+        .map(|name| {
+            tx_bench::scenario(name).unwrap_or_else(|| {
+                eprintln!("Unknown scenario {name}, see `tx-bench list`");
+                process::exit(1);
+            })
+        })
+        .collect()
+}
+
+fn list(format: Format) {
+    let mut table = Table::new(&["name", "description", "wasm"]);
+    for scenario in SCENARIOS {
+        table.push(vec![
+            scenario.name.into(),
+            scenario.description.into(),
+            scenario.wasm.into(),
+        ]);
+    }
+    print!("{}", table.render(format));
+}
+
+fn print_gas(gas: &BTreeMap<String, u64>, format: Format) {
+    let mut table = Table::new(&["wasm", "gas"]);
+    for (name, gas) in gas {
+        table.push(vec![name.clone().into(), (*gas).into()]);
+    }
+    print!("{}", table.render(format));
+}
+
+/// Format the gas like `gas.json`
+fn gas_json(gas: &BTreeMap<String, u64>) -> String {
+    serde_json::to_string_pretty(gas).unwrap()
+}
+
+fn load_corpus(path: &Path) -> Corpus {
+    Corpus::load(path).unwrap_or_else(|err| {
+        panic!("Unable to read the corpus {}: {err}", path.display())
+    })
+}
@@ -0,0 +1,133 @@
+//! Node-side metrics scraped from the ledger's Prometheus endpoint before
+//! and after a benchmark, so that the server-side work is reported next to
+//! the client-side timings.
+
+use std::collections::BTreeMap;
+use std::io::{Read, Write};
+use std::net::TcpStream;
+use std::time::Duration;
+
+use crate::results;
+
+/// Environment variable with the address of the Prometheus endpoint to
+/// scrape, set by `devnet`. If unset, no metrics are captured.
+pub const ENV_VAR_METRICS_ADDRESS: &str = "TX_BENCH_METRICS_ADDRESS";
+/// The `instrumentation_namespace` of the ledger's Tendermint config
+const NAMESPACE: &str = "namadan_tm";
+/// How long to wait for the endpoint to respond
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+/// The values of all the metrics of one scrape. Labelled series are summed
+/// up per metric name.
+#[derive(Debug, Clone, Default)]
+pub struct Snapshot(BTreeMap<String, f64>);
+
+impl Snapshot {
+    /// Scrape the endpoint at `ENV_VAR_METRICS_ADDRESS`, if any. A failed
+    /// scrape is reported and otherwise ignored, so that it doesn't stop the
+    /// benchmarks.
+    pub fn take() -> Option<Self> {
+        let addr = std::env::var(ENV_VAR_METRICS_ADDRESS).ok()?;
+        match scrape(&addr) {
+            Ok(body) => Some(Self::parse(&body)),
+            Err(err) => {
+                eprintln!("Unable to scrape metrics from {addr}: {err}");
+                None
+            }
+        }
+    }
+
+    /// Parse the Prometheus text exposition format
+    fn parse(body: &str) -> Self {
+        let mut metrics = BTreeMap::new();
+        for line in body.lines() {
+            if line.starts_with('#') || line.trim().is_empty() {
+                continue;
+            }
+            let (series, value) = match line.rsplit_once(' ') {
+                Some(split) => split,
+                None => continue,
+            };
+            let name = series.split('{').next().unwrap_or(series);
+            if let Ok(value) = value.parse::<f64>() {
+                *metrics.entry(name.to_string()).or_default() += value;
+            }
+        }
+        Self(metrics)
+    }
+
+    fn get(&self, metric: &str) -> f64 {
+        self.0
+            .get(&format!("{NAMESPACE}_{metric}"))
+            .copied()
+            .unwrap_or_default()
+    }
+
+    /// Scrape again, print the deltas since this snapshot for the benchmark
+    /// `id` and record them for its results
+    pub fn report(self, id: &str) {
+        let after = match Self::take() {
+            Some(after) => after,
+            None => return,
+        };
+        let delta = |metric: &str| after.get(metric) - self.get(metric);
+        let intervals = delta("consensus_block_interval_seconds_count");
+        let deltas = Deltas {
+            blocks: delta("consensus_height"),
+            block_time_ms: (intervals > 0.0).then(|| {
+                1000.0 * delta("consensus_block_interval_seconds_sum")
+                    / intervals
+            }),
+            txs: delta("consensus_total_txs"),
+            rounds: delta("consensus_rounds"),
+            mempool_size: after.get("mempool_size"),
+        };
+        let block_time = deltas
+            .block_time_ms
+            .map(|block_time| format!("{block_time:.0}ms"))
+            .unwrap_or_else(|| "-".to_string());
+        println!(
+            "{id:<40} node: {} blocks, mean block time {block_time}, {} txs, \
+             {} rounds, mempool size {} -> {}",
+            deltas.blocks,
+            deltas.txs,
+            deltas.rounds,
+            self.get("mempool_size"),
+            deltas.mempool_size,
+        );
+        results::record_node_metrics(id, deltas);
+    }
+}
+
+/// What the node did while a benchmark ran
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Deltas {
+    /// Blocks committed
+    pub blocks: f64,
+    /// Mean time between the blocks, if any were committed
+    pub block_time_ms: Option<f64>,
+    /// Txs committed
+    pub txs: f64,
+    /// Consensus rounds
+    pub rounds: f64,
+    /// Txs in the mempool at the end
+    pub mempool_size: f64,
+}
+
+/// Get the metrics page of the endpoint at `addr`
+fn scrape(addr: &str) -> std::io::Result<String> {
+    let mut stream = TcpStream::connect(addr)?;
+    stream.set_read_timeout(Some(TIMEOUT))?;
+    stream.set_write_timeout(Some(TIMEOUT))?;
+    write!(
+        stream,
+        "GET /metrics HTTP/1.0\r\nHost: {addr}\r\nAccept: text/plain\r\n\r\n"
+    )?;
+    let mut response = String::new();
+    stream.read_to_string(&mut response)?;
+    let body = response
+        .split_once("\r\n\r\n")
+        .map(|(_headers, body)| body)
+        .unwrap_or_default();
+    Ok(body.to_string())
+}
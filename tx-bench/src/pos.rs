@@ -0,0 +1,313 @@
+//! The PoS lifecycle of delegators with a growing number of bonds: every
+//! bond is made in an epoch of its own, so that unbonding everything at
+//! once has to walk all of them and leaves as many unbonds for the
+//! withdrawal to walk. Bonds only become unbonds and unbonds only mature
+//! across epochs, so every step is timed once as it happens rather than
+//! repeated by criterion.
+//!
+//! Given a genesis in `TX_BENCH_SLASHING_GENESIS`, the lifecycle runs on a
+//! devnet of its own instead, where it's repeated for a validator that is
+//! slashed for double signing while bonded to, so that the withdrawals
+//! have to account for the slash.
+
+use std::collections::BTreeMap;
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+use borsh::BorshSerialize;
+use criterion::Criterion;
+use namada::ledger::queries::RPC;
+use namada::types::address::Address;
+use namada::types::storage::Epoch;
+use namada::types::token;
+use namada::types::transaction::pos;
+use tokio::runtime::Runtime;
+
+use crate::chain::{Account, Chain};
+use crate::devnet::Devnet;
+use crate::results;
+use crate::submit::Committed;
+use crate::tx;
+use crate::wasm;
+
+/// A genesis to start the devnet with a slashed validator from
+pub const ENV_VAR_SLASHING_GENESIS: &str = "TX_BENCH_SLASHING_GENESIS";
+/// Numbers of bonds each delegator makes
+const BOND_COUNTS: [usize; 3] = [1, 4, 16];
+/// How much every bond is of
+const BOND: u64 = 10;
+/// Validators of the devnet with a slashed validator. One of four can
+/// misbehave without stalling the chain.
+const DEVNET_VALIDATORS: u16 = 4;
+/// The devnet validator that double signs
+const SLASHED_VALIDATOR: usize = 1;
+/// How long to wait for the double signing validator to be slashed
+const SLASH_TIMEOUT: Duration = Duration::from_secs(600);
+
+/// A delegator, with the number of bonds it makes
+struct Delegator {
+    account: Account,
+    bonds: usize,
+}
+
+/// The txs of one validator's delegators
+#[derive(Default)]
+struct Lifecycle {
+    bonds: BTreeMap<usize, Vec<Option<Committed>>>,
+    unbonds: BTreeMap<usize, Option<Committed>>,
+    withdrawals: BTreeMap<usize, Option<Committed>>,
+}
+
+impl Lifecycle {
+    fn report(&self, validator: &str) {
+        for (count, bonds) in &self.bonds {
+            let id = format!("pos/bond/{validator}/{count}");
+            results::record_wasm(&id, "tx_bond");
+            let times: Vec<Duration> = bonds
+                .iter()
+                .flatten()
+                .filter(|committed| committed.code == "0")
+                .map(|committed| committed.time_to_commit)
+                .collect();
+            results::record_errors(&id, bonds.len() - times.len());
+            results::record_samples(&id, times);
+        }
+        for (step, wasm, txs) in [
+            ("unbond", "tx_unbond", &self.unbonds),
+            ("withdraw", "tx_withdraw", &self.withdrawals),
+        ] {
+            for (count, committed) in txs {
+                let id = format!("pos/{step}/{validator}/{count}");
+                results::record_wasm(&id, wasm);
+                match committed {
+                    Some(committed) if committed.code == "0" => {
+                        results::record_samples(
+                            &id,
+                            [committed.time_to_commit],
+                        );
+                        println!(
+                            "{id:<40} commit: {:?}, gas used: {}",
+                            committed.time_to_commit, committed.gas_used,
+                        );
+                    }
+                    _ => {
+                        results::record_errors(&id, 1);
+                        println!("{id:<40} failed: {committed:?}");
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Fund one delegator per bond count
+async fn delegators(chain: &mut Chain) -> Vec<Delegator> {
+    let mut delegators = vec![];
+    for bonds in BOND_COUNTS {
+        let funds = token::Amount::whole(2 * BOND * bonds as u64);
+        delegators.push(Delegator {
+            account: chain.new_account(funds).await,
+            bonds,
+        });
+    }
+    delegators
+}
+
+/// Have every delegator bond to `validator`, each in as many epochs as it
+/// has bonds
+async fn bond(
+    chain: &mut Chain,
+    delegators: &[Delegator],
+    validator: &Address,
+    lifecycle: &mut Lifecycle,
+) {
+    let rounds = delegators.iter().map(|d| d.bonds).max().unwrap_or(0);
+    for round in 0..rounds {
+        let epoch = chain.epoch().await;
+        for delegator in delegators.iter().filter(|d| d.bonds > round) {
+            let bond = pos::Bond {
+                validator: validator.clone(),
+                amount: token::Amount::whole(BOND),
+                source: Some(delegator.account.address.clone()),
+            };
+            let committed = chain
+                .submit(
+                    "tx_bond",
+                    bond.try_to_vec().unwrap(),
+                    Some(&delegator.account.key),
+                )
+                .await;
+            lifecycle
+                .bonds
+                .entry(delegator.bonds)
+                .or_default()
+                .push(committed);
+        }
+        chain.wait_for_epoch(epoch.next()).await;
+    }
+}
+
+/// Have every delegator unbond everything from `validator` at once
+async fn unbond(
+    chain: &mut Chain,
+    delegators: &[Delegator],
+    validator: &Address,
+    lifecycle: &mut Lifecycle,
+) {
+    for delegator in delegators {
+        let unbond = pos::Unbond {
+            validator: validator.clone(),
+            amount: token::Amount::whole(BOND * delegator.bonds as u64),
+            source: Some(delegator.account.address.clone()),
+        };
+        let committed = chain
+            .submit(
+                "tx_unbond",
+                unbond.try_to_vec().unwrap(),
+                Some(&delegator.account.key),
+            )
+            .await;
+        lifecycle.unbonds.insert(delegator.bonds, committed);
+    }
+}
+
+/// Have every delegator withdraw its matured unbonds from `validator`
+async fn withdraw(
+    chain: &mut Chain,
+    delegators: &[Delegator],
+    validator: &Address,
+    lifecycle: &mut Lifecycle,
+) {
+    for delegator in delegators {
+        let withdraw = pos::Withdraw {
+            validator: validator.clone(),
+            source: Some(delegator.account.address.clone()),
+        };
+        let committed = chain
+            .submit(
+                "tx_withdraw",
+                withdraw.try_to_vec().unwrap(),
+                Some(&delegator.account.key),
+            )
+            .await;
+        lifecycle.withdrawals.insert(delegator.bonds, committed);
+    }
+}
+
+/// The epoch by which all the bonds made before now have their stake
+async fn pipeline_epoch(chain: &Chain) -> Epoch {
+    let pipeline_len = chain.pos_params().await.pipeline_len;
+    chain.epoch().await + pipeline_len
+}
+
+/// The epoch by which all the unbonds made before now can be withdrawn
+async fn withdrawable_epoch(chain: &Chain) -> Epoch {
+    let unbonding_len = chain.pos_params().await.unbonding_len;
+    chain.epoch().await + unbonding_len + 1
+}
+
+/// Block until `validator` has been slashed
+async fn wait_for_slash(chain: &Chain, validator: &Address) {
+    let deadline = Instant::now() + SLASH_TIMEOUT;
+    loop {
+        let slashes = RPC
+            .vp()
+            .pos()
+            .validator_slashes(&chain.client, validator)
+            .await
+            .expect("Unable to query the slashes");
+        if !slashes.is_empty() {
+            return;
+        }
+        assert!(
+            Instant::now() < deadline,
+            "{validator} was not slashed within {SLASH_TIMEOUT:?}"
+        );
+        tokio::time::sleep(Duration::from_secs(1)).await;
+    }
+}
+
+/// Run the lifecycle for delegators of `validator` on the configured chain
+async fn run(validator: Address) -> Lifecycle {
+    let mut chain = Chain::connect().await;
+    let delegators = delegators(&mut chain).await;
+    let mut lifecycle = Lifecycle::default();
+    bond(&mut chain, &delegators, &validator, &mut lifecycle).await;
+    chain.wait_for_epoch(pipeline_epoch(&chain).await).await;
+    unbond(&mut chain, &delegators, &validator, &mut lifecycle).await;
+    chain.wait_for_epoch(withdrawable_epoch(&chain).await).await;
+    withdraw(&mut chain, &delegators, &validator, &mut lifecycle).await;
+    lifecycle
+}
+
+/// Run the lifecycle on a devnet for delegators of an honest validator and
+/// of one that is slashed once their bonds are active, before they unbond
+async fn run_with_slashing(devnet: &mut Devnet) -> (Lifecycle, Lifecycle) {
+    let mut chain = Chain::connect_to(devnet.rpc_address.clone()).await;
+    let honest = devnet.validator_address(0);
+    let slashed = devnet.validator_address(SLASHED_VALIDATOR);
+
+    let mut honest_lifecycle = Lifecycle::default();
+    let mut slashed_lifecycle = Lifecycle::default();
+    let honest_delegators = delegators(&mut chain).await;
+    let slashed_delegators = delegators(&mut chain).await;
+    bond(&mut chain, &honest_delegators, &honest, &mut honest_lifecycle)
+        .await;
+    bond(
+        &mut chain,
+        &slashed_delegators,
+        &slashed,
+        &mut slashed_lifecycle,
+    )
+    .await;
+    chain.wait_for_epoch(pipeline_epoch(&chain).await).await;
+
+    devnet.equivocate(SLASHED_VALIDATOR);
+    wait_for_slash(&chain, &slashed).await;
+
+    unbond(&mut chain, &honest_delegators, &honest, &mut honest_lifecycle)
+        .await;
+    unbond(
+        &mut chain,
+        &slashed_delegators,
+        &slashed,
+        &mut slashed_lifecycle,
+    )
+    .await;
+    chain.wait_for_epoch(withdrawable_epoch(&chain).await).await;
+    withdraw(&mut chain, &honest_delegators, &honest, &mut honest_lifecycle)
+        .await;
+    withdraw(
+        &mut chain,
+        &slashed_delegators,
+        &slashed,
+        &mut slashed_lifecycle,
+    )
+    .await;
+    (honest_lifecycle, slashed_lifecycle)
+}
+
+/// Bond, unbond and withdraw with a growing number of bonds per delegator
+pub fn lifecycles(_c: &mut Criterion) {
+    let runtime = Runtime::new().unwrap();
+    match std::env::var(ENV_VAR_SLASHING_GENESIS) {
+        Ok(genesis) => {
+            let mut devnet = Devnet::start(
+                Path::new(&genesis),
+                &wasm::wasm_dir(),
+                DEVNET_VALIDATORS,
+            );
+            let (honest, slashed) =
+                runtime.block_on(run_with_slashing(&mut devnet));
+            honest.report("honest");
+            slashed.report("slashed");
+        }
+        Err(_) => {
+            println!(
+                "{ENV_VAR_SLASHING_GENESIS} is not set, skipping the \
+                 slashed validator"
+            );
+            runtime.block_on(run(tx::validator())).report("honest");
+        }
+    }
+}
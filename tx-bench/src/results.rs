@@ -0,0 +1,357 @@
+//! Normalized results of a benchmark run. Criterion spreads its estimates
+//! over one directory per benchmark, so after a run the samples of every
+//! benchmark are read back and joined with the wasm hashes, the gas costs,
+//! the errors counted while benchmarking and the node's metrics, into one
+//! table that can be written out as JSON, CSV or Markdown.
+//!
+//! The times are summarized over a benchmark's samples. A criterion sample
+//! is the mean time of a batch of iterations, which evens out the slowest
+//! ones, so the `sample_p95_ns` and `sample_p99_ns` columns aren't tail
+//! latencies of single iterations. Only the samples recorded with
+//! `record_samples`, e.g. of the lifecycles, are single iterations.
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime};
+
+use serde_json::Value;
+
+use crate::metrics::Deltas;
+use crate::wasm;
+use crate::SCENARIOS;
+
+/// Failed iterations by benchmark ID, counted while benchmarking
+static ERRORS: Mutex<BTreeMap<String, usize>> = Mutex::new(BTreeMap::new());
+
+/// Add `count` failed iterations to the benchmark `id`
+pub fn record_errors(id: &str, count: usize) {
+    *ERRORS.lock().unwrap().entry(id.to_string()).or_default() += count;
+}
+
+/// Samples of the benchmarks that are timed outside of criterion, such as
+/// lifecycles that can't be repeated at will, in nanoseconds by benchmark ID
+static SAMPLES: Mutex<BTreeMap<String, Vec<f64>>> = Mutex::new(BTreeMap::new());
+
+/// Add samples of the benchmark `id` that was timed outside of criterion
+pub fn record_samples(id: &str, samples: impl IntoIterator<Item = Duration>) {
+    SAMPLES
+        .lock()
+        .unwrap()
+        .entry(id.to_string())
+        .or_default()
+        .extend(samples.into_iter().map(|sample| sample.as_nanos() as f64));
+}
+
+/// The wasm that every benchmark ran by ID, for the benchmarks of scenarios
+/// whose txs don't all run the same one
+static WASMS: Mutex<BTreeMap<String, &'static str>> =
+    Mutex::new(BTreeMap::new());
+
+/// Set the wasm that the benchmark `id` ran, e.g. `tx_bond`
+pub fn record_wasm(id: &str, wasm: &'static str) {
+    WASMS.lock().unwrap().insert(id.to_string(), wasm);
+}
+
+/// Deltas of the node's metrics by benchmark ID, scraped while benchmarking
+static NODE_METRICS: Mutex<BTreeMap<String, Deltas>> =
+    Mutex::new(BTreeMap::new());
+
+/// Set the deltas of the node's metrics over the benchmark `id`
+pub fn record_node_metrics(id: &str, deltas: Deltas) {
+    NODE_METRICS.lock().unwrap().insert(id.to_string(), deltas);
+}
+
+/// How tables are written out
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum Format {
+    /// Aligned columns for the terminal
+    #[default]
+    Text,
+    Json,
+    Csv,
+    Markdown,
+}
+
+impl Format {
+    /// The format of a file, by its extension
+    pub fn from_path(path: &Path) -> Option<Self> {
+        match path.extension()?.to_str()? {
+            "json" => Some(Self::Json),
+            "csv" => Some(Self::Csv),
+            "md" => Some(Self::Markdown),
+            "txt" => Some(Self::Text),
+            _ => None,
+        }
+    }
+}
+
+/// Rows of values under named columns
+#[derive(Debug, Clone)]
+pub struct Table {
+    pub columns: Vec<&'static str>,
+    pub rows: Vec<Vec<Value>>,
+}
+
+impl Table {
+    pub fn new(columns: &[&'static str]) -> Self {
+        Self {
+            columns: columns.to_vec(),
+            rows: vec![],
+        }
+    }
+
+    pub fn push(&mut self, row: Vec<Value>) {
+        debug_assert_eq!(row.len(), self.columns.len());
+        self.rows.push(row);
+    }
+
+    pub fn render(&self, format: Format) -> String {
+        match format {
+            Format::Text => self.render_text(),
+            Format::Json => self.render_json(),
+            Format::Csv => self.render_csv(),
+            Format::Markdown => self.render_markdown(),
+        }
+    }
+
+    /// An array with one object per row
+    fn render_json(&self) -> String {
+        let rows: Vec<Value> = self
+            .rows
+            .iter()
+            .map(|row| {
+                let object = self
+                    .columns
+                    .iter()
+                    .map(|column| column.to_string())
+                    .zip(row.iter().cloned())
+                    .collect();
+                Value::Object(object)
+            })
+            .collect();
+        serde_json::to_string_pretty(&rows).unwrap()
+    }
+
+    fn render_csv(&self) -> String {
+        let escape = |cell: String| {
+            if cell.contains([',', '"', '\n']) {
+                format!("\"{}\"", cell.replace('"', "\"\""))
+            } else {
+                cell
+            }
+        };
+        let mut out = self.columns.join(",");
+        out.push('\n');
+        for row in &self.rows {
+            let cells: Vec<String> =
+                row.iter().map(|value| escape(cell(value))).collect();
+            out.push_str(&cells.join(","));
+            out.push('\n');
+        }
+        out
+    }
+
+    fn render_markdown(&self) -> String {
+        let line =
+            |cells: Vec<String>| format!("| {} |\n", cells.join(" | "));
+        let mut out =
+            line(self.columns.iter().map(|c| c.to_string()).collect());
+        out.push_str(&line(
+            self.columns.iter().map(|_| "---".to_string()).collect(),
+        ));
+        for row in &self.rows {
+            out.push_str(&line(
+                row.iter()
+                    .map(|value| cell(value).replace('|', "\\|"))
+                    .collect(),
+            ));
+        }
+        out
+    }
+
+    fn render_text(&self) -> String {
+        let rows: Vec<Vec<String>> = self
+            .rows
+            .iter()
+            .map(|row| {
+                row.iter()
+                    .map(|value| match value {
+                        Value::Null => "-".to_string(),
+                        value => cell(value),
+                    })
+                    .collect()
+            })
+            .collect();
+        let widths: Vec<usize> = self
+            .columns
+            .iter()
+            .enumerate()
+            .map(|(i, column)| {
+                rows.iter()
+                    .map(|row| row[i].len())
+                    .chain([column.len()])
+                    .max()
+                    .unwrap_or_default()
+            })
+            .collect();
+        let line = |cells: Vec<&str>| {
+            let cells: Vec<String> = cells
+                .iter()
+                .zip(&widths)
+                .map(|(cell, width)| format!("{cell:<width$}"))
+                .collect();
+            format!("{}\n", cells.join("  ").trim_end())
+        };
+        let mut out = line(self.columns.clone());
+        for row in &rows {
+            out.push_str(&line(row.iter().map(String::as_str).collect()));
+        }
+        out
+    }
+}
+
+/// A value as it's written into a CSV or Markdown cell
+fn cell(value: &Value) -> String {
+    match value {
+        Value::Null => String::new(),
+        Value::String(string) => string.clone(),
+        value => value.to_string(),
+    }
+}
+
+/// The results of every benchmark that criterion measured since `since`
+/// or that was recorded with `record_samples`, with the hashes and gas of
+/// the wasms in `wasm_dir` and the node's metrics, if they were scraped
+pub fn collect(
+    criterion_dir: &Path,
+    since: SystemTime,
+    wasm_dir: &Path,
+) -> Table {
+    let hashes = wasm::hashes(wasm_dir);
+    let gas = wasm::gas(wasm_dir);
+    let errors = ERRORS.lock().unwrap();
+    let wasms = WASMS.lock().unwrap();
+    let node_metrics = NODE_METRICS.lock().unwrap();
+    let mut table = Table::new(&[
+        "id",
+        "kind",
+        "wasm",
+        "wasm_hash",
+        "median_ns",
+        "sample_p95_ns",
+        "sample_p99_ns",
+        "samples",
+        "errors",
+        "gas",
+        "node_blocks",
+        "node_block_time_ms",
+        "node_txs",
+        "node_rounds",
+        "node_mempool_size",
+    ]);
+    let mut measured = measured(criterion_dir, since);
+    measured.extend(SAMPLES.lock().unwrap().clone());
+    for (id, mut times) in measured {
+        times.sort_by(f64::total_cmp);
+        // Benchmark IDs are `<scenario>/<kind>/<parameter>`
+        let scenario = id.split('/').next().unwrap_or_default();
+        let kind: Vec<&str> = id.splitn(3, '/').take(2).collect();
+        let wasm = wasms.get(&id).copied().or_else(|| {
+            SCENARIOS
+                .iter()
+                .find(|s| s.name == scenario)
+                .and_then(|s| s.wasm)
+        });
+        let node = node_metrics.get(&id);
+        table.push(vec![
+            id.clone().into(),
+            kind.join("/").into(),
+            wasm.into(),
+            wasm.and_then(|wasm| hashes.get(wasm).cloned()).into(),
+            percentile(&times, 50.0).into(),
+            percentile(&times, 95.0).into(),
+            percentile(&times, 99.0).into(),
+            times.len().into(),
+            errors.get(&id).copied().unwrap_or_default().into(),
+            wasm.and_then(|wasm| gas.get(wasm).copied()).into(),
+            node.map(|node| node.blocks).into(),
+            node.and_then(|node| node.block_time_ms).into(),
+            node.map(|node| node.txs).into(),
+            node.map(|node| node.rounds).into(),
+            node.map(|node| node.mempool_size).into(),
+        ]);
+    }
+    table
+}
+
+/// The nearest-rank percentile of sorted samples
+fn percentile(sorted: &[f64], percent: f64) -> Option<f64> {
+    if sorted.is_empty() {
+        return None;
+    }
+    let rank = (percent / 100.0 * sorted.len() as f64).ceil() as usize;
+    Some(sorted[rank.clamp(1, sorted.len()) - 1].round())
+}
+
+/// The time per iteration of every sample, in nanoseconds, of every
+/// benchmark whose latest samples were written since `since`, by ID
+fn measured(dir: &Path, since: SystemTime) -> BTreeMap<String, Vec<f64>> {
+    let mut measured = BTreeMap::new();
+    let mut dirs = vec![dir.to_path_buf()];
+    while let Some(dir) = dirs.pop() {
+        let entries = match fs::read_dir(&dir) {
+            Ok(entries) => entries,
+            Err(_) => continue,
+        };
+        for path in entries.filter_map(|entry| entry.ok().map(|e| e.path())) {
+            if !path.is_dir() {
+                continue;
+            }
+            // Criterion keeps the latest samples of every benchmark in `new`
+            if path.file_name().and_then(|name| name.to_str()) != Some("new")
+            {
+                dirs.push(path);
+                continue;
+            }
+            let sample = path.join("sample.json");
+            let fresh = fs::metadata(&sample)
+                .and_then(|metadata| metadata.modified())
+                .map(|modified| modified >= since)
+                .unwrap_or(false);
+            if fresh {
+                let id = read_json(&path.join("benchmark.json"))["full_id"]
+                    .as_str()
+                    .expect("No ID in benchmark.json")
+                    .to_string();
+                measured.insert(id, per_iteration(&read_json(&sample)));
+            }
+        }
+    }
+    measured
+}
+
+/// Criterion samples are total times of a number of iterations, so this is
+/// the mean time per iteration of every sample
+fn per_iteration(sample: &Value) -> Vec<f64> {
+    let values = |key: &str| -> Vec<f64> {
+        sample[key]
+            .as_array()
+            .map(|values| values.iter().filter_map(Value::as_f64).collect())
+            .unwrap_or_default()
+    };
+    values("times")
+        .into_iter()
+        .zip(values("iters"))
+        .map(|(time, iters)| time / iters)
+        .collect()
+}
+
+fn read_json(path: &Path) -> Value {
+    let json = fs::read_to_string(path).unwrap_or_else(|err| {
+        panic!("Unable to read {}: {err}", path.display())
+    });
+    serde_json::from_str(&json)
+        .unwrap_or_else(|err| panic!("Invalid {}: {err}", path.display()))
+}
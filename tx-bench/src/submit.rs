@@ -0,0 +1,350 @@
+//! The ways a benchmarked transaction can be submitted to the ledger.
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use namada::ledger::args;
+use namada::proto::Tx;
+use namada::types::address::Address;
+use namada::types::chain::ChainId;
+use namada::types::key::common::SecretKey;
+use namada::types::storage::Epoch;
+use namada::types::transaction::{
+    process_tx, EncryptionKey, Fee, GasLimit, TxType, WrapperTx,
+};
+use tendermint_rpc::{Client, HttpClient};
+
+use crate::results;
+
+/// How long to wait for a broadcasted tx to be committed
+const COMMIT_TIMEOUT: Duration = Duration::from_secs(60);
+/// How often to poll the node for new blocks
+const POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+/// Dry-run, fire-and-forget broadcast and block inclusion measure very
+/// different things, so every benchmark case is run in each mode.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SubmitMode {
+    /// Execute the tx against the node's state without broadcasting it
+    DryRun,
+    /// Return as soon as the tx has been accepted into the mempool
+    BroadcastOnly,
+    /// Broadcast and then wait for the tx to be committed in a block
+    Inclusion,
+}
+
+impl SubmitMode {
+    pub const ALL: [Self; 3] =
+        [Self::DryRun, Self::BroadcastOnly, Self::Inclusion];
+
+    /// Set the submission flags of the tx args for this mode. Inclusion is
+    /// broadcasted only too, as we watch for the commit ourselves in
+    /// `wait_for_commit`.
+    pub fn apply(&self, tx: &mut args::Tx) {
+        tx.dry_run = matches!(self, Self::DryRun);
+        tx.broadcast_only =
+            matches!(self, Self::BroadcastOnly | Self::Inclusion);
+    }
+}
+
+impl fmt::Display for SubmitMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::DryRun => write!(f, "dry_run"),
+            Self::BroadcastOnly => write!(f, "broadcast_only"),
+            Self::Inclusion => write!(f, "inclusion"),
+        }
+    }
+}
+
+/// The `applied` event of a committed tx
+#[derive(Debug, Clone)]
+pub struct Committed {
+    pub hash: String,
+    pub height: u64,
+    pub code: String,
+    /// Gas the node charged for the tx
+    pub gas_used: u64,
+    /// Addresses of the accounts the tx initialized
+    pub initialized_accounts: Vec<String>,
+    /// Time from broadcast until the node reported the tx as applied
+    pub time_to_commit: Duration,
+}
+
+/// Get the height of the last committed block
+pub async fn latest_height(client: &HttpClient) -> u64 {
+    client
+        .latest_block()
+        .await
+        .expect("Unable to query the latest block")
+        .block
+        .header
+        .height
+        .value()
+}
+
+/// Get the ID of the chain the node runs
+pub async fn chain_id(client: &HttpClient) -> ChainId {
+    let status = client.status().await.expect("Unable to query the node");
+    ChainId::from_str(status.node_info.network.as_str())
+        .expect("Invalid chain ID")
+}
+
+/// Watch the node's block events after `from_height` until the tx with the
+/// given `hash` is reported as applied. The `applied` event of a wrapper tx
+/// carries the hash of the inner tx, see [`applied_hash`].
+pub async fn wait_for_commit(
+    client: &HttpClient,
+    from_height: u64,
+    hash: &str,
+    broadcasted: Instant,
+) -> Option<Committed> {
+    let (height, attrs) = wait_for_event(
+        client,
+        from_height,
+        broadcasted + COMMIT_TIMEOUT,
+        |event_type, attrs| {
+            event_type == "applied"
+                && attrs
+                    .get("hash")
+                    .map(|applied| applied.eq_ignore_ascii_case(hash))
+                    .unwrap_or(false)
+        },
+    )
+    .await?;
+    Some(committed(height, &attrs, broadcasted))
+}
+
+/// Like [`wait_for_commit`] for several txs broadcasted after
+/// `from_height`, given by their hashes and when they were broadcasted.
+/// Every block is only watched once, so that each tx's time to commit ends
+/// when its own block is seen. The commits are in the order of the txs.
+pub async fn wait_for_commits(
+    client: &HttpClient,
+    from_height: u64,
+    txs: &[(String, Instant)],
+) -> Vec<Option<Committed>> {
+    let mut commits = vec![None; txs.len()];
+    let last_broadcast = match txs.iter().map(|(_, at)| *at).max() {
+        Some(last_broadcast) => last_broadcast,
+        None => return commits,
+    };
+    let mut pending = txs.len();
+    watch_events(
+        client,
+        from_height,
+        last_broadcast + COMMIT_TIMEOUT,
+        |height, event_type, attrs| {
+            let applied = match attrs.get("hash") {
+                Some(applied) if event_type == "applied" => applied,
+                _ => return false,
+            };
+            let tx = txs
+                .iter()
+                .position(|(hash, _)| applied.eq_ignore_ascii_case(hash));
+            if let Some(tx) = tx {
+                if commits[tx].is_none() {
+                    commits[tx] = Some(committed(height, attrs, txs[tx].1));
+                    pending -= 1;
+                }
+            }
+            pending == 0
+        },
+    )
+    .await;
+    commits
+}
+
+/// The commit of a tx broadcasted at `broadcasted` from the attributes of
+/// its `applied` event in the block at `height`
+fn committed(
+    height: u64,
+    attrs: &BTreeMap<String, String>,
+    broadcasted: Instant,
+) -> Committed {
+    let attr = |key: &str| attrs.get(key).cloned().unwrap_or_default();
+    Committed {
+        hash: attr("hash"),
+        height,
+        code: attr("code"),
+        gas_used: attr("gas_used").parse().unwrap_or_default(),
+        initialized_accounts: serde_json::from_str(&attr(
+            "initialized_accounts",
+        ))
+        .unwrap_or_default(),
+        time_to_commit: broadcasted.elapsed(),
+    }
+}
+
+/// Watch the node's end block events after `from_height` until one matches
+/// its type and attributes, giving up at `deadline`. Returns the height of
+/// the block with the event and the event's attributes.
+pub async fn wait_for_event(
+    client: &HttpClient,
+    from_height: u64,
+    deadline: Instant,
+    matches: impl Fn(&str, &BTreeMap<String, String>) -> bool,
+) -> Option<(u64, BTreeMap<String, String>)> {
+    let mut found = None;
+    watch_events(
+        client,
+        from_height,
+        deadline,
+        |height, event_type, attrs| {
+            let is_match = matches(event_type, attrs);
+            if is_match {
+                found = Some((height, attrs.clone()));
+            }
+            is_match
+        },
+    )
+    .await;
+    found
+}
+
+/// Pass the node's end block events after `from_height` to `on_event`,
+/// with the height of their block, until it returns `true` or `deadline`
+/// has passed
+async fn watch_events(
+    client: &HttpClient,
+    from_height: u64,
+    deadline: Instant,
+    mut on_event: impl FnMut(u64, &str, &BTreeMap<String, String>) -> bool,
+) {
+    let mut height = from_height + 1;
+    while Instant::now() < deadline {
+        if latest_height(client).await < height {
+            tokio::time::sleep(POLL_INTERVAL).await;
+            continue;
+        }
+        let results = client
+            .block_results(height.try_into().unwrap())
+            .await
+            .expect("Unable to query block results");
+        for event in results.end_block_events.unwrap_or_default() {
+            let attrs = event
+                .attributes
+                .iter()
+                .map(|tag| (tag.key.to_string(), tag.value.to_string()))
+                .collect();
+            if on_event(height, &event.type_str, &attrs) {
+                return;
+            }
+        }
+        height += 1;
+    }
+}
+
+/// Wrap an inner tx into a fee-less wrapper tx signed by `key`, encoded and
+/// ready to be broadcasted
+pub fn wrap(
+    tx: Tx,
+    key: &SecretKey,
+    chain_id: &ChainId,
+    epoch: Epoch,
+    fee_token: &Address,
+) -> Vec<u8> {
+    let fee = Fee {
+        amount: 0.into(),
+        token: fee_token.clone(),
+    };
+    wrap_with_fee(tx, key, chain_id, epoch, fee, 0.into())
+}
+
+/// Like `wrap`, paying `fee` with the given gas limit
+pub fn wrap_with_fee(
+    tx: Tx,
+    key: &SecretKey,
+    chain_id: &ChainId,
+    epoch: Epoch,
+    fee: Fee,
+    gas_limit: GasLimit,
+) -> Vec<u8> {
+    let wrapper = WrapperTx::new(
+        fee,
+        key,
+        epoch,
+        gas_limit,
+        tx,
+        EncryptionKey::default(),
+        None,
+    );
+    wrapper
+        .sign(key, chain_id.clone(), None)
+        .expect("Unable to sign the wrapper tx")
+        .to_bytes()
+}
+
+/// The hash that the `applied` event of an encoded wrapper tx is reported
+/// with, which is the hash of the inner tx it wraps
+pub fn applied_hash(tx: &[u8]) -> String {
+    let tx = Tx::try_from(tx).expect("Unable to decode the tx");
+    match process_tx(tx).expect("Unable to decode the wrapper tx") {
+        TxType::Wrapper(wrapper) => wrapper.tx_hash.to_string(),
+        _ => panic!("Only wrapper txs can be broadcasted"),
+    }
+}
+
+/// Broadcast an encoded wrapper tx and wait for it to be committed. A tx
+/// the mempool rejects is never committed.
+pub async fn broadcast_and_wait(
+    client: &HttpClient,
+    tx: Vec<u8>,
+) -> Option<Committed> {
+    let hash = applied_hash(&tx);
+    let height = latest_height(client).await;
+    let broadcasted = Instant::now();
+    let response = client
+        .broadcast_tx_sync(tx.into())
+        .await
+        .expect("Unable to broadcast the tx");
+    if response.code.is_ok() {
+        wait_for_commit(client, height, &hash, broadcasted).await
+    } else {
+        None
+    }
+}
+
+/// Collects the time-to-commit of every iteration of an inclusion
+/// benchmark
+#[derive(Debug, Clone, Default)]
+pub struct CommitSamples(Arc<Mutex<Vec<Option<Committed>>>>);
+
+impl CommitSamples {
+    pub fn push(&self, committed: Option<Committed>) {
+        self.0.lock().unwrap().push(committed);
+    }
+
+    /// Print a summary line for the benchmark `id` and record the txs that
+    /// timed out or were rejected as its errors
+    pub fn report(&self, id: &str) {
+        let samples = self.0.lock().unwrap();
+        if samples.is_empty() {
+            return;
+        }
+        let mut times: Vec<Duration> = samples
+            .iter()
+            .flatten()
+            .map(|committed| committed.time_to_commit)
+            .collect();
+        times.sort();
+        let timeouts = samples.len() - times.len();
+        let rejected = samples
+            .iter()
+            .flatten()
+            .filter(|committed| committed.code != "0")
+            .count();
+        results::record_errors(id, timeouts + rejected);
+        match times.get(times.len() / 2) {
+            Some(median) => println!(
+                "{id:<40} commit: median {median:?}, max {:?}, {timeouts} \
+                 timed out",
+                times.last().unwrap(),
+            ),
+            None => println!("{id:<40} commit: all {timeouts} timed out"),
+        }
+    }
+}
@@ -0,0 +1,427 @@
+//! Fee and gas-limit parameter sweeps. Every tx kind in [`SweptTx::ALL`] is
+//! submitted with each combination of fee amount, fee token and gas limit,
+//! to find where txs start failing for lack of gas and what paying a fee
+//! costs. The fees are always paid by the faucet's key, which signs the
+//! wrappers.
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use borsh::BorshSerialize;
+use criterion::{BenchmarkId, Criterion};
+use namada::ledger::args;
+use namada::types::address::Address;
+use namada::types::token;
+use namada::types::transaction::governance::{
+    InitProposalData, ProposalType,
+};
+use namada::types::transaction::{pos, Fee, GasLimit};
+use tokio::runtime::Runtime;
+
+use crate::chain::Chain;
+use crate::governance;
+use crate::ibc;
+use crate::metrics;
+use crate::results;
+use crate::submit::{self, SubmitMode};
+use crate::tx::{
+    self, build_transfer, transfer_setup, TransferKind, NATIVE_TOKEN,
+};
+
+/// Fee amounts to sweep, in the fee token's base unit
+const FEE_AMOUNTS: [u64; 3] = [0, 10, 1_000];
+/// Gas limits to sweep
+const GAS_LIMITS: [u64; 6] = [0, 100, 1_000, 10_000, 100_000, 1_000_000];
+/// Tokens to pay fees in, by wallet alias
+const FEE_TOKENS: [(&str, &str); 2] = [
+    ("nam", NATIVE_TOKEN),
+    (
+        "btc",
+        "atest1v4ehgw36xdzryve5gsc52veeg5cnsv2yx5eygvp38qcrvd29xy6rys6p8yc5xvp4xfpy2v694wgwcp",
+    ),
+];
+/// How much every swept bond is of, and every swept unbond unbonds
+const BOND: u64 = 10;
+
+/// The txs the sweep submits
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SweptTx {
+    /// A transfer from the faucet
+    Transfer(TransferKind),
+    /// A delegation to the genesis validator
+    Bond,
+    /// Unbonding part of a delegation to the genesis validator
+    Unbond,
+    /// A governance proposal by the faucet
+    InitProposal,
+    /// Creating a client of a mock IBC counterparty
+    IbcCreateClient,
+}
+
+impl SweptTx {
+    pub const ALL: [Self; 6] = [
+        Self::Transfer(TransferKind::Transparent),
+        Self::Transfer(TransferKind::Shielding),
+        Self::Bond,
+        Self::Unbond,
+        Self::InitProposal,
+        Self::IbcCreateClient,
+    ];
+
+    /// The wasm the tx runs
+    pub fn wasm(&self) -> &'static str {
+        match self {
+            Self::Transfer(_) => "tx_transfer",
+            Self::Bond => "tx_bond",
+            Self::Unbond => "tx_unbond",
+            Self::InitProposal => "tx_init_proposal",
+            Self::IbcCreateClient => "tx_ibc",
+        }
+    }
+}
+
+impl fmt::Display for SweptTx {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Transfer(kind) => write!(f, "{kind}"),
+            Self::Bond => write!(f, "bond"),
+            Self::Unbond => write!(f, "unbond"),
+            Self::InitProposal => write!(f, "init_proposal"),
+            Self::IbcCreateClient => write!(f, "ibc_create_client"),
+        }
+    }
+}
+
+/// One point of the sweep
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct FeeCase {
+    pub fee_token: &'static str,
+    pub fee_amount: u64,
+    pub gas_limit: u64,
+}
+
+impl FeeCase {
+    /// Every combination of the swept parameters. A zero fee is the same in
+    /// every token, so it's only included once.
+    pub fn all() -> Vec<Self> {
+        let mut cases = vec![];
+        for (fee_token, _) in FEE_TOKENS {
+            for fee_amount in FEE_AMOUNTS {
+                if fee_amount == 0 && fee_token != FEE_TOKENS[0].0 {
+                    continue;
+                }
+                for gas_limit in GAS_LIMITS {
+                    cases.push(Self {
+                        fee_token,
+                        fee_amount,
+                        gas_limit,
+                    });
+                }
+            }
+        }
+        cases
+    }
+
+    /// The fee of this case
+    pub fn fee(&self) -> Fee {
+        let (_, token) = FEE_TOKENS
+            .iter()
+            .find(|(alias, _)| *alias == self.fee_token)
+            .expect("Fee token must be one of FEE_TOKENS");
+        Fee {
+            amount: self.fee_amount.into(),
+            token: Address::from_str(token)
+                .expect("Unable to construct fee token"),
+        }
+    }
+
+    /// Set the fee and gas limit of the tx args to this case
+    pub fn apply(&self, tx: &mut args::Tx) {
+        let fee = self.fee();
+        tx.fee_token = fee.token;
+        tx.fee_amount = fee.amount;
+        tx.gas_limit = self.gas_limit.into();
+    }
+}
+
+impl fmt::Display for FeeCase {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "fee={}{}/gas={}",
+            self.fee_amount, self.fee_token, self.gas_limit
+        )
+    }
+}
+
+/// Whether a submitted tx made it into a block successfully and how long
+/// that took
+#[derive(Debug, Clone, Copy)]
+struct Outcome {
+    accepted: bool,
+    elapsed: Duration,
+}
+
+/// Outcomes of every iteration, by tx kind and sweep case
+#[derive(Debug, Clone, Default)]
+struct Outcomes(Arc<Mutex<BTreeMap<(String, FeeCase), Vec<Outcome>>>>);
+
+impl Outcomes {
+    fn push(&self, tx: SweptTx, case: FeeCase, outcome: Outcome) {
+        self.0
+            .lock()
+            .unwrap()
+            .entry((tx.to_string(), case))
+            .or_default()
+            .push(outcome);
+    }
+
+    /// For every tx kind and fee setting, print the lowest gas limit at
+    /// which all txs were accepted and the time paying the fee added over
+    /// the fee-less case with the same gas limit
+    fn report(&self) {
+        let outcomes = self.0.lock().unwrap();
+        let median = |outcomes: &[Outcome]| {
+            let mut times: Vec<Duration> =
+                outcomes.iter().map(|outcome| outcome.elapsed).collect();
+            times.sort();
+            times[times.len() / 2]
+        };
+        let mut settings: Vec<(&String, &'static str, u64)> = outcomes
+            .keys()
+            .map(|(kind, case)| (kind, case.fee_token, case.fee_amount))
+            .collect();
+        settings.dedup();
+
+        for (kind, fee_token, fee_amount) in settings {
+            let cases = outcomes.iter().filter(|((k, case), _)| {
+                k == kind
+                    && case.fee_token == fee_token
+                    && case.fee_amount == fee_amount
+            });
+            let mut min_gas = None;
+            let mut overheads = vec![];
+            for ((_, case), samples) in cases {
+                let failed =
+                    samples.iter().filter(|outcome| !outcome.accepted).count();
+                let id = format!("fee_sweep/{kind}/{case}");
+                results::record_errors(&id, failed);
+                let accepted = failed == 0;
+                if accepted && min_gas.is_none() {
+                    min_gas = Some(case.gas_limit);
+                }
+                if fee_amount == 0 {
+                    continue;
+                }
+                let baseline = FeeCase {
+                    fee_token: FEE_TOKENS[0].0,
+                    fee_amount: 0,
+                    gas_limit: case.gas_limit,
+                };
+                if let Some(baseline) = outcomes.get(&(kind.clone(), baseline))
+                {
+                    let overhead = median(samples).as_secs_f64()
+                        - median(baseline).as_secs_f64();
+                    overheads.push(overhead);
+                }
+            }
+            let min_gas = min_gas
+                .map(|gas| gas.to_string())
+                .unwrap_or_else(|| "none".to_string());
+            let overhead = if overheads.is_empty() {
+                "-".to_string()
+            } else {
+                let mean =
+                    overheads.iter().sum::<f64>() / overheads.len() as f64;
+                format!("{:+.1}ms", mean * 1000.0)
+            };
+            println!(
+                "fee_sweep/{kind} fee={fee_amount}{fee_token}: accepted from \
+                 gas limit {min_gas}, fee overhead {overhead}"
+            );
+        }
+    }
+}
+
+/// Submit `iters` txs of a kind that isn't a transfer with the fee `case`,
+/// recording their outcomes. The accounts and bonds they need are set up
+/// untimed. Returns the time the txs took to be committed in total.
+async fn submit_chain_txs(
+    chain: &mut Chain,
+    tx: SweptTx,
+    case: &FeeCase,
+    iters: u64,
+    outcomes: &Outcomes,
+) -> Duration {
+    let validator = tx::validator();
+    let bond = token::Amount::whole(BOND);
+    // Bonds and unbonds are made by a delegator of their own, with enough
+    // funds for every iteration
+    let delegator = match tx {
+        SweptTx::Bond => Some(
+            chain
+                .new_account(token::Amount::whole(2 * BOND * iters))
+                .await,
+        ),
+        SweptTx::Unbond => {
+            let delegator = chain
+                .new_account(token::Amount::whole(2 * BOND * iters))
+                .await;
+            chain
+                .bond(
+                    &delegator,
+                    &validator,
+                    token::Amount::whole(BOND * iters),
+                )
+                .await;
+            Some(delegator)
+        }
+        _ => None,
+    };
+
+    let mut total = Duration::ZERO;
+    for _ in 0..iters {
+        let data = match tx {
+            SweptTx::Transfer(_) => {
+                unreachable!("Transfers are built by the SDK")
+            }
+            SweptTx::Bond => pos::Bond {
+                validator: validator.clone(),
+                amount: bond,
+                source: delegator.as_ref().map(|d| d.address.clone()),
+            }
+            .try_to_vec()
+            .unwrap(),
+            SweptTx::Unbond => pos::Unbond {
+                validator: validator.clone(),
+                amount: bond,
+                source: delegator.as_ref().map(|d| d.address.clone()),
+            }
+            .try_to_vec()
+            .unwrap(),
+            SweptTx::InitProposal => {
+                let (start, end, grace) =
+                    governance::proposal_epochs(chain).await;
+                InitProposalData {
+                    id: None,
+                    content: b"tx-bench fee sweep proposal".to_vec(),
+                    author: chain.faucet.address.clone(),
+                    r#type: ProposalType::Default(None),
+                    voting_start_epoch: start,
+                    voting_end_epoch: end,
+                    grace_epoch: grace,
+                }
+                .try_to_vec()
+                .unwrap()
+            }
+            SweptTx::IbcCreateClient => ibc::create_client_data(chain),
+        };
+        let signer = delegator
+            .as_ref()
+            .map(|d| d.key.clone())
+            .unwrap_or_else(|| chain.faucet.key.clone());
+        let start = Instant::now();
+        let committed = chain
+            .submit_with_fee(
+                tx.wasm(),
+                data,
+                Some(&signer),
+                case.fee(),
+                GasLimit::from(case.gas_limit),
+            )
+            .await;
+        let elapsed = start.elapsed();
+        total += elapsed;
+        let accepted = committed
+            .map(|committed| committed.code == "0")
+            .unwrap_or(false);
+        outcomes.push(tx, case.clone(), Outcome { accepted, elapsed });
+    }
+    total
+}
+
+/// Sweep fees and gas limits over every tx kind. Txs are waited on until
+/// they're committed, as running out of gas only shows up in the result of
+/// the applied tx.
+pub fn fee_sweep(c: &mut Criterion) {
+    let mut group = c.benchmark_group("fee_sweep");
+    group.sample_size(10);
+    let outcomes = Outcomes::default();
+    let runtime = Runtime::new().unwrap();
+    let mut chain = runtime.block_on(Chain::connect());
+
+    for tx in SweptTx::ALL {
+        for case in FeeCase::all() {
+            let id = BenchmarkId::new(tx.to_string(), &case);
+            results::record_wasm(&format!("fee_sweep/{tx}/{case}"), tx.wasm());
+            let outcomes = outcomes.clone();
+            let node_metrics = metrics::Snapshot::take();
+            match tx {
+                SweptTx::Transfer(kind) => {
+                    group.bench_with_input(id, &case, move |b, case| {
+                        b.to_async(Runtime::new().unwrap()).iter_batched(
+                            || {
+                                let mut setup =
+                                    transfer_setup(kind, SubmitMode::Inclusion);
+                                case.apply(&mut setup.1.tx);
+                                setup
+                            },
+                            |(mut shielded_ctx, transfer_tx, client, _wallet)| {
+                                let outcomes = outcomes.clone();
+                                let case = case.clone();
+                                async move {
+                                    let start = Instant::now();
+                                    let res = build_transfer(
+                                        &mut shielded_ctx,
+                                        &client,
+                                        &transfer_tx,
+                                    )
+                                    .await;
+                                    let committed = match res {
+                                        Ok(built) => {
+                                            submit::broadcast_and_wait(
+                                                &client, built,
+                                            )
+                                            .await
+                                        }
+                                        Err(_) => None,
+                                    };
+                                    let accepted = committed
+                                        .map(|committed| committed.code == "0")
+                                        .unwrap_or(false);
+                                    outcomes.push(
+                                        tx,
+                                        case,
+                                        Outcome {
+                                            accepted,
+                                            elapsed: start.elapsed(),
+                                        },
+                                    );
+                                }
+                            },
+                            criterion::BatchSize::LargeInput,
+                        )
+                    });
+                }
+                _ => {
+                    group.bench_with_input(id, &case, |b, case| {
+                        b.iter_custom(|iters| {
+                            runtime.block_on(submit_chain_txs(
+                                &mut chain, tx, case, iters, &outcomes,
+                            ))
+                        })
+                    });
+                }
+            }
+            if let Some(node_metrics) = node_metrics {
+                node_metrics.report(&format!("fee_sweep/{tx}/{case}"));
+            }
+        }
+    }
+
+    group.finish();
+    outcomes.report();
+}
@@ -1,15 +1,19 @@
 use std::str::FromStr;
 
-use criterion::Criterion;
+use criterion::{BenchmarkId, Criterion};
 // use namada::core::types::token::Amount;
 
 use namada::ledger::{tx, masp};
 use masp_primitives::transaction::builder::TransactionMetadata;
 use masp_primitives::transaction::Transaction;
+use namada::ledger::rpc;
 use namada::ledger::wallet::Store;
 use namada::ledger::wallet::Wallet;
-use namada::types::address::Address;
+use namada::proto::Tx;
+use namada::types::address::{self, Address};
 use namada::types::masp::{TransferSource, TransferTarget};
+use namada::types::token;
+use namada::types::transaction::Fee;
 use std::path::Path;
 
 use namada::types::key::common::SecretKey;
@@ -27,15 +31,49 @@ use namada::ledger::masp::find_valid_diversifier;
 use namada::ledger::wallet::SdkWalletUtils;
 use rand::rngs::OsRng;
 use std::env;
+use std::fmt;
 use std::fs::File;
 use std::fs::OpenOptions;
 use std::io::Read;
 use std::io::Write;
 use std::path::PathBuf;
-use tokio::runtime::Runtime;
+use std::sync::{Arc, Mutex};
 
 use wasm_bindgen::prelude::*;
 
+use crate::alloc;
+use crate::keys::{self, KeyStorage, Keyring};
+use crate::metrics;
+use crate::results;
+use crate::submit::{self, CommitSamples, SubmitMode};
+use crate::wasm;
+
+/// Address of the native token of the e2e test chain
+pub const NATIVE_TOKEN: &str = "atest1v4ehgw36x3prswzxggunzv6pxqmnvdj9xvcyzvpsggeyvs3cg9qnywf589qnwvfsg5erg3fkl09rg5";
+/// Address of the e2e test chain's faucet, if the wallet doesn't have one
+const FAUCET: &str = "atest1v4ehgw36gyerxv6xgyunqv3egsmnv3pj8quny3fc8prrs32rg4qnxv2ygser2djxgcmnzv2y3dnxyq";
+/// Key to withdraw funds from the e2e test chain's faucet, if the wallet
+/// doesn't have one
+const FAUCET_KEY: &str = "0079b5f7bf9a7634c3ab1f7853bc196283e4190422aa21085a0dbc548e554e0da2";
+/// Address of the e2e test chain's genesis validator, if the wallet doesn't
+/// have one
+const VALIDATOR: &str = "atest1v4ehgw36xepngd2rxsmrjwfh8qmyxv3jgsu5ydjpxgmr2d3s8q6ngvfkxgerwsf4g5mrxd3j2pc5u5";
+/// Alias of the faucet in the wallets `init-network` generates
+const FAUCET_ALIAS: &str = "faucet";
+/// Alias of the faucet's key in the wallets `init-network` generates
+const FAUCET_KEY_ALIAS: &str = "faucet-key";
+/// Alias of the first genesis validator in the wallets `init-network`
+/// generates
+const VALIDATOR_ALIAS: &str = "validator-0";
+/// RPC address of the local ledger
+pub const LEDGER_ADDRESS: &str = "127.0.0.1:27657";
+/// Environment variable overriding `LEDGER_ADDRESS`, set by `devnet`
+pub const ENV_VAR_LEDGER_ADDRESS: &str = "TX_BENCH_LEDGER_ADDRESS";
+/// Environment variable with the path of a `wallet.toml` to start from, e.g.
+/// one written by `fixtures`. If unset, the benchmarks start from an empty
+/// wallet.
+pub const ENV_VAR_WALLET: &str = "TX_BENCH_WALLET";
+
 /// Shielded context file name
 const FILE_NAME: &str = "shielded.dat";
 const TMP_FILE_NAME: &str = "shielded.tmp";
@@ -129,17 +167,23 @@ impl masp::ShieldedUtils for FuzzerShieldedUtils {
         }
         // Atomically update the old shielded context file with new data.
         // Atomicity is required to prevent other client instances from reading
-        // corrupt data.
-        std::fs::rename(tmp_path.clone(), self.context_dir.join(FILE_NAME))?;
-        // Finally, remove our temporary file to allow future saving of shielded
-        // contexts.
-        std::fs::remove_file(tmp_path)?;
+        // corrupt data. The rename also moves the temporary file out of the
+        // way of future saves, so there's nothing left to remove on success.
+        if let Err(err) =
+            std::fs::rename(tmp_path.clone(), self.context_dir.join(FILE_NAME))
+        {
+            // Remove our temporary file to allow future saving of shielded
+            // contexts.
+            let _ = std::fs::remove_file(tmp_path);
+            return Err(err);
+        }
         Ok(())
     }
 }
 
 
-async fn shielded(
+/// Build the MASP part of a transfer
+pub async fn shielded(
     ctx: &mut masp::ShieldedContext<FuzzerShieldedUtils>,
     client: &HttpClient,
     args: args::TxTransfer,
@@ -147,90 +191,344 @@ async fn shielded(
     ctx.gen_shielded_transfer(client, args, true).await
 }
 
+/// Build the signed transfer of the transfer args with its MASP part, if
+/// any, wrapped with the args' fee and gas limit and ready to be
+/// broadcasted. Unlike the SDK's `submit_transfer`, this leaves the
+/// broadcast to the caller, who then knows the hash of the tx.
+pub async fn build_transfer(
+    ctx: &mut masp::ShieldedContext<FuzzerShieldedUtils>,
+    client: &HttpClient,
+    args: &args::TxTransfer,
+) -> Result<Vec<u8>, builder::Error> {
+    let masp_tx = shielded(ctx, client, args.clone())
+        .await?
+        .map(|(tx, _metadata)| tx);
+    let source = match &args.source {
+        TransferSource::Address(source) => source.clone(),
+        TransferSource::ExtendedSpendingKey(_) => address::masp(),
+    };
+    let target = match &args.target {
+        TransferTarget::Address(target) => target.clone(),
+        TransferTarget::PaymentAddress(_) => address::masp(),
+    };
+    let transfer = token::Transfer {
+        source,
+        target,
+        token: args.token.clone(),
+        sub_prefix: args.sub_prefix.clone(),
+        amount: args.amount,
+        key: None,
+        shielded: masp_tx,
+    };
+    let key = args
+        .tx
+        .signing_key
+        .as_ref()
+        .expect("Transfers are benchmarked with a signing key");
+    let chain_id = submit::chain_id(client).await;
+    let tx = Tx::new(
+        args.tx_code_path.clone(),
+        Some(transfer.try_to_vec().unwrap()),
+        chain_id.clone(),
+        None,
+    )
+    .sign(key);
+    let fee = Fee {
+        amount: args.tx.fee_amount,
+        token: args.tx.fee_token.clone(),
+    };
+    let epoch = rpc::query_epoch(client).await;
+    Ok(submit::wrap_with_fee(
+        tx,
+        key,
+        &chain_id,
+        epoch,
+        fee,
+        args.tx.gas_limit.clone(),
+    ))
+}
+
+/// The RPC address of the ledger to benchmark against
+pub fn ledger_address() -> TendermintAddress {
+    let addr = env::var(ENV_VAR_LEDGER_ADDRESS)
+        .unwrap_or_else(|_| LEDGER_ADDRESS.to_string());
+    TendermintAddress::from_str(&addr).expect("Unable to connect to RPC")
+}
+
+/// The wallet to benchmark with, loaded from `ENV_VAR_WALLET` if set
+pub fn load_wallet() -> Wallet<SdkWalletUtils<PathBuf>> {
+    match env::var(ENV_VAR_WALLET) {
+        Ok(path) => {
+            let path = PathBuf::from(path);
+            let store = std::fs::read(&path)
+                .map_err(|err| err.to_string())
+                .and_then(|bytes| {
+                    Store::decode(bytes).map_err(|err| err.to_string())
+                })
+                .unwrap_or_else(|err| {
+                    panic!("Unable to load {}: {err}", path.display())
+                });
+            let dir = path.parent().unwrap_or(Path::new("./")).to_path_buf();
+            Wallet::new(dir, store)
+        }
+        Err(_) => Wallet::new(
+            Path::new("wallet.toml").to_path_buf(),
+            Store::default(),
+        ),
+    }
+}
+
+/// The faucet's address and key. They are read from the wallet in
+/// `ENV_VAR_WALLET`, e.g. the one generated for a devnet, falling back to
+/// the e2e test chain's faucet.
+pub fn faucet() -> (Address, SecretKey) {
+    let wallet = load_wallet();
+    let address = match wallet.store().find_address(FAUCET_ALIAS) {
+        Some(address) => address.clone(),
+        None => Address::from_str(FAUCET).expect("Invalid faucet address"),
+    };
+    let key = match wallet.store().find_key(FAUCET_KEY_ALIAS) {
+        Some(stored) => stored
+            .get(true, None)
+            .expect("The faucet key must be unencrypted"),
+        None => SecretKey::from_str(FAUCET_KEY).expect("Invalid secret key"),
+    };
+    (address, key)
+}
+
+/// The address of the genesis validator the benchmarks bond to. Like the
+/// faucet, it's read from the wallet in `ENV_VAR_WALLET`, falling back to
+/// the e2e test chain's validator.
+pub fn validator() -> Address {
+    match load_wallet().store().find_address(VALIDATOR_ALIAS) {
+        Some(address) => address.clone(),
+        None => Address::from_str(VALIDATOR).expect("Invalid validator address"),
+    }
+}
+
+/// The transfers we know how to benchmark
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TransferKind {
+    /// From the faucet to albert
+    Transparent,
+    /// From the faucet to a freshly generated payment address
+    Shielding,
+}
+
+impl TransferKind {
+    pub const ALL: [Self; 2] = [Self::Transparent, Self::Shielding];
+}
+
+impl fmt::Display for TransferKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Transparent => write!(f, "transparent"),
+            Self::Shielding => write!(f, "shielded"),
+        }
+    }
+}
+
+/// Build everything a transfer from the faucet needs
+pub fn transfer_setup(
+    kind: TransferKind,
+    mode: SubmitMode,
+) -> (
+    masp::ShieldedContext<FuzzerShieldedUtils>,
+    args::TxTransfer,
+    HttpClient,
+    Wallet<SdkWalletUtils<PathBuf>>,
+) {
+    let wasm_dir = wasm::wasm_dir();
+    let tx_transfer_bytes = wasm::read(&wasm_dir, "tx_transfer");
+    let tx_reveal_pk_bytes = wasm::read(&wasm_dir, "tx_reveal_pk");
+    let mut wallet = load_wallet();
+
+    // Generate a spending key
+    let (alias, _spending_key) = wallet.gen_spending_key("joe".to_string(), None);
+    let viewing_key = wallet.find_viewing_key(alias.clone()).expect("A viewing key");
+    let (div, _g_d) = find_valid_diversifier(&mut OsRng);
+
+
+    let payment_addr = ExtendedFullViewingKey::from(*viewing_key).fvk.vk.to_payment_address(div)
+        .expect("a PaymentAddress");
+    let native_token = Address::from_str(NATIVE_TOKEN)
+        .expect("Unable to construct native token");
+    let (faucet_addr, faucet_key) = faucet();
+    // Recipient of transparent transfers
+    let albert_addr = Address::from_str("atest1v4ehgw36gfpy2vp4gfryywfsxym5gs338y65gse4xgeyydjzxepnqdzrxdqnqwfhxcmnzw2p762kje")
+        .expect("Unable to construct target");
+
+    let target = match kind {
+        TransferKind::Transparent => TransferTarget::Address(albert_addr),
+        TransferKind::Shielding => TransferTarget::PaymentAddress(payment_addr.clone().into()),
+    };
+
+    // Construct out transaction
+    let mut transfer_tx: args::TxTransfer = args::TxTransfer {
+        amount: 23000000.into(),
+        native_token: native_token.clone(),
+        source: TransferSource::Address(faucet_addr.clone()),
+        target,
+        token: native_token.clone(),
+        sub_prefix: None,
+        tx_code_path: tx_transfer_bytes,
+        tx: args::Tx {
+            broadcast_only: false,
+            dry_run: false,
+            fee_amount: 0.into(),
+            fee_token: native_token,
+            force: false,
+            gas_limit: 0.into(),
+            initialized_account_alias: None,
+            ledger_address: (),
+            password: None,
+            signer: None,
+            signing_key: Some(faucet_key),
+            tx_code_path: tx_reveal_pk_bytes,
+        },
+    };
+    mode.apply(&mut transfer_tx.tx);
+
+    let shielded_ctx = FuzzerShieldedUtils::new(Path::new("./").to_path_buf());
+    // let mut shielded_ctx = masp::ShieldedContext::default();
+
+    let client = HttpClient::new(ledger_address()).unwrap();
+    // what we want
+    // shielded_ctx.gen_shielded_transfer(client, args, transfer_tx);
+    (shielded_ctx, transfer_tx, client, wallet)
+}
+
 pub fn transfer(c: &mut Criterion) {
     let mut group = c.benchmark_group("transfer");
     group.sample_size(10);
     // let amount = Amount::whole(500);
 
-    group.bench_function("shielded", move |b| {
-            b.to_async(Runtime::new().unwrap()).iter_batched(
-                || {
-                    let mut tx_transfer_wasm = File::open("../wasm/tx_transfer.2bceb190b553ea34a653d59f235be5df657c1d900f24de2dada58dff19d53b3c.wasm").unwrap();
-                    let mut tx_reveal_pk_wasm = File::open("../wasm/tx_reveal_pk.d5f92e24ee566e5ecbb0def6bade4c942dd3dc5c7258b460fb8edc4cc641ebcf.wasm").unwrap();
-
-                    let mut tx_transfer_bytes = vec![];
-                    tx_transfer_wasm.read_to_end(&mut tx_transfer_bytes).unwrap();
-
-                    let mut tx_reveal_pk_bytes = vec![];
-                    tx_reveal_pk_wasm.read_to_end(&mut tx_reveal_pk_bytes).unwrap();
-
-                    let mut wallet : Wallet<SdkWalletUtils<PathBuf>> = Wallet::new(
-                        Path::new("wallet.toml").to_path_buf(),
-                        Store::default(),
-                    );
-
-                    // Generate a spending key
-                    let (alias, _spending_key) = wallet.gen_spending_key("joe".to_string(), None);
-                    let viewing_key = wallet.find_viewing_key(alias.clone()).expect("A viewing key");
-                    let (div, _g_d) = find_valid_diversifier(&mut OsRng);
-
-
-                    let payment_addr = ExtendedFullViewingKey::from(*viewing_key).fvk.vk.to_payment_address(div)
-                        .expect("a PaymentAddress");
-                    let native_token = Address::from_str("atest1v4ehgw36x3prswzxggunzv6pxqmnvdj9xvcyzvpsggeyvs3cg9qnywf589qnwvfsg5erg3fkl09rg5")
-                        .expect("Unable to construct native token");
-                    // Address of the faucet
-                    let faucet_addr = Address::from_str("atest1v4ehgw36gyerxv6xgyunqv3egsmnv3pj8quny3fc8prrs32rg4qnxv2ygser2djxgcmnzv2y3dnxyq")
-                        .expect("Unable to construct source");
-                    // Key to withdraw funds from the faucet
-                    let faucet_key = SecretKey::from_str("0079b5f7bf9a7634c3ab1f7853bc196283e4190422aa21085a0dbc548e554e0da2").expect("Invalid secret key");
-
-                    // Construct out shielding transaction
-                    let transfer_tx: args::TxTransfer = args::TxTransfer {
-                        amount: 23000000.into(),
-                        native_token: native_token.clone(),
-                        source: TransferSource::Address(faucet_addr.clone()),
-                        target: TransferTarget::PaymentAddress(payment_addr.clone().into()),
-                        token: native_token.clone(),
-                        sub_prefix: None,
-                        tx_code_path: tx_transfer_bytes,
-                        tx: args::Tx {
-                            broadcast_only: false,
-                            dry_run: false,
-                            fee_amount: 0.into(),
-                            fee_token: native_token,
-                            force: false,
-                            gas_limit: 0.into(),
-                            initialized_account_alias: None,
-                            ledger_address: (),
-                            password: None,
-                            signer: None,
-                            signing_key: Some(faucet_key),
-                            tx_code_path: tx_reveal_pk_bytes,
-                        },
-                    };
-
-                    let shielded_ctx = FuzzerShieldedUtils::new(Path::new("./").to_path_buf());
-                    // let mut shielded_ctx = masp::ShieldedContext::default();
-
-                    let addr = TendermintAddress::from_str("127.0.0.1:27657")
-                        .expect("Unable to connect to RPC");
-                    let client = HttpClient::new(addr).unwrap();
-                    // what we want
-                    // shielded_ctx.gen_shielded_transfer(client, args, transfer_tx);
-                    (shielded_ctx, transfer_tx, client, wallet)
-                },
-                |(mut shielded_ctx, transfer_tx, client, mut wallet)| {
-                    async move {
-                        let _res = tx::submit_transfer::<HttpClient, SdkWalletUtils<PathBuf>,_>(&client, &mut wallet, &mut shielded_ctx, transfer_tx).await;
-                        // let _res = shielded(&mut shielded_ctx, &client, transfer_tx.clone()).await;
-                        // println!("Results: {:?}", res);
-                        ()
-                    }
-                },
-                criterion::BatchSize::LargeInput,
-            )
-        });
+    for storage in KeyStorage::ALL {
+        // The signing key is taken out of the keyring in every iteration,
+        // so that the decryption is part of the measurement
+        let mut keyring = Keyring::new(storage, keys::cache_configured());
+        keyring.insert(keys::KEY_ALIAS, faucet().1);
+        let label = keyring.label();
+        let keyring = Arc::new(Mutex::new(keyring));
+
+        for mode in SubmitMode::ALL {
+            let id = BenchmarkId::new("shielded", format!("{mode}/{label}"));
+            let heap = alloc::Samples::default();
+            let commits = CommitSamples::default();
+
+            let samples = heap.clone();
+            let commit_samples = commits.clone();
+            let keyring = keyring.clone();
+            let node_metrics = metrics::Snapshot::take();
+            let result_id = format!("transfer/shielded/{mode}/{label}");
+            let errors_id = result_id.clone();
+            group.bench_with_input(id, &mode, move |b, &mode| {
+                b.to_async(alloc::runtime()).iter_batched(
+                    || transfer_setup(TransferKind::Shielding, mode),
+                    |(mut shielded_ctx, mut transfer_tx, client, mut wallet)| {
+                        let samples = samples.clone();
+                        let commit_samples = commit_samples.clone();
+                        let keyring = keyring.clone();
+                        let errors_id = errors_id.clone();
+                        async move {
+                            let region = alloc::Region::start();
+                            let key = keyring.lock().unwrap().key(keys::KEY_ALIAS);
+                            transfer_tx.tx.signing_key = Some(key);
+                            let res = if mode == SubmitMode::Inclusion {
+                                // Build the proof first, so that the time to
+                                // commit is measured from the broadcast on
+                                let built = build_transfer(&mut shielded_ctx, &client, &transfer_tx).await;
+                                let committed = match &built {
+                                    Ok(tx) => submit::broadcast_and_wait(&client, tx.clone()).await,
+                                    Err(_) => None,
+                                };
+                                commit_samples.push(committed);
+                                built.map(|_| ()).map_err(|err| err.to_string())
+                            } else {
+                                tx::submit_transfer::<HttpClient, SdkWalletUtils<PathBuf>,_>(&client, &mut wallet, &mut shielded_ctx, transfer_tx).await
+                                    .map_err(|err| err.to_string())
+                            };
+                            samples.push(region.finish());
+                            // Inclusion failures are counted by the commit
+                            // samples, which also see rejected txs
+                            if res.is_err() && mode != SubmitMode::Inclusion {
+                                results::record_errors(&errors_id, 1);
+                            }
+                        }
+                    },
+                    criterion::BatchSize::LargeInput,
+                )
+            });
+
+            heap.report(&result_id);
+            commits.report(&result_id);
+            if let Some(node_metrics) = node_metrics {
+                node_metrics.report(&result_id);
+            }
+        }
+    }
 
     group.finish();
 }
+
+/// Measure persisting the shielded context, which serializes the whole
+/// context into memory before writing it out.
+pub fn shielded_context(c: &mut Criterion) {
+    let mut group = c.benchmark_group("shielded_context");
+    group.sample_size(10);
+    let heap = alloc::Samples::default();
+
+    // Reuse the context left behind by previous transfer runs, if any
+    let utils = FuzzerShieldedUtils::new(Path::new("./").to_path_buf()).utils;
+    let ctx = masp::ShieldedUtils::load(utils.clone()).unwrap_or_default();
+
+    let samples = heap.clone();
+    group.bench_function("save", move |b| {
+        b.iter_batched(
+            || {
+                let dir = tempfile::tempdir().unwrap();
+                let utils = FuzzerShieldedUtils {
+                    context_dir: dir.path().to_path_buf(),
+                };
+                (dir, utils)
+            },
+            |(_dir, utils)| {
+                let region = alloc::Region::start();
+                masp::ShieldedUtils::save(&utils, &ctx).unwrap();
+                samples.push(region.finish());
+            },
+            criterion::BatchSize::LargeInput,
+        )
+    });
+
+    group.finish();
+    heap.report("shielded_context/save");
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Test that a saved context can be saved over and loaded back.
+    #[test]
+    fn test_shielded_context_save_load_round_trip() {
+        let dir = tempfile::tempdir().unwrap();
+        let utils = FuzzerShieldedUtils {
+            context_dir: dir.path().to_path_buf(),
+        };
+        let ctx = masp::ShieldedContext {
+            utils: utils.clone(),
+            ..Default::default()
+        };
+
+        // Saving twice must succeed, the first save mustn't leave its
+        // temporary file behind
+        masp::ShieldedUtils::save(&utils, &ctx).unwrap();
+        masp::ShieldedUtils::save(&utils, &ctx).unwrap();
+        assert!(!dir.path().join(TMP_FILE_NAME).exists());
+
+        let loaded = masp::ShieldedUtils::load(utils).unwrap();
+        assert_eq!(loaded.utils.context_dir, dir.path());
+        assert_eq!(loaded.try_to_vec().unwrap(), ctx.try_to_vec().unwrap());
+    }
+}
@@ -0,0 +1,192 @@
+//! The cost of maintaining the validator sets as they grow. The chain is
+//! populated with 10, 100 and then 1000 validators, each with stake of its
+//! own, and at every size new validators are initialized and bonds are
+//! made that move a validator from the below capacity set into the
+//! consensus set, pushing the weakest consensus validator out. Populating
+//! the chain takes two txs per validator, so each size is only measured
+//! a few times, directly rather than by criterion.
+
+use std::collections::BTreeMap;
+use std::time::Duration;
+
+use borsh::BorshSerialize;
+use criterion::Criterion;
+use namada::ledger::pos::WeightedValidator;
+use namada::ledger::queries::RPC;
+use namada::types::storage::Epoch;
+use namada::types::token;
+use namada::types::transaction::pos;
+use tokio::runtime::Runtime;
+
+use crate::chain::Chain;
+use crate::results;
+use crate::submit::Committed;
+
+/// Numbers of validators to measure with, on top of the genesis ones
+const SET_SIZES: [usize; 3] = [10, 100, 1000];
+/// Txs of each kind measured at every size
+const SAMPLES: usize = 5;
+/// Stake of the first validator, every next one has a token more, so that
+/// they can be told apart in the sets
+const STAKE: u64 = 100;
+
+/// The txs measured at one size
+#[derive(Default)]
+struct Measurements {
+    init_validator: Vec<Option<Committed>>,
+    /// Bonds that moved a validator into the consensus set
+    promote: Vec<Option<Committed>>,
+}
+
+impl Measurements {
+    fn report(&self, size: usize) {
+        for (kind, wasm, txs) in [
+            ("init_validator", "tx_init_validator", &self.init_validator),
+            // Validators are promoted by bonding to them
+            ("promote", "tx_bond", &self.promote),
+        ] {
+            let id = format!("validator_set/{kind}/{size}");
+            results::record_wasm(&id, wasm);
+            let times: Vec<Duration> = txs
+                .iter()
+                .flatten()
+                .filter(|committed| committed.code == "0")
+                .map(|committed| committed.time_to_commit)
+                .collect();
+            let gas: Vec<u64> = txs
+                .iter()
+                .flatten()
+                .filter(|committed| committed.code == "0")
+                .map(|committed| committed.gas_used)
+                .collect();
+            let failed = txs.len() - times.len();
+            results::record_errors(&id, failed);
+            results::record_samples(&id, times.iter().copied());
+            match times.len() {
+                0 if txs.is_empty() => println!("{id:<40} not measured"),
+                0 => println!("{id:<40} {failed} failed"),
+                ok => println!(
+                    "{id:<40} commit: mean {:?}, gas used: mean {}, \
+                     {failed} failed",
+                    times.iter().sum::<Duration>() / ok as u32,
+                    gas.iter().sum::<u64>() / ok as u64,
+                ),
+            }
+        }
+    }
+}
+
+/// Initialize a validator and have the faucet bond `stake` to it
+async fn add_validator(chain: &mut Chain, stake: u64) -> Option<Committed> {
+    let (_key, data) = chain.init_validator_data();
+    let faucet_key = chain.faucet.key.clone();
+    let committed = chain
+        .submit(
+            "tx_init_validator",
+            data.try_to_vec().unwrap(),
+            Some(&faucet_key),
+        )
+        .await;
+    let address = committed
+        .as_ref()
+        .filter(|committed| committed.code == "0")
+        .and_then(|committed| committed.initialized_accounts.first())?;
+    let faucet = chain.faucet.clone();
+    chain
+        .bond(
+            &faucet,
+            &address.parse().expect("Invalid validator address"),
+            token::Amount::whole(stake),
+        )
+        .await;
+    committed
+}
+
+/// The consensus and below capacity validator sets at the pipeline epoch,
+/// where bonds made now take effect
+async fn sets(
+    chain: &Chain,
+) -> (Vec<WeightedValidator>, Vec<WeightedValidator>) {
+    let pipeline_len = chain.pos_params().await.pipeline_len;
+    let epoch: Option<Epoch> = Some(chain.epoch().await + pipeline_len);
+    let pos = RPC.vp().pos();
+    let consensus = pos
+        .consensus_validator_set(&chain.client, &epoch)
+        .await
+        .expect("Unable to query the consensus validator set");
+    let below_capacity = pos
+        .below_capacity_validator_set(&chain.client, &epoch)
+        .await
+        .expect("Unable to query the below capacity validator set");
+    (
+        consensus.into_iter().collect(),
+        below_capacity.into_iter().collect(),
+    )
+}
+
+/// Have the faucet bond just enough to the strongest below capacity
+/// validator for it to replace the weakest consensus validator, if the
+/// consensus set is full
+async fn promote(chain: &mut Chain) -> Option<Option<Committed>> {
+    let (consensus, below_capacity) = sets(chain).await;
+    let weakest = consensus.iter().map(|v| v.bonded_stake).min()?;
+    let strongest = below_capacity.iter().max_by_key(|v| v.bonded_stake)?;
+    let bond = pos::Bond {
+        validator: strongest.address.clone(),
+        amount: weakest - strongest.bonded_stake + token::Amount::whole(1),
+        source: Some(chain.faucet.address.clone()),
+    };
+    let faucet_key = chain.faucet.key.clone();
+    Some(
+        chain
+            .submit("tx_bond", bond.try_to_vec().unwrap(), Some(&faucet_key))
+            .await,
+    )
+}
+
+/// Measure init_validator and bonds moving validators between the sets at
+/// a growing number of validators
+pub fn scaling(_c: &mut Criterion) {
+    let runtime = Runtime::new().unwrap();
+    let measurements = runtime.block_on(async {
+        let mut chain = Chain::connect().await;
+        let max_validator_slots = chain.pos_params().await.max_validator_slots;
+        let mut validators = 0;
+        let mut measurements = BTreeMap::new();
+        for size in SET_SIZES {
+            while validators < size {
+                let stake = STAKE + validators as u64;
+                add_validator(&mut chain, stake)
+                    .await
+                    .expect("Unable to add a validator");
+                validators += 1;
+            }
+            let mut measured = Measurements::default();
+            for sample in 0..SAMPLES {
+                let stake = STAKE + (validators + sample) as u64;
+                measured
+                    .init_validator
+                    .push(add_validator(&mut chain, stake).await);
+            }
+            validators += SAMPLES;
+            for _ in 0..SAMPLES {
+                match promote(&mut chain).await {
+                    Some(committed) => measured.promote.push(committed),
+                    None => {
+                        println!(
+                            "There are at most {max_validator_slots} \
+                             consensus validators, so none are below \
+                             capacity with {validators}"
+                        );
+                        break;
+                    }
+                }
+            }
+            measurements.insert(size, measured);
+        }
+        measurements
+    });
+    for (size, measured) in measurements {
+        measured.report(size);
+    }
+}
@@ -0,0 +1,105 @@
+//! Loading the built wasm artifacts from a wasm directory.
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Default directory holding the hashed wasm artifacts
+pub const WASM_DIR: &str = "../wasm";
+/// Environment variable overriding `WASM_DIR`
+pub const ENV_VAR_WASM_DIR: &str = "TX_BENCH_WASM_DIR";
+
+/// The directory to load the wasm artifacts from
+pub fn wasm_dir() -> PathBuf {
+    std::env::var(ENV_VAR_WASM_DIR)
+        .map(PathBuf::from)
+        .unwrap_or_else(|_| PathBuf::from(WASM_DIR))
+}
+
+/// Find the artifact of the wasm `name` (e.g. `tx_transfer`) in `dir`. The
+/// artifacts are named `<name>.<sha256>.wasm` by `checksums.py`, which
+/// lists the current one in `checksums.json`, or just `<name>.wasm` straight
+/// out of the `wasm_source` Makefile if there's no `checksums.json`. Older
+/// hashed artifacts left in the dir are never picked.
+pub fn find(dir: impl AsRef<Path>, name: &str) -> PathBuf {
+    let dir = dir.as_ref();
+    let file_name = format!("{name}.wasm");
+    let path = if dir.join("checksums.json").exists() {
+        let hashed = checksums(dir).remove(&file_name).unwrap_or_else(|| {
+            panic!("No {name} in the checksums.json of {}", dir.display())
+        });
+        dir.join(hashed)
+    } else {
+        dir.join(file_name)
+    };
+    if !path.exists() {
+        panic!("No {name} wasm found at {}", path.display());
+    }
+    path
+}
+
+/// Read the artifact of the wasm `name` from `dir`
+pub fn read(dir: impl AsRef<Path>, name: &str) -> Vec<u8> {
+    let path = find(dir, name);
+    fs::read(&path).unwrap_or_else(|err| {
+        panic!("Unable to read {}: {err}", path.display())
+    })
+}
+
+/// Read the `checksums.json` of `dir` into a map from the wasm file name
+/// (e.g. `tx_transfer.wasm`) to the hashed artifact's file name. Both the
+/// current format, which also holds the gas cost, and the older plain one
+/// are understood.
+pub fn checksums(dir: impl AsRef<Path>) -> BTreeMap<String, String> {
+    let path = dir.as_ref().join("checksums.json");
+    let checksums = fs::read_to_string(&path).unwrap_or_else(|err| {
+        panic!("Unable to read {}: {err}", path.display())
+    });
+    let checksums: BTreeMap<String, serde_json::Value> =
+        serde_json::from_str(&checksums).expect("Invalid checksums.json");
+    checksums
+        .into_iter()
+        .map(|(name, entry)| {
+            let file_name = entry
+                .get("hash")
+                .unwrap_or(&entry)
+                .as_str()
+                .unwrap_or_else(|| panic!("Invalid checksum for {name}"))
+                .to_string();
+            (name, file_name)
+        })
+        .collect()
+}
+
+/// The hash of every wasm in the `checksums.json` of `dir`, by name (e.g.
+/// `tx_transfer`)
+pub fn hashes(dir: impl AsRef<Path>) -> BTreeMap<String, String> {
+    checksums(dir)
+        .into_iter()
+        .filter_map(|(file_name, hashed)| {
+            let name = file_name.strip_suffix(".wasm")?;
+            let hash = hashed
+                .strip_prefix(&format!("{name}."))?
+                .strip_suffix(".wasm")?;
+            Some((name.to_string(), hash.to_string()))
+        })
+        .collect()
+}
+
+/// The gas of every wasm in the `gas.json` of `dir`, by name (e.g.
+/// `tx_transfer`), or nothing if there's no `gas.json`
+pub fn gas(dir: impl AsRef<Path>) -> BTreeMap<String, u64> {
+    let path = dir.as_ref().join("gas.json");
+    let gas = match fs::read_to_string(&path) {
+        Ok(gas) => gas,
+        Err(_) => return BTreeMap::new(),
+    };
+    let gas: BTreeMap<String, u64> =
+        serde_json::from_str(&gas).expect("Invalid gas.json");
+    gas.into_iter()
+        .map(|(file_name, gas)| {
+            let name = file_name.strip_suffix(".wasm").unwrap_or(&file_name);
+            (name.to_string(), gas)
+        })
+        .collect()
+}
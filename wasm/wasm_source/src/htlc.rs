@@ -0,0 +1,104 @@
+//! Storage and tx data of hashed time-locked escrows, shared by `vp_htlc`
+//! and the txs that lock and release them.
+//!
+//! `tx_htlc_lock` initializes an escrow account with `vp_htlc`, stores the
+//! terms of the [`Lock`] under [`lock_key`] and moves the locked amount from
+//! the owner into it. The whole amount is released to the recipient by
+//! `tx_htlc_claim` with a preimage of the hash lock before the timeout
+//! epoch, or back to the owner by `tx_htlc_refund` from the timeout on.
+//!
+//! Two parties swap tokens by locking them for each other under the same
+//! hash, the one who knows the preimage with the later timeout. Claiming
+//! one escrow reveals the preimage that claims the other.
+
+#[cfg(feature = "vp_htlc")]
+use namada_vp_prelude::*;
+#[cfg(not(feature = "vp_htlc"))]
+use namada_tx_prelude::*;
+
+#[cfg(not(feature = "vp_htlc"))]
+use crate::tx_error::HostResultExt;
+
+/// Storage key segment of the lock
+const LOCK_STORAGE_KEY: &str = "htlc_lock";
+
+/// The terms of an escrow
+#[derive(Debug, Clone, PartialEq, BorshSerialize, BorshDeserialize)]
+pub struct Lock {
+    /// The account the amount is locked from and refunded to
+    pub owner: Address,
+    /// The account that can claim the amount
+    pub recipient: Address,
+    pub token: Address,
+    pub amount: token::Amount,
+    /// SHA-256 hash of the preimage that claims the amount
+    pub hash_lock: hash::Hash,
+    /// The first epoch in which the amount can no longer be claimed, but
+    /// only refunded
+    pub timeout: storage::Epoch,
+}
+
+impl Lock {
+    /// Check that `preimage` opens the hash lock
+    pub fn is_preimage(&self, preimage: &[u8]) -> bool {
+        hash::Hash::sha256(preimage) == self.hash_lock
+    }
+}
+
+/// Data of `tx_htlc_lock`
+#[derive(Debug, Clone, PartialEq, BorshSerialize, BorshDeserialize)]
+pub struct InitHtlc {
+    pub lock: Lock,
+    /// The escrow's VP, normally `vp_htlc`
+    pub vp_code: Vec<u8>,
+}
+
+/// Data of `tx_htlc_claim` and `tx_htlc_refund`
+#[derive(Debug, Clone, PartialEq, BorshSerialize, BorshDeserialize)]
+pub enum Release {
+    /// Release the amount to the recipient
+    Claim { escrow: Address, preimage: Vec<u8> },
+    /// Release the amount back to the owner
+    Refund { escrow: Address },
+}
+
+impl Release {
+    /// The escrow that is released
+    pub fn escrow(&self) -> &Address {
+        match self {
+            Release::Claim { escrow, .. } | Release::Refund { escrow } => {
+                escrow
+            }
+        }
+    }
+}
+
+/// Storage key of the lock of `escrow`
+pub fn lock_key(escrow: &Address) -> storage::Key {
+    storage::Key::from(escrow.to_db_key())
+        .push(&LOCK_STORAGE_KEY.to_owned())
+        .expect("Cannot obtain a storage key")
+}
+
+/// Transfer the locked amount out of `escrow` to `target` and remove the
+/// lock
+#[cfg(not(feature = "vp_htlc"))]
+pub fn release(
+    ctx: &mut Ctx,
+    escrow: &Address,
+    lock: &Lock,
+    target: &Address,
+) -> TxResult {
+    token::transfer(
+        ctx,
+        escrow,
+        target,
+        &lock.token,
+        None,
+        lock.amount,
+        &None,
+        &None,
+    )
+    .or_domain("Transfer")?;
+    ctx.delete(&lock_key(escrow))
+}
@@ -1,17 +1,95 @@
+#[cfg(any(
+    feature = "tx_htlc_claim",
+    feature = "tx_htlc_lock",
+    feature = "tx_htlc_refund",
+    feature = "vp_htlc"
+))]
+pub mod htlc;
+#[cfg(any(feature = "tx_transfer", feature = "vp_masp"))]
+pub mod masp_asset;
+#[cfg(any(
+    feature = "tx_approve_multisig",
+    feature = "tx_bond",
+    feature = "tx_init_multisig_account",
+    feature = "tx_transfer",
+    feature = "tx_unbond",
+    feature = "tx_update_vp",
+    feature = "tx_vote_proposal",
+    feature = "tx_withdraw",
+    feature = "vp_multisig"
+))]
+pub mod multisig;
+#[cfg(any(
+    feature = "tx_bond",
+    feature = "tx_htlc_lock",
+    feature = "tx_ibc",
+    feature = "tx_init_proposal",
+    feature = "tx_set_spending_limit",
+    feature = "tx_transfer",
+    feature = "vp_user"
+))]
+pub mod spending_limit;
+#[cfg(any(feature = "tx_burn", feature = "tx_mint", feature = "vp_token"))]
+pub mod supply;
+#[cfg(any(feature = "tx_init_vesting_account", feature = "vp_vesting"))]
+pub mod vesting;
+
+#[cfg(feature = "tx_approve_multisig")]
+pub mod tx_approve_multisig;
 #[cfg(feature = "tx_bond")]
 pub mod tx_bond;
+#[cfg(feature = "tx_burn")]
+pub mod tx_burn;
 #[cfg(feature = "tx_change_validator_commission")]
 pub mod tx_change_validator_commission;
+#[cfg(any(
+    feature = "tx_approve_multisig",
+    feature = "tx_bond",
+    feature = "tx_burn",
+    feature = "tx_change_validator_commission",
+    feature = "tx_htlc_claim",
+    feature = "tx_htlc_lock",
+    feature = "tx_htlc_refund",
+    feature = "tx_ibc",
+    feature = "tx_init_account",
+    feature = "tx_init_multisig_account",
+    feature = "tx_init_proposal",
+    feature = "tx_init_validator",
+    feature = "tx_init_vesting_account",
+    feature = "tx_mint",
+    feature = "tx_reveal_pk",
+    feature = "tx_set_spending_limit",
+    feature = "tx_transfer",
+    feature = "tx_unbond",
+    feature = "tx_update_vp",
+    feature = "tx_vote_proposal",
+    feature = "tx_withdraw"
+))]
+pub mod tx_error;
+#[cfg(feature = "tx_htlc_claim")]
+pub mod tx_htlc_claim;
+#[cfg(feature = "tx_htlc_lock")]
+pub mod tx_htlc_lock;
+#[cfg(feature = "tx_htlc_refund")]
+pub mod tx_htlc_refund;
 #[cfg(feature = "tx_ibc")]
 pub mod tx_ibc;
 #[cfg(feature = "tx_init_account")]
 pub mod tx_init_account;
+#[cfg(feature = "tx_init_multisig_account")]
+pub mod tx_init_multisig_account;
 #[cfg(feature = "tx_init_proposal")]
 pub mod tx_init_proposal;
 #[cfg(feature = "tx_init_validator")]
 pub mod tx_init_validator;
+#[cfg(feature = "tx_init_vesting_account")]
+pub mod tx_init_vesting_account;
+#[cfg(feature = "tx_mint")]
+pub mod tx_mint;
 #[cfg(feature = "tx_reveal_pk")]
 pub mod tx_reveal_pk;
+#[cfg(feature = "tx_set_spending_limit")]
+pub mod tx_set_spending_limit;
 #[cfg(feature = "tx_transfer")]
 pub mod tx_transfer;
 #[cfg(feature = "tx_unbond")]
@@ -23,10 +101,14 @@ pub mod tx_vote_proposal;
 #[cfg(feature = "tx_withdraw")]
 pub mod tx_withdraw;
 
+#[cfg(feature = "vp_htlc")]
+pub mod vp_htlc;
 #[cfg(feature = "vp_implicit")]
 pub mod vp_implicit;
 #[cfg(feature = "vp_masp")]
 pub mod vp_masp;
+#[cfg(feature = "vp_multisig")]
+pub mod vp_multisig;
 #[cfg(feature = "vp_testnet_faucet")]
 pub mod vp_testnet_faucet;
 #[cfg(feature = "vp_token")]
@@ -36,3 +118,5 @@ pub mod vp_user;
 
 #[cfg(feature = "vp_validator")]
 pub mod vp_validator;
+#[cfg(feature = "vp_vesting")]
+pub mod vp_vesting;
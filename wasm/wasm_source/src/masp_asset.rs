@@ -0,0 +1,94 @@
+//! A registry of the MASP asset types of shielded tokens, shared by
+//! `vp_masp` and `tx_transfer`.
+//!
+//! An asset type is a hash of a token and the epoch its notes were shielded
+//! in, so it can't be mapped back to them. When a token is shielded,
+//! `tx_transfer` registers the token and epoch under the [`asset_key`] of
+//! their asset type with [`register`], and `vp_masp` checks that they hash
+//! to it. Unshielding can then look up the value balance's components
+//! instead of hashing the token with every past epoch, which is only needed
+//! for the notes shielded before the registry existed.
+
+use masp_primitives::asset_type::AssetType;
+#[cfg(feature = "vp_masp")]
+use namada_vp_prelude::address::masp;
+#[cfg(feature = "vp_masp")]
+use namada_vp_prelude::storage::KeySeg;
+#[cfg(feature = "vp_masp")]
+use namada_vp_prelude::*;
+#[cfg(not(feature = "vp_masp"))]
+use namada_tx_prelude::address::masp;
+#[cfg(not(feature = "vp_masp"))]
+use namada_tx_prelude::storage::KeySeg;
+#[cfg(not(feature = "vp_masp"))]
+use namada_tx_prelude::*;
+
+/// Storage key segment prefixing the registered asset types
+const ASSET_STORAGE_KEY: &str = "asset";
+
+/// The MASP asset type of `token` timestamped with `epoch`, if it has one
+pub fn asset_type(
+    epoch: storage::Epoch,
+    token: &Address,
+) -> Option<AssetType> {
+    // Timestamp the chosen token with the epoch
+    let token_bytes = (token, epoch.0).try_to_vec().ok()?;
+    // Generate the unique asset identifier from the unique token address
+    AssetType::new(token_bytes.as_ref()).ok()
+}
+
+/// Storage key of the token and epoch of `asset_type`
+pub fn asset_key(asset_type: &AssetType) -> storage::Key {
+    let identifier: String = asset_type
+        .get_identifier()
+        .iter()
+        .map(|byte| format!("{byte:02x}"))
+        .collect();
+    storage::Key::from(masp().to_db_key())
+        .push(&ASSET_STORAGE_KEY.to_owned())
+        .and_then(|key| key.push(&identifier))
+        .expect("Cannot obtain a storage key")
+}
+
+/// Check if the given storage key is an asset type registration
+pub fn is_asset_key(key: &storage::Key) -> bool {
+    matches!(
+        &key.segments[..],
+        [
+            storage::DbKeySeg::AddressSeg(address),
+            storage::DbKeySeg::StringSeg(prefix),
+            storage::DbKeySeg::StringSeg(_),
+        ] if address == &masp() && prefix == ASSET_STORAGE_KEY
+    )
+}
+
+/// The token and epoch of `asset_type`, if it's registered
+pub fn read<S>(
+    storage: &S,
+    asset_type: &AssetType,
+) -> storage_api::Result<Option<(Address, storage::Epoch)>>
+where
+    S: storage_api::StorageRead,
+{
+    storage.read(&asset_key(asset_type))
+}
+
+/// Register the asset type of `token` shielded in the current epoch, unless
+/// it already is
+pub fn register<S>(
+    storage: &mut S,
+    token: &Address,
+) -> storage_api::Result<()>
+where
+    S: storage_api::StorageRead + storage_api::StorageWrite,
+{
+    let epoch = storage.get_block_epoch()?;
+    let asset_type = asset_type(epoch, token).ok_or_else(|| {
+        storage_api::Error::new_const("The token has no MASP asset type")
+    })?;
+    let key = asset_key(&asset_type);
+    if !storage.has_key(&key)? {
+        storage.write(&key, (token.clone(), epoch))?;
+    }
+    Ok(())
+}
@@ -0,0 +1,152 @@
+//! Storage and tx data of multisignature accounts, shared by `vp_multisig`
+//! and the txs that set them up.
+//!
+//! An account stores its public keys with a threshold under
+//! [`key_set_key`]. A tx carries only one signature, so the other key
+//! holders approve the tx in advance with `tx_approve_multisig`, which
+//! writes an approval of the tx's code hash under [`approval_key`] for the
+//! hash of its data (see [`data_hash`]). The tx is authorized once its own
+//! signature and the approvals it consumes add up to the threshold.
+//!
+//! An approval is good for a single tx: the VP only counts the approvals
+//! that the tx deletes, which the txs that act on behalf of an account do
+//! with [`consume_approvals`].
+
+#[cfg(feature = "vp_multisig")]
+use namada_vp_prelude::*;
+#[cfg(not(feature = "vp_multisig"))]
+use namada_tx_prelude::*;
+
+/// Storage key segment of the key set
+const KEY_SET_STORAGE_KEY: &str = "multisig_keys";
+/// Storage key segment prefixing the approvals
+const APPROVAL_STORAGE_KEY: &str = "multisig_approval";
+
+/// The public keys of a multisignature account and how many of them have
+/// to authorize a tx
+#[derive(Debug, Clone, PartialEq, BorshSerialize, BorshDeserialize)]
+pub struct KeySet {
+    pub public_keys: Vec<key::common::PublicKey>,
+    pub threshold: u8,
+}
+
+impl KeySet {
+    /// A threshold of zero would authorize anything and a threshold above
+    /// the number of keys nothing. A key that's in the set more than once
+    /// would count as many times towards the threshold.
+    pub fn is_valid(&self) -> bool {
+        let unique = self
+            .public_keys
+            .iter()
+            .enumerate()
+            .all(|(index, pk)| !self.public_keys[..index].contains(pk));
+        unique
+            && self.threshold > 0
+            && self.threshold as usize <= self.public_keys.len()
+    }
+}
+
+/// Data of `tx_init_multisig_account`
+#[derive(Debug, Clone, PartialEq, BorshSerialize, BorshDeserialize)]
+pub struct InitMultisigAccount {
+    pub key_set: KeySet,
+    /// The account's VP, normally `vp_multisig`
+    pub vp_code: Vec<u8>,
+}
+
+/// Data of `tx_approve_multisig`, signed by the key at `index`
+#[derive(Debug, Clone, PartialEq, BorshSerialize, BorshDeserialize)]
+pub struct Approval {
+    pub account: Address,
+    /// The hash of the approved tx's code
+    pub code_hash: hash::Hash,
+    /// The [`data_hash`] of the approved tx
+    pub data_hash: hash::Hash,
+    /// Index of the approving key in the account's key set
+    pub index: u8,
+}
+
+/// Storage key of the key set of `owner`
+pub fn key_set_key(owner: &Address) -> storage::Key {
+    storage::Key::from(owner.to_db_key())
+        .push(&KEY_SET_STORAGE_KEY.to_owned())
+        .expect("Cannot obtain a storage key")
+}
+
+/// Check if the given storage key is a key set, returning its owner
+pub fn is_key_set_key(key: &storage::Key) -> Option<&Address> {
+    match &key.segments[..] {
+        [
+            storage::DbKeySeg::AddressSeg(owner),
+            storage::DbKeySeg::StringSeg(segment),
+        ] if segment == KEY_SET_STORAGE_KEY => Some(owner),
+        _ => None,
+    }
+}
+
+/// Storage key of the approval of the tx with `data_hash` by the key of
+/// `owner` at `index`, which holds the approved code hash
+pub fn approval_key(
+    owner: &Address,
+    data_hash: &hash::Hash,
+    index: u8,
+) -> storage::Key {
+    storage::Key::from(owner.to_db_key())
+        .push(&APPROVAL_STORAGE_KEY.to_owned())
+        .and_then(|key| key.push(&data_hash.to_string()))
+        .and_then(|key| key.push(&index.to_string()))
+        .expect("Cannot obtain a storage key")
+}
+
+/// Check if the given storage key is an approval, returning its owner and
+/// the index of the approving key
+pub fn is_approval_key(key: &storage::Key) -> Option<(&Address, u8)> {
+    match &key.segments[..] {
+        [
+            storage::DbKeySeg::AddressSeg(owner),
+            storage::DbKeySeg::StringSeg(prefix),
+            storage::DbKeySeg::StringSeg(_data_hash),
+            storage::DbKeySeg::StringSeg(index),
+        ] if prefix == APPROVAL_STORAGE_KEY => {
+            index.parse().ok().map(|index| (owner, index))
+        }
+        _ => None,
+    }
+}
+
+/// The hash a tx is approved for: that of its data, without the signature
+pub fn data_hash(data: &[u8]) -> hash::Hash {
+    hash::Hash::sha256(data)
+}
+
+/// Delete the approvals of the tx with `data` by the keys of `owner`, if
+/// it's a multisignature account, so that the tx can count them and they
+/// can't be used again.
+///
+/// Only established accounts can have a key set, so implicit and internal
+/// addresses cost nothing. For an established account, reading its key
+/// set is the cheapest way to tell if it's a multisignature account, as
+/// its VP is stored as the whole wasm.
+pub fn consume_approvals<S>(
+    storage: &mut S,
+    owner: &Address,
+    data: &[u8],
+) -> storage_api::Result<()>
+where
+    S: storage_api::StorageRead + storage_api::StorageWrite,
+{
+    if !matches!(owner, Address::Established(_)) {
+        return Ok(());
+    }
+    let key_set: Option<KeySet> = storage.read(&key_set_key(owner))?;
+    if let Some(key_set) = key_set {
+        let data_hash = data_hash(data);
+        for index in 0..key_set.public_keys.len() as u8 {
+            let key = approval_key(owner, &data_hash, index);
+            if storage.has_key(&key)? {
+                storage.delete(&key)?;
+            }
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,178 @@
+//! Opt-in per-epoch spending limits of user accounts, enforced by `vp_user`.
+//!
+//! An account limits how much of a token it can be debited per epoch under
+//! [`limit_key`]. A VP can't keep count of the debits itself, so the txs
+//! that debit an account with a limit add the amount to the record under
+//! [`spent_key`] with [`record_debit`], and the VP checks the record against
+//! the debits and the limit.
+//!
+//! A limit can be lowered right away, but a raise is only written under
+//! [`pending_limit_key`] and takes effect [`COOLDOWN_EPOCHS`] later, so a
+//! compromised key can't lift the limit before the owner notices.
+
+#[cfg(feature = "vp_user")]
+use namada_vp_prelude::*;
+#[cfg(not(feature = "vp_user"))]
+use namada_tx_prelude::*;
+
+/// Epochs until a raised limit takes effect
+pub const COOLDOWN_EPOCHS: u64 = 2;
+
+/// Storage key segment prefixing the spending limit keys
+const SPENDING_LIMIT_STORAGE_KEY: &str = "spending_limit";
+/// Storage key segment of the limit
+const LIMIT_STORAGE_KEY: &str = "limit";
+/// Storage key segment of a raised limit waiting for its cool-down
+const PENDING_STORAGE_KEY: &str = "pending";
+/// Storage key segment of the debits in the current epoch
+const SPENT_STORAGE_KEY: &str = "spent";
+
+/// A raised limit and the epoch it takes effect in
+#[derive(Debug, Clone, PartialEq, BorshSerialize, BorshDeserialize)]
+pub struct PendingLimit {
+    pub amount: token::Amount,
+    pub effective: storage::Epoch,
+}
+
+/// The amount debited in an epoch
+#[derive(Debug, Clone, PartialEq, BorshSerialize, BorshDeserialize)]
+pub struct Spent {
+    pub epoch: storage::Epoch,
+    pub amount: token::Amount,
+}
+
+/// Data of `tx_set_spending_limit`
+#[derive(Debug, Clone, PartialEq, BorshSerialize, BorshDeserialize)]
+pub struct SetSpendingLimit {
+    pub owner: Address,
+    pub token: Address,
+    /// Maximum debit of the token per epoch
+    pub amount: token::Amount,
+}
+
+/// The spending limit storage keys of an account
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LimitKey {
+    Limit,
+    Pending,
+    Spent,
+}
+
+impl LimitKey {
+    fn segment(&self) -> &'static str {
+        match self {
+            LimitKey::Limit => LIMIT_STORAGE_KEY,
+            LimitKey::Pending => PENDING_STORAGE_KEY,
+            LimitKey::Spent => SPENT_STORAGE_KEY,
+        }
+    }
+}
+
+fn key(owner: &Address, token: &Address, limit_key: LimitKey) -> storage::Key {
+    storage::Key::from(owner.to_db_key())
+        .push(&SPENDING_LIMIT_STORAGE_KEY.to_owned())
+        .and_then(|key| key.push(token))
+        .and_then(|key| key.push(&limit_key.segment().to_owned()))
+        .expect("Cannot obtain a storage key")
+}
+
+/// Storage key of the limit of `owner` for `token`
+pub fn limit_key(owner: &Address, token: &Address) -> storage::Key {
+    key(owner, token, LimitKey::Limit)
+}
+
+/// Storage key of a raised limit of `owner` for `token`
+pub fn pending_limit_key(owner: &Address, token: &Address) -> storage::Key {
+    key(owner, token, LimitKey::Pending)
+}
+
+/// Storage key of the debits of `owner` in `token`
+pub fn spent_key(owner: &Address, token: &Address) -> storage::Key {
+    key(owner, token, LimitKey::Spent)
+}
+
+/// Check if the given storage key is a spending limit key, returning its
+/// owner and token
+pub fn is_spending_limit_key(
+    key: &storage::Key,
+) -> Option<(&Address, &Address, LimitKey)> {
+    match &key.segments[..] {
+        [
+            storage::DbKeySeg::AddressSeg(owner),
+            storage::DbKeySeg::StringSeg(prefix),
+            storage::DbKeySeg::AddressSeg(token),
+            storage::DbKeySeg::StringSeg(limit_key),
+        ] if prefix == SPENDING_LIMIT_STORAGE_KEY => {
+            let limit_key = match limit_key.as_str() {
+                LIMIT_STORAGE_KEY => LimitKey::Limit,
+                PENDING_STORAGE_KEY => LimitKey::Pending,
+                SPENT_STORAGE_KEY => LimitKey::Spent,
+                _ => return None,
+            };
+            Some((owner, token, limit_key))
+        }
+        _ => None,
+    }
+}
+
+/// The limit of `owner` for `token` in `epoch`, if it has one
+pub fn read_limit<S>(
+    storage: &S,
+    owner: &Address,
+    token: &Address,
+    epoch: storage::Epoch,
+) -> storage_api::Result<Option<token::Amount>>
+where
+    S: storage_api::StorageRead,
+{
+    let pending: Option<PendingLimit> =
+        storage.read(&pending_limit_key(owner, token))?;
+    match pending {
+        Some(pending) if pending.effective <= epoch => Ok(Some(pending.amount)),
+        _ => storage.read(&limit_key(owner, token)),
+    }
+}
+
+/// The amount of `token` that `owner` has been debited in `epoch`
+pub fn read_spent<S>(
+    storage: &S,
+    owner: &Address,
+    token: &Address,
+    epoch: storage::Epoch,
+) -> storage_api::Result<token::Amount>
+where
+    S: storage_api::StorageRead,
+{
+    let spent: Option<Spent> = storage.read(&spent_key(owner, token))?;
+    Ok(match spent {
+        Some(spent) if spent.epoch == epoch => spent.amount,
+        _ => token::Amount::default(),
+    })
+}
+
+/// Add a debit of `amount` to the record of `owner` in `token`, if it has a
+/// limit for it
+pub fn record_debit<S>(
+    storage: &mut S,
+    owner: &Address,
+    token: &Address,
+    amount: token::Amount,
+) -> storage_api::Result<()>
+where
+    S: storage_api::StorageRead + storage_api::StorageWrite,
+{
+    let epoch = storage.get_block_epoch()?;
+    if storage.has_key(&limit_key(owner, token))?
+        || storage.has_key(&pending_limit_key(owner, token))?
+    {
+        let spent = read_spent(storage, owner, token, epoch)?;
+        storage.write(
+            &spent_key(owner, token),
+            Spent {
+                epoch,
+                amount: spent + amount,
+            },
+        )?;
+    }
+    Ok(())
+}
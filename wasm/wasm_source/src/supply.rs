@@ -0,0 +1,103 @@
+//! Storage and tx data of the total supply of tokens, shared by `vp_token`
+//! and the txs that mint and burn them.
+//!
+//! A token can track its total supply under [`supply_key`], which every
+//! mint or burn has to update by the amount it credits or debits. A token
+//! with a mint authority under [`mint_authority_key`] can only be minted in
+//! txs signed by the authority, up to the hard cap under
+//! [`supply_cap_key`], if it has one.
+
+#[cfg(feature = "vp_token")]
+use namada_vp_prelude::*;
+#[cfg(not(feature = "vp_token"))]
+use namada_tx_prelude::*;
+
+#[cfg(not(feature = "vp_token"))]
+use crate::tx_error::{HostResultExt, TxError};
+
+/// Storage key segment of the total supply
+const SUPPLY_STORAGE_KEY: &str = "supply";
+/// Storage key segment of the hard cap of the total supply
+const SUPPLY_CAP_STORAGE_KEY: &str = "supply_cap";
+/// Storage key segment of the address allowed to mint
+const MINT_AUTHORITY_STORAGE_KEY: &str = "mint_authority";
+
+/// Data of `tx_mint`, signed by the mint authority
+#[derive(Debug, Clone, PartialEq, BorshSerialize, BorshDeserialize)]
+pub struct Mint {
+    pub token: Address,
+    pub target: Address,
+    pub amount: token::Amount,
+}
+
+/// Data of `tx_burn`, signed by the owner
+#[derive(Debug, Clone, PartialEq, BorshSerialize, BorshDeserialize)]
+pub struct Burn {
+    pub token: Address,
+    pub owner: Address,
+    pub amount: token::Amount,
+}
+
+fn key(token: &Address, segment: &str) -> storage::Key {
+    storage::Key::from(token.to_db_key())
+        .push(&segment.to_owned())
+        .expect("Cannot obtain a storage key")
+}
+
+/// Storage key of the total supply of `token`
+pub fn supply_key(token: &Address) -> storage::Key {
+    key(token, SUPPLY_STORAGE_KEY)
+}
+
+/// Storage key of the hard cap of the total supply of `token`
+pub fn supply_cap_key(token: &Address) -> storage::Key {
+    key(token, SUPPLY_CAP_STORAGE_KEY)
+}
+
+/// Storage key of the mint authority of `token`
+pub fn mint_authority_key(token: &Address) -> storage::Key {
+    key(token, MINT_AUTHORITY_STORAGE_KEY)
+}
+
+/// Credit `amount` of `token` to `target` and add it to the total supply
+#[cfg(not(feature = "vp_token"))]
+pub fn mint(
+    ctx: &mut Ctx,
+    token: &Address,
+    target: &Address,
+    amount: token::Amount,
+) -> TxResult {
+    let balance_key = token::balance_key(token, target);
+    let balance: token::Amount = ctx.read(&balance_key)?.unwrap_or_default();
+    let supply: token::Amount =
+        ctx.read(&supply_key(token))?.unwrap_or_default();
+    ctx.write(&balance_key, balance + amount)
+        .or_domain("Minting")?;
+    ctx.write(&supply_key(token), supply + amount)
+        .or_domain("Minting")?;
+    Ok(())
+}
+
+/// Debit `amount` of `token` from `owner` and subtract it from the total
+/// supply
+#[cfg(not(feature = "vp_token"))]
+pub fn burn(
+    ctx: &mut Ctx,
+    token: &Address,
+    owner: &Address,
+    amount: token::Amount,
+) -> TxResult {
+    let balance_key = token::balance_key(token, owner);
+    let balance: token::Amount = ctx.read(&balance_key)?.unwrap_or_default();
+    let supply: token::Amount = ctx
+        .read(&supply_key(token))?
+        .ok_or_else(|| TxError::domain("The token doesn't track its supply"))?;
+    if balance < amount || supply < amount {
+        return Err(TxError::domain("Insufficient balance to burn").into());
+    }
+    ctx.write(&balance_key, balance - amount)
+        .or_domain("Burning")?;
+    ctx.write(&supply_key(token), supply - amount)
+        .or_domain("Burning")?;
+    Ok(())
+}
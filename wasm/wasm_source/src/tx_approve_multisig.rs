@@ -0,0 +1,28 @@
+//! A tx to approve a tx of a multisignature account with one of its keys,
+//! see [`crate::multisig`]. The account's VP checks that the tx is signed
+//! by the key with the index of the approval. The approval is consumed by
+//! the approved tx.
+
+use namada_tx_prelude::*;
+
+use crate::multisig::{self, Approval};
+use crate::tx_error;
+
+#[transaction]
+fn apply_tx(ctx: &mut Ctx, tx_data: Vec<u8>) -> TxResult {
+    let approval: Approval = tx_error::decode_signed(&tx_data, "Approval")?;
+    debug_log!(
+        "apply_tx called to approve {} with code {} for {} with key {}",
+        approval.data_hash,
+        approval.code_hash,
+        approval.account,
+        approval.index
+    );
+
+    let key = multisig::approval_key(
+        &approval.account,
+        &approval.data_hash,
+        approval.index,
+    );
+    ctx.write(&key, approval.code_hash)
+}
@@ -2,17 +2,26 @@
 
 use namada_tx_prelude::*;
 
+use crate::multisig;
+use crate::spending_limit;
+use crate::tx_error::{self, HostResultExt};
+
 #[transaction]
 fn apply_tx(ctx: &mut Ctx, tx_data: Vec<u8>) -> TxResult {
-    let signed = SignedTxData::try_from_slice(&tx_data[..])
-        .wrap_err("failed to decode SignedTxData")
-        .unwrap();
-    let data = signed.data.ok_or_err_msg("Missing data").unwrap();
-    let bond = transaction::pos::Bond::try_from_slice(&data[..])
-        .wrap_err("failed to decode Bond")
-        .unwrap();
+    let data = tx_error::signed_data(&tx_data)?;
+    let bond: transaction::pos::Bond = tx_error::decode(&data, "Bond")?;
+
+    let source = bond.source.as_ref().unwrap_or(&bond.validator);
+    multisig::consume_approvals(ctx, source, &data)
+        .or_domain("Multisig approval")?;
+    // Bonded tokens count towards the source's spending limit, if any
+    let native_token = ctx.get_native_token()?;
+    spending_limit::record_debit(ctx, source, &native_token, bond.amount)
+        .or_domain("Spending limit")?;
 
     ctx.bond_tokens(bond.source.as_ref(), &bond.validator, bond.amount)
+        .or_domain("Bonding")?;
+    Ok(())
 }
 
 #[cfg(test)]
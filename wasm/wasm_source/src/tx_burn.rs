@@ -0,0 +1,23 @@
+//! A tx to burn tokens of the owner, see [`crate::supply`].
+
+use namada_tx_prelude::*;
+
+use crate::supply::{self, Burn};
+use crate::tx_error;
+
+#[transaction]
+fn apply_tx(ctx: &mut Ctx, tx_data: Vec<u8>) -> TxResult {
+    let Burn {
+        token,
+        owner,
+        amount,
+    } = tx_error::decode_signed(&tx_data, "Burn")?;
+    debug_log!(
+        "apply_tx called to burn {} of {} from {}",
+        amount,
+        token,
+        owner
+    );
+
+    supply::burn(ctx, &token, &owner, amount)
+}
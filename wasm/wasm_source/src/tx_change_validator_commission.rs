@@ -3,17 +3,17 @@
 use namada_tx_prelude::transaction::pos::CommissionChange;
 use namada_tx_prelude::*;
 
+use crate::tx_error::{self, HostResultExt};
+
 #[transaction]
 fn apply_tx(ctx: &mut Ctx, tx_data: Vec<u8>) -> TxResult {
-    let signed = SignedTxData::try_from_slice(&tx_data[..])
-        .wrap_err("failed to decode SignedTxData")?;
-    let data = signed.data.ok_or_err_msg("Missing data")?;
     let CommissionChange {
         validator,
         new_rate,
-    } = transaction::pos::CommissionChange::try_from_slice(&data[..])
-        .wrap_err("failed to decode Decimal value")?;
+    } = tx_error::decode_signed(&tx_data, "CommissionChange")?;
     ctx.change_validator_commission_rate(&validator, &new_rate)
+        .or_domain("Commission rate change")?;
+    Ok(())
 }
 
 #[cfg(test)]
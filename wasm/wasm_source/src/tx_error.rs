@@ -0,0 +1,99 @@
+//! Errors of the tx wasms.
+//!
+//! A tx that fails returns a [`TxError`], which is logged with `log_string`
+//! as `tx error <code>: <message>` when it's turned into the tx's result.
+//! Unlike `debug_log!`, this is kept in release builds, so that clients can
+//! tell from the node's log why a tx failed. The host calls that apply the
+//! tx, such as bonding or transferring, fail with errors of their own, which
+//! the txs turn into domain errors with [`HostResultExt::or_domain`].
+
+use std::fmt;
+
+use namada_tx_prelude::*;
+
+/// Why a tx failed
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TxError {
+    /// The tx data can't be decoded as `SignedTxData`
+    DecodeSignedTxData,
+    /// The signed tx data has no data
+    MissingData,
+    /// The data can't be decoded as the named type
+    Decode(&'static str),
+    /// The tx can't be applied in the current state
+    Domain(String),
+}
+
+impl TxError {
+    /// A domain error with the given reason
+    pub fn domain(reason: impl Into<String>) -> Self {
+        Self::Domain(reason.into())
+    }
+
+    /// The code the error is logged with
+    pub fn code(&self) -> u8 {
+        match self {
+            TxError::DecodeSignedTxData => 1,
+            TxError::MissingData => 2,
+            TxError::Decode(_) => 3,
+            TxError::Domain(_) => 4,
+        }
+    }
+}
+
+impl fmt::Display for TxError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TxError::DecodeSignedTxData => {
+                write!(f, "failed to decode SignedTxData")
+            }
+            TxError::MissingData => write!(f, "Missing data"),
+            TxError::Decode(ty) => write!(f, "failed to decode {ty}"),
+            TxError::Domain(reason) => write!(f, "{reason}"),
+        }
+    }
+}
+
+impl From<TxError> for Error {
+    fn from(err: TxError) -> Self {
+        log_string(format!("tx error {}: {}", err.code(), err));
+        Error::new_alloc(err.to_string())
+    }
+}
+
+/// Turns the errors of host calls into domain errors
+pub trait HostResultExt<T> {
+    /// Map the error to a [`TxError::Domain`] saying which `action` failed
+    fn or_domain(self, action: &str) -> Result<T, TxError>;
+}
+
+impl<T> HostResultExt<T> for EnvResult<T> {
+    fn or_domain(self, action: &str) -> Result<T, TxError> {
+        self.map_err(|err| {
+            TxError::domain(format!("{action} failed with: {err}"))
+        })
+    }
+}
+
+/// Get the data out of signed tx data
+pub fn signed_data(tx_data: &[u8]) -> Result<Vec<u8>, TxError> {
+    let signed = SignedTxData::try_from_slice(tx_data)
+        .map_err(|_| TxError::DecodeSignedTxData)?;
+    signed.data.ok_or(TxError::MissingData)
+}
+
+/// Decode `data` as `T`, called `ty` in the error
+pub fn decode<T: BorshDeserialize>(
+    data: &[u8],
+    ty: &'static str,
+) -> Result<T, TxError> {
+    T::try_from_slice(data).map_err(|_| TxError::Decode(ty))
+}
+
+/// Decode the data of signed tx data as `T`, called `ty` in the error
+pub fn decode_signed<T: BorshDeserialize>(
+    tx_data: &[u8],
+    ty: &'static str,
+) -> Result<T, TxError> {
+    decode(&signed_data(tx_data)?, ty)
+}
@@ -0,0 +1,30 @@
+//! A tx to claim the tokens of a hashed time-locked escrow for its
+//! recipient with a preimage of the hash lock, see [`crate::htlc`].
+
+use namada_tx_prelude::*;
+
+use crate::htlc::{self, Lock, Release};
+use crate::tx_error::{self, TxError};
+
+#[transaction]
+fn apply_tx(ctx: &mut Ctx, tx_data: Vec<u8>) -> TxResult {
+    let release: Release = tx_error::decode_signed(&tx_data, "Release")?;
+    debug_log!("apply_tx called with HTLC release: {:?}", release);
+    let (escrow, preimage) = match release {
+        Release::Claim { escrow, preimage } => (escrow, preimage),
+        Release::Refund { .. } => {
+            return Err(TxError::domain("Expected an HTLC claim").into());
+        }
+    };
+
+    let lock: Lock = ctx
+        .read(&htlc::lock_key(&escrow))?
+        .ok_or_else(|| TxError::domain("Missing HTLC lock"))?;
+    if !lock.is_preimage(&preimage) {
+        return Err(TxError::domain("Invalid HTLC preimage").into());
+    }
+    if ctx.get_block_epoch()? >= lock.timeout {
+        return Err(TxError::domain("The HTLC has timed out").into());
+    }
+    htlc::release(ctx, &escrow, &lock, &lock.recipient)
+}
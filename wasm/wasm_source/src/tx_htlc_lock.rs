@@ -0,0 +1,109 @@
+//! A tx to lock tokens of the owner in a new hashed time-locked escrow, see
+//! [`crate::htlc`]. The locked tokens count towards the owner's spending
+//! limit, if any.
+
+use namada_tx_prelude::*;
+
+use crate::htlc::{self, InitHtlc};
+use crate::spending_limit;
+use crate::tx_error::{self, HostResultExt, TxError};
+
+#[transaction]
+fn apply_tx(ctx: &mut Ctx, tx_data: Vec<u8>) -> TxResult {
+    let InitHtlc { lock, vp_code } =
+        tx_error::decode_signed(&tx_data, "InitHtlc")?;
+    debug_log!(
+        "apply_tx called to lock {} of {} from {} for {} until {}",
+        lock.amount,
+        lock.token,
+        lock.owner,
+        lock.recipient,
+        lock.timeout
+    );
+    if lock.timeout <= ctx.get_block_epoch()? {
+        return Err(
+            TxError::domain("The HTLC timeout has already passed").into(),
+        );
+    }
+
+    let escrow = ctx.init_account(&vp_code).or_domain("Escrow creation")?;
+    ctx.write(&htlc::lock_key(&escrow), &lock)
+        .or_domain("Lock storage")?;
+    spending_limit::record_debit(ctx, &lock.owner, &lock.token, lock.amount)
+        .or_domain("Spending limit")?;
+    token::transfer(
+        ctx,
+        &lock.owner,
+        &escrow,
+        &lock.token,
+        None,
+        lock.amount,
+        &None,
+        &None,
+    )
+    .or_domain("Transfer")?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use namada::proto::Tx;
+    use namada::types::chain::ChainId;
+    use namada_tests::log::test;
+    use namada_tests::tx::*;
+    use namada_tx_prelude::StorageWrite;
+
+    use super::*;
+    use crate::htlc::Lock;
+
+    const VP_ALWAYS_TRUE_WASM: &str =
+        "../../wasm_for_tests/vp_always_true.wasm";
+
+    /// Test that locking tokens of an account with a spending limit records
+    /// the debit, so that its VP accepts it.
+    #[test]
+    fn test_lock_records_debit() {
+        tx_host_env::init();
+
+        let owner = address::testing::established_address_1();
+        let token = address::nam();
+        let amount = token::Amount::whole(60);
+        tx_host_env::with(|tx_env| {
+            tx_env.spawn_accounts([&owner, &token]);
+            tx_env.credit_tokens(&owner, &token, None, amount);
+            tx_env
+                .wl_storage
+                .write(
+                    &spending_limit::limit_key(&owner, &token),
+                    token::Amount::whole(100),
+                )
+                .unwrap();
+        });
+
+        let init_htlc = InitHtlc {
+            lock: Lock {
+                owner: owner.clone(),
+                recipient: address::testing::established_address_2(),
+                token: token.clone(),
+                amount,
+                hash_lock: hash::Hash::sha256(b"secret"),
+                timeout: storage::Epoch(1),
+            },
+            vp_code: std::fs::read(VP_ALWAYS_TRUE_WASM)
+                .expect("cannot load wasm"),
+        };
+        let tx = Tx::new(
+            vec![],
+            Some(init_htlc.try_to_vec().unwrap()),
+            ChainId::default(),
+            None,
+        );
+        let signed_tx = tx.sign(&key::testing::keypair_1());
+        apply_tx(ctx(), signed_tx.data.unwrap()).unwrap();
+
+        let epoch = ctx().get_block_epoch().unwrap();
+        let spent =
+            spending_limit::read_spent(&*ctx(), &owner, &token, epoch).unwrap();
+        assert_eq!(spent, amount);
+    }
+}
@@ -0,0 +1,27 @@
+//! A tx to refund the tokens of a timed out hashed time-locked escrow to
+//! its owner, see [`crate::htlc`].
+
+use namada_tx_prelude::*;
+
+use crate::htlc::{self, Lock, Release};
+use crate::tx_error::{self, TxError};
+
+#[transaction]
+fn apply_tx(ctx: &mut Ctx, tx_data: Vec<u8>) -> TxResult {
+    let release: Release = tx_error::decode_signed(&tx_data, "Release")?;
+    debug_log!("apply_tx called with HTLC release: {:?}", release);
+    let escrow = match release {
+        Release::Refund { escrow } => escrow,
+        Release::Claim { .. } => {
+            return Err(TxError::domain("Expected an HTLC refund").into());
+        }
+    };
+
+    let lock: Lock = ctx
+        .read(&htlc::lock_key(&escrow))?
+        .ok_or_else(|| TxError::domain("Missing HTLC lock"))?;
+    if ctx.get_block_epoch()? < lock.timeout {
+        return Err(TxError::domain("The HTLC hasn't timed out yet").into());
+    }
+    htlc::release(ctx, &escrow, &lock, &lock.owner)
+}
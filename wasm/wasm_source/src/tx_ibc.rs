@@ -2,13 +2,48 @@
 //! This tx executes an IBC operation according to the given IBC message as the
 //! tx_data. This tx uses an IBC message wrapped inside
 //! `key::ed25519::SignedTxData` as its input as declared in `ibc` crate.
+//! The tokens sent out with a `MsgTransfer` count towards the sender's
+//! spending limit, if any.
 
+use std::str::FromStr;
+
+use namada_tx_prelude::namada_core::ibc::applications::ics20_fungible_token_transfer::msgs::transfer::MsgTransfer;
+use namada_tx_prelude::namada_core::ledger::ibc::data::IbcMessage;
 use namada_tx_prelude::*;
 
+use crate::spending_limit;
+use crate::tx_error::{self, HostResultExt, TxError};
+
 #[transaction]
 fn apply_tx(ctx: &mut Ctx, tx_data: Vec<u8>) -> TxResult {
-    let signed = SignedTxData::try_from_slice(&tx_data[..])
-        .wrap_err("failed to decode SignedTxData")?;
-    let data = signed.data.ok_or_err_msg("Missing data")?;
-    ctx.dispatch_ibc_action(&data)
+    let data = tx_error::signed_data(&tx_data)?;
+    let message = IbcMessage::decode_from_tx_data(&data)
+        .map_err(|_| TxError::Decode("IbcMessage"))?;
+    if let Ok(transfer) = message.msg_transfer() {
+        record_transfer_debit(ctx, &transfer)?;
+    }
+    ctx.dispatch_ibc_action(&data).or_domain("IBC action")?;
+    Ok(())
+}
+
+/// Add the tokens sent out with a `MsgTransfer` to the sender's spending
+/// record. Only tokens of this chain are limited, the vouchers of tokens
+/// received over IBC have no address to set a limit for.
+fn record_transfer_debit(ctx: &mut Ctx, transfer: &MsgTransfer) -> TxResult {
+    let coin = match &transfer.token {
+        Some(coin) => coin,
+        None => return Ok(()),
+    };
+    let (token, sender) = match (
+        Address::decode(&coin.denom),
+        Address::decode(transfer.sender.to_string()),
+    ) {
+        (Ok(token), Ok(sender)) => (token, sender),
+        _ => return Ok(()),
+    };
+    let amount = token::Amount::from_str(&coin.amount)
+        .map_err(|_| TxError::Decode("token::Amount"))?;
+    spending_limit::record_debit(ctx, &sender, &token, amount)
+        .or_domain("Spending limit")?;
+    Ok(())
 }
@@ -3,17 +3,18 @@
 
 use namada_tx_prelude::*;
 
+use crate::tx_error::{self, HostResultExt};
+
 #[transaction]
 fn apply_tx(ctx: &mut Ctx, tx_data: Vec<u8>) -> TxResult {
-    let signed = SignedTxData::try_from_slice(&tx_data[..])
-        .wrap_err("failed to decode SignedTxData")?;
-    let data = signed.data.ok_or_err_msg("Missing data")?;
-    let tx_data = transaction::InitAccount::try_from_slice(&data[..])
-        .wrap_err("failed to decode InitAccount")?;
+    let tx_data: transaction::InitAccount =
+        tx_error::decode_signed(&tx_data, "InitAccount")?;
     debug_log!("apply_tx called to init a new established account");
 
-    let address = ctx.init_account(&tx_data.vp_code)?;
+    let address =
+        ctx.init_account(&tx_data.vp_code).or_domain("Account creation")?;
     let pk_key = key::pk_key(&address);
-    ctx.write(&pk_key, &tx_data.public_key)?;
+    ctx.write(&pk_key, &tx_data.public_key)
+        .or_domain("Public key storage")?;
     Ok(())
 }
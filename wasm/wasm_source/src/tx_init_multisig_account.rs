@@ -0,0 +1,30 @@
+//! A tx to initialize a new established address controlled by a set of
+//! public keys with a threshold, see [`crate::multisig`].
+
+use namada_tx_prelude::*;
+
+use crate::multisig::{self, InitMultisigAccount};
+use crate::tx_error::{self, HostResultExt, TxError};
+
+#[transaction]
+fn apply_tx(ctx: &mut Ctx, tx_data: Vec<u8>) -> TxResult {
+    let tx_data: InitMultisigAccount =
+        tx_error::decode_signed(&tx_data, "InitMultisigAccount")?;
+    debug_log!(
+        "apply_tx called to init a new {}-of-{} multisig account",
+        tx_data.key_set.threshold,
+        tx_data.key_set.public_keys.len()
+    );
+    if !tx_data.key_set.is_valid() {
+        return Err(TxError::domain(
+            "Invalid multisig threshold or duplicate public keys",
+        )
+        .into());
+    }
+
+    let address =
+        ctx.init_account(&tx_data.vp_code).or_domain("Account creation")?;
+    ctx.write(&multisig::key_set_key(&address), &tx_data.key_set)
+        .or_domain("Key set storage")?;
+    Ok(())
+}
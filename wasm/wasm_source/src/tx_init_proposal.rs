@@ -1,16 +1,24 @@
-//! A tx to create a governance proposal.
+//! A tx to create a governance proposal. The proposal fund counts towards
+//! the author's spending limit, if any.
 
 use namada_tx_prelude::*;
 
+use crate::spending_limit;
+use crate::tx_error::{self, HostResultExt};
+
 #[transaction]
 fn apply_tx(ctx: &mut Ctx, tx_data: Vec<u8>) -> TxResult {
-    let signed = SignedTxData::try_from_slice(&tx_data[..])
-        .wrap_err("failed to decode SignedTxData")?;
-    let data = signed.data.ok_or_err_msg("Missing data")?;
-    let tx_data =
-        transaction::governance::InitProposalData::try_from_slice(&data[..])
-            .wrap_err("failed to decode InitProposalData")?;
+    let tx_data: transaction::governance::InitProposalData =
+        tx_error::decode_signed(&tx_data, "InitProposalData")?;
     log_string("apply_tx called to create a new governance proposal");
 
-    governance::init_proposal(ctx, tx_data)
+    let fund: token::Amount = ctx
+        .read(&gov_storage::get_min_proposal_fund_key())?
+        .unwrap_or_default();
+    let native_token = ctx.get_native_token()?;
+    spending_limit::record_debit(ctx, &tx_data.author, &native_token, fund)
+        .or_domain("Spending limit")?;
+
+    governance::init_proposal(ctx, tx_data).or_domain("Proposal creation")?;
+    Ok(())
 }
@@ -4,24 +4,17 @@
 use namada_tx_prelude::transaction::InitValidator;
 use namada_tx_prelude::*;
 
+use crate::tx_error::{self, HostResultExt};
+
 #[transaction]
 fn apply_tx(ctx: &mut Ctx, tx_data: Vec<u8>) -> TxResult {
-    let signed = SignedTxData::try_from_slice(&tx_data[..])
-        .wrap_err("failed to decode SignedTxData")?;
-    let data = signed.data.ok_or_err_msg("Missing data")?;
-    let init_validator = InitValidator::try_from_slice(&data[..])
-        .wrap_err("failed to decode InitValidator")?;
+    let init_validator: InitValidator =
+        tx_error::decode_signed(&tx_data, "InitValidator")?;
     debug_log!("apply_tx called to init a new validator account");
 
     // Register the validator in PoS
-    match ctx.init_validator(init_validator) {
-        Ok(validator_address) => {
-            debug_log!("Created validator {}", validator_address.encode(),)
-        }
-        Err(err) => {
-            debug_log!("Validator creation failed with: {}", err);
-            panic!()
-        }
-    }
+    let validator_address =
+        ctx.init_validator(init_validator).or_domain("Validator creation")?;
+    debug_log!("Created validator {}", validator_address.encode());
     Ok(())
 }
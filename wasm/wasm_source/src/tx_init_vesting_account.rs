@@ -0,0 +1,25 @@
+//! A tx to initialize a new established address with a public key, a
+//! validity predicate and a vesting schedule, see [`crate::vesting`].
+
+use namada_tx_prelude::*;
+
+use crate::tx_error::{self, HostResultExt};
+use crate::vesting::{self, InitVestingAccount};
+
+#[transaction]
+fn apply_tx(ctx: &mut Ctx, tx_data: Vec<u8>) -> TxResult {
+    let tx_data: InitVestingAccount =
+        tx_error::decode_signed(&tx_data, "InitVestingAccount")?;
+    debug_log!(
+        "apply_tx called to init a new vesting account with {} tranches",
+        tx_data.schedule.tranches.len()
+    );
+
+    let address =
+        ctx.init_account(&tx_data.vp_code).or_domain("Account creation")?;
+    ctx.write(&key::pk_key(&address), &tx_data.public_key)
+        .or_domain("Public key storage")?;
+    ctx.write(&vesting::schedule_key(&address), &tx_data.schedule)
+        .or_domain("Vesting schedule storage")?;
+    Ok(())
+}
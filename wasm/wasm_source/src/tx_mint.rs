@@ -0,0 +1,19 @@
+//! A tx to mint tokens, signed by the token's mint authority, see
+//! [`crate::supply`].
+
+use namada_tx_prelude::*;
+
+use crate::supply::{self, Mint};
+use crate::tx_error;
+
+#[transaction]
+fn apply_tx(ctx: &mut Ctx, tx_data: Vec<u8>) -> TxResult {
+    let Mint {
+        token,
+        target,
+        amount,
+    } = tx_error::decode_signed(&tx_data, "Mint")?;
+    debug_log!("apply_tx called to mint {} of {} to {}", amount, token, target);
+
+    supply::mint(ctx, &token, &target, amount)
+}
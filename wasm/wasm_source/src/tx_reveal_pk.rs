@@ -6,10 +6,13 @@
 use namada_tx_prelude::key::common;
 use namada_tx_prelude::*;
 
+use crate::tx_error::{self, HostResultExt};
+
 #[transaction]
 fn apply_tx(ctx: &mut Ctx, tx_data: Vec<u8>) -> TxResult {
-    let pk = common::PublicKey::try_from_slice(&tx_data[..])
-        .wrap_err("failed to decode common::PublicKey from tx_data")?;
+    let pk: common::PublicKey =
+        tx_error::decode(&tx_data, "common::PublicKey")?;
     debug_log!("tx_reveal_pk called with pk: {pk}");
-    key::reveal_pk(ctx, &pk)
+    key::reveal_pk(ctx, &pk).or_domain("Public key reveal")?;
+    Ok(())
 }
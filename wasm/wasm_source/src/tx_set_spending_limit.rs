@@ -0,0 +1,45 @@
+//! A tx to set the per-epoch spending limit of an account for a token, see
+//! [`crate::spending_limit`]. A new or lower limit takes effect right away,
+//! a higher one only after the cool-down.
+
+use namada_tx_prelude::*;
+
+use crate::spending_limit::{self, PendingLimit, SetSpendingLimit};
+use crate::tx_error::{self, HostResultExt};
+
+#[transaction]
+fn apply_tx(ctx: &mut Ctx, tx_data: Vec<u8>) -> TxResult {
+    let SetSpendingLimit {
+        owner,
+        token,
+        amount,
+    } = tx_error::decode_signed(&tx_data, "SetSpendingLimit")?;
+    debug_log!(
+        "apply_tx called to limit {} to spend {} of {} per epoch",
+        owner,
+        amount,
+        token
+    );
+
+    let epoch = ctx.get_block_epoch()?;
+    let current = spending_limit::read_limit(ctx, &owner, &token, epoch)?;
+    match current {
+        Some(current) if amount > current => {
+            let pending = PendingLimit {
+                amount,
+                effective: epoch + spending_limit::COOLDOWN_EPOCHS,
+            };
+            let key = spending_limit::pending_limit_key(&owner, &token);
+            ctx.write(&key, pending)
+                .or_domain("Spending limit update")?;
+        }
+        _ => {
+            // A lower limit also cancels any pending raise
+            ctx.write(&spending_limit::limit_key(&owner, &token), amount)
+                .or_domain("Spending limit update")?;
+            ctx.delete(&spending_limit::pending_limit_key(&owner, &token))
+                .or_domain("Spending limit update")?;
+        }
+    }
+    Ok(())
+}
@@ -4,13 +4,16 @@
 
 use namada_tx_prelude::*;
 
+use crate::masp_asset;
+use crate::multisig;
+use crate::spending_limit;
+use crate::tx_error::{self, HostResultExt};
+
 #[transaction]
 fn apply_tx(ctx: &mut Ctx, tx_data: Vec<u8>) -> TxResult {
-    let signed = SignedTxData::try_from_slice(&tx_data[..])
-        .wrap_err("failed to decode SignedTxData")?;
-    let data = signed.data.ok_or_err_msg("Missing data")?;
-    let transfer = token::Transfer::try_from_slice(&data[..])
-        .wrap_err("failed to decode token::Transfer")?;
+    let data = tx_error::signed_data(&tx_data)?;
+    let transfer: token::Transfer =
+        tx_error::decode(&data, "token::Transfer")?;
     debug_log!("apply_tx called with transfer: {:#?}", transfer);
     let token::Transfer {
         source,
@@ -21,7 +24,17 @@ fn apply_tx(ctx: &mut Ctx, tx_data: Vec<u8>) -> TxResult {
         key,
         shielded,
     } = transfer;
+    multisig::consume_approvals(ctx, &source, &data)
+        .or_domain("Multisig approval")?;
+    spending_limit::record_debit(ctx, &source, &token, amount)
+        .or_domain("Spending limit")?;
+    if target == address::masp() {
+        masp_asset::register(ctx, &token)
+            .or_domain("Asset type registration")?;
+    }
     token::transfer(
         ctx, &source, &target, &token, sub_prefix, amount, &key, &shielded,
     )
+    .or_domain("Transfer")?;
+    Ok(())
 }
@@ -3,15 +3,20 @@
 
 use namada_tx_prelude::*;
 
+use crate::multisig;
+use crate::tx_error::{self, HostResultExt};
+
 #[transaction]
 fn apply_tx(ctx: &mut Ctx, tx_data: Vec<u8>) -> TxResult {
-    let signed = SignedTxData::try_from_slice(&tx_data[..])
-        .wrap_err("failed to decode SignedTxData")?;
-    let data = signed.data.ok_or_err_msg("Missing data")?;
-    let unbond = transaction::pos::Unbond::try_from_slice(&data[..])
-        .wrap_err("failed to decode Unbond")?;
+    let data = tx_error::signed_data(&tx_data)?;
+    let unbond: transaction::pos::Unbond = tx_error::decode(&data, "Unbond")?;
+    let source = unbond.source.as_ref().unwrap_or(&unbond.validator);
+    multisig::consume_approvals(ctx, source, &data)
+        .or_domain("Multisig approval")?;
 
     ctx.unbond_tokens(unbond.source.as_ref(), &unbond.validator, unbond.amount)
+        .or_domain("Unbonding")?;
+    Ok(())
 }
 
 #[cfg(test)]
@@ -4,15 +4,19 @@
 
 use namada_tx_prelude::*;
 
+use crate::multisig;
+use crate::tx_error::{self, HostResultExt};
+
 #[transaction]
 fn apply_tx(ctx: &mut Ctx, tx_data: Vec<u8>) -> TxResult {
-    let signed = SignedTxData::try_from_slice(&tx_data[..])
-        .wrap_err("failed to decode SignedTxData")?;
-    let data = signed.data.ok_or_err_msg("Missing data")?;
-    let update_vp = transaction::UpdateVp::try_from_slice(&data[..])
-        .wrap_err("failed to decode UpdateVp")?;
+    let data = tx_error::signed_data(&tx_data)?;
+    let update_vp: transaction::UpdateVp = tx_error::decode(&data, "UpdateVp")?;
+    multisig::consume_approvals(ctx, &update_vp.addr, &data)
+        .or_domain("Multisig approval")?;
 
     debug_log!("update VP for: {:#?}", update_vp.addr);
 
     ctx.update_validity_predicate(&update_vp.addr, update_vp.vp_code)
+        .or_domain("VP update")?;
+    Ok(())
 }
@@ -2,16 +2,19 @@
 
 use namada_tx_prelude::*;
 
+use crate::multisig;
+use crate::tx_error::{self, HostResultExt};
+
 #[transaction]
 fn apply_tx(ctx: &mut Ctx, tx_data: Vec<u8>) -> TxResult {
-    let signed = SignedTxData::try_from_slice(&tx_data[..])
-        .wrap_err("failed to decode SignedTxData")?;
-    let data = signed.data.ok_or_err_msg("Missing data")?;
-    let tx_data =
-        transaction::governance::VoteProposalData::try_from_slice(&data[..])
-            .wrap_err("failed to decode VoteProposalData")?;
+    let data = tx_error::signed_data(&tx_data)?;
+    let tx_data: transaction::governance::VoteProposalData =
+        tx_error::decode(&data, "VoteProposalData")?;
+    multisig::consume_approvals(ctx, &tx_data.voter, &data)
+        .or_domain("Multisig approval")?;
 
     debug_log!("apply_tx called to vote a governance proposal");
 
-    governance::vote_proposal(ctx, tx_data)
+    governance::vote_proposal(ctx, tx_data).or_domain("Voting")?;
+    Ok(())
 }
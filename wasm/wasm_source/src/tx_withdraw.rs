@@ -3,16 +3,21 @@
 
 use namada_tx_prelude::*;
 
+use crate::multisig;
+use crate::tx_error::{self, HostResultExt};
+
 #[transaction]
 fn apply_tx(ctx: &mut Ctx, tx_data: Vec<u8>) -> TxResult {
-    let signed = SignedTxData::try_from_slice(&tx_data[..])
-        .wrap_err("failed to decode SignedTxData")?;
-    let data = signed.data.ok_or_err_msg("Missing data")?;
-    let withdraw = transaction::pos::Withdraw::try_from_slice(&data[..])
-        .wrap_err("failed to decode Withdraw")?;
-
-    let slashed =
-        ctx.withdraw_tokens(withdraw.source.as_ref(), &withdraw.validator)?;
+    let data = tx_error::signed_data(&tx_data)?;
+    let withdraw: transaction::pos::Withdraw =
+        tx_error::decode(&data, "Withdraw")?;
+    let source = withdraw.source.as_ref().unwrap_or(&withdraw.validator);
+    multisig::consume_approvals(ctx, source, &data)
+        .or_domain("Multisig approval")?;
+
+    let slashed = ctx
+        .withdraw_tokens(withdraw.source.as_ref(), &withdraw.validator)
+        .or_domain("Withdrawal")?;
     if slashed != token::Amount::default() {
         debug_log!("New withdrawal slashed for {}", slashed);
     }
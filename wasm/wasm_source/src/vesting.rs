@@ -0,0 +1,61 @@
+//! Storage and tx data of vesting schedules, shared by `vp_vesting` and
+//! `tx_init_vesting_account`.
+//!
+//! A vesting account is initialized with `vp_vesting`, a public key and a
+//! [`VestingSchedule`] stored under [`schedule_key`] all in one
+//! `tx_init_vesting_account`, so that the schedule is fixed before the
+//! account can hold anything.
+
+#[cfg(feature = "vp_vesting")]
+use namada_vp_prelude::storage::KeySeg;
+#[cfg(feature = "vp_vesting")]
+use namada_vp_prelude::*;
+#[cfg(not(feature = "vp_vesting"))]
+use namada_tx_prelude::storage::KeySeg;
+#[cfg(not(feature = "vp_vesting"))]
+use namada_tx_prelude::*;
+
+/// Storage key segment of the vesting schedule
+const SCHEDULE_STORAGE_KEY: &str = "vesting_schedule";
+
+/// An amount that vests in an epoch
+#[derive(Debug, Clone, PartialEq, BorshSerialize, BorshDeserialize)]
+pub struct Tranche {
+    pub epoch: storage::Epoch,
+    pub amount: token::Amount,
+}
+
+/// The tranches of a token that vest over time
+#[derive(Debug, Clone, PartialEq, BorshSerialize, BorshDeserialize)]
+pub struct VestingSchedule {
+    pub token: Address,
+    pub tranches: Vec<Tranche>,
+}
+
+impl VestingSchedule {
+    /// The amount that hasn't vested yet in `epoch`
+    pub fn unvested(&self, epoch: storage::Epoch) -> token::Amount {
+        self.tranches
+            .iter()
+            .filter(|tranche| tranche.epoch > epoch)
+            .fold(token::Amount::default(), |unvested, tranche| {
+                unvested + tranche.amount
+            })
+    }
+}
+
+/// Data of `tx_init_vesting_account`
+#[derive(Debug, Clone, PartialEq, BorshSerialize, BorshDeserialize)]
+pub struct InitVestingAccount {
+    pub public_key: key::common::PublicKey,
+    pub schedule: VestingSchedule,
+    /// The account's VP, normally `vp_vesting`
+    pub vp_code: Vec<u8>,
+}
+
+/// Storage key of the vesting schedule of `owner`
+pub fn schedule_key(owner: &Address) -> storage::Key {
+    storage::Key::from(owner.to_db_key())
+        .push(&SCHEDULE_STORAGE_KEY.to_owned())
+        .expect("Cannot obtain a storage key")
+}
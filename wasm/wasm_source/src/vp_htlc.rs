@@ -0,0 +1,420 @@
+//! A hashed time-locked escrow VP, see [`crate::htlc`].
+//!
+//! The escrow has no key of its own. Its balance of the locked token can
+//! only be released in full, together with the removal of the lock, by a tx
+//! whose data is a [`Release`] of this escrow that credits the amount to:
+//! - the recipient, if it reveals a preimage of the hash lock before the
+//!   timeout epoch, or
+//! - the owner, from the timeout epoch on.
+//!
+//! The lock can only be written when the escrow is initialized and its VP
+//! can never be changed.
+
+use namada_vp_prelude::storage::KeySeg;
+use namada_vp_prelude::*;
+
+use crate::htlc::{self, Lock, Release};
+
+enum KeyType<'a> {
+    Token(&'a Address),
+    Vp(&'a Address),
+    Lock,
+    Unknown,
+}
+
+impl<'a> KeyType<'a> {
+    fn new(key: &'a storage::Key, owner: &Address) -> KeyType<'a> {
+        if let Some(address) = token::is_any_token_balance_key(key) {
+            Self::Token(address)
+        } else if let Some((_, address)) =
+            token::is_any_multitoken_balance_key(key)
+        {
+            Self::Token(address)
+        } else if let Some(address) = key.is_validity_predicate() {
+            Self::Vp(address)
+        } else if key == &htlc::lock_key(owner) {
+            Self::Lock
+        } else {
+            Self::Unknown
+        }
+    }
+}
+
+#[validity_predicate]
+fn validate_tx(
+    ctx: &Ctx,
+    tx_data: Vec<u8>,
+    addr: Address,
+    keys_changed: BTreeSet<storage::Key>,
+    verifiers: BTreeSet<Address>,
+) -> VpResult {
+    debug_log!(
+        "vp_htlc called with escrow addr: {}, key_changed: {:?}, verifiers: \
+         {:?}",
+        addr,
+        keys_changed,
+        verifiers
+    );
+
+    if !is_valid_tx(ctx, &tx_data)? {
+        return reject();
+    }
+
+    let lock_key = htlc::lock_key(&addr);
+    let lock: Option<Lock> = ctx.read_pre(&lock_key)?;
+
+    for key in keys_changed.iter() {
+        let is_valid = match KeyType::new(key, &addr) {
+            KeyType::Token(owner) => {
+                if owner == &addr {
+                    let pre: token::Amount =
+                        ctx.read_pre(key)?.unwrap_or_default();
+                    let post: token::Amount =
+                        ctx.read_post(key)?.unwrap_or_default();
+                    let change = post.change() - pre.change();
+                    // credit is always accepted, debit only as a release of
+                    // the whole locked amount
+                    let valid = change >= 0
+                        || match &lock {
+                            Some(lock) => {
+                                key == &token::balance_key(&lock.token, &addr)
+                                    && change == -lock.amount.change()
+                                    && !ctx.has_key_post(&lock_key)?
+                                    && is_released(ctx, &addr, lock, &tx_data)?
+                            }
+                            None => false,
+                        };
+                    debug_log!(
+                        "token key: {}, change: {}, valid modification: {}",
+                        key,
+                        change,
+                        valid
+                    );
+                    valid
+                } else {
+                    // If this is not the owner, allow any change
+                    true
+                }
+            }
+            KeyType::Lock => match &lock {
+                // The lock is written when the escrow is initialized, together
+                // with its VP
+                None => {
+                    let vp_key = storage::Key::validity_predicate(&addr);
+                    !ctx.has_key_pre(&vp_key)?
+                }
+                // and removed when it's released
+                Some(lock) => {
+                    !ctx.has_key_post(key)?
+                        && is_released(ctx, &addr, lock, &tx_data)?
+                }
+            },
+            KeyType::Vp(owner) => {
+                if owner == &addr {
+                    // The VP is only written when the escrow is initialized
+                    !ctx.has_key_pre(key)?
+                } else {
+                    let vp: Vec<u8> = ctx.read_bytes_post(key)?.unwrap();
+                    is_vp_whitelisted(ctx, &vp)?
+                }
+            }
+            KeyType::Unknown => {
+                // Nothing else can be changed in the escrow's address space
+                key.segments.get(0) != Some(&addr.to_db_key())
+            }
+        };
+        if !is_valid {
+            debug_log!("key {} modification failed vp", key);
+            return reject();
+        }
+    }
+
+    accept()
+}
+
+/// Check that the tx data releases `escrow` under the terms of `lock` and
+/// that the tx credits the locked amount to the account it's released to
+fn is_released(
+    ctx: &Ctx,
+    escrow: &Address,
+    lock: &Lock,
+    tx_data: &[u8],
+) -> EnvResult<bool> {
+    let release = SignedTxData::try_from_slice(tx_data)
+        .ok()
+        .and_then(|signed| signed.data)
+        .and_then(|data| Release::try_from_slice(&data[..]).ok());
+    let release = match release {
+        Some(release) if release.escrow() == escrow => release,
+        _ => return Ok(false),
+    };
+    let epoch = ctx.get_block_epoch()?;
+    let target = match &release {
+        Release::Claim { preimage, .. }
+            if lock.is_preimage(preimage) && epoch < lock.timeout =>
+        {
+            &lock.recipient
+        }
+        Release::Refund { .. } if epoch >= lock.timeout => &lock.owner,
+        _ => {
+            debug_log!(
+                "HTLC release {:?} in epoch {} rejected",
+                release,
+                epoch
+            );
+            return Ok(false);
+        }
+    };
+    let key = token::balance_key(&lock.token, target);
+    let pre: token::Amount = ctx.read_pre(&key)?.unwrap_or_default();
+    let post: token::Amount = ctx.read_post(&key)?.unwrap_or_default();
+    Ok(post.change() - pre.change() >= lock.amount.change())
+}
+
+#[cfg(test)]
+mod tests {
+    use namada::types::storage::Epoch;
+    // Use this as `#[test]` annotation to enable logging
+    use namada_tests::log::test;
+    use namada_tests::tx::{self, tx_host_env, TestTxEnv};
+    use namada_tests::vp::*;
+    use namada_tx_prelude::{StorageWrite, TxEnv};
+    use namada_vp_prelude::key::SigScheme;
+    use proptest::prelude::*;
+
+    use super::*;
+
+    /// The preimage of the test locks
+    const SECRET: &[u8] = b"htlc secret";
+    /// The timeout epoch of the test locks
+    const TIMEOUT: u64 = 10;
+
+    /// A lock of 100 NAM for `established_address_3` from
+    /// `established_address_2`
+    fn lock() -> Lock {
+        Lock {
+            owner: address::testing::established_address_2(),
+            recipient: address::testing::established_address_3(),
+            token: address::nam(),
+            amount: token::Amount::whole(100),
+            hash_lock: hash::Hash::sha256(SECRET),
+            timeout: Epoch(TIMEOUT),
+        }
+    }
+
+    /// Run the VP on a tx with the `release` data that transfers the locked
+    /// amount out of an escrow with [`lock`] to `target` in `epoch`,
+    /// removing the lock
+    fn release(release: Release, target: Address, epoch: u64) -> bool {
+        // Initialize a tx environment
+        let mut tx_env = TestTxEnv::default();
+
+        let escrow = address::testing::established_address_1();
+        let lock = lock();
+
+        // Spawn the accounts to be able to modify their storage
+        tx_env.spawn_accounts([
+            &escrow,
+            &lock.owner,
+            &lock.recipient,
+            &lock.token,
+        ]);
+
+        // Lock the tokens in the escrow
+        tx_env.credit_tokens(&escrow, &lock.token, None, lock.amount);
+        tx_env
+            .wl_storage
+            .write(&htlc::lock_key(&escrow), lock.clone())
+            .unwrap();
+        tx_env.wl_storage.storage.block.epoch = Epoch(epoch);
+        tx_env.commit_genesis();
+
+        // Initialize VP environment from a transaction
+        vp_host_env::init_from_tx(escrow.clone(), tx_env, |address| {
+            // Release the escrow in a transaction
+            tx_host_env::token::transfer(
+                tx::ctx(),
+                address,
+                &target,
+                &lock.token,
+                None,
+                lock.amount,
+                &None,
+                &None,
+            )
+            .unwrap();
+            tx::ctx().delete(&htlc::lock_key(address)).unwrap();
+        });
+
+        // Anyone can release an escrow, the signature isn't checked
+        let data = release.try_to_vec().unwrap();
+        let sig =
+            key::common::SigScheme::sign(&key::testing::keypair_1(), &data);
+        let signed = SignedTxData {
+            data: Some(data),
+            sig,
+        };
+
+        let vp_env = vp_host_env::take();
+        let tx_data: Vec<u8> = signed.try_to_vec().unwrap();
+        let keys_changed: BTreeSet<storage::Key> =
+            vp_env.all_touched_storage_keys();
+        let verifiers: BTreeSet<Address> = BTreeSet::default();
+        vp_host_env::set(vp_env);
+        validate_tx(&CTX, tx_data, escrow, keys_changed, verifiers).unwrap()
+    }
+
+    fn claim(preimage: &[u8]) -> Release {
+        Release::Claim {
+            escrow: address::testing::established_address_1(),
+            preimage: preimage.to_vec(),
+        }
+    }
+
+    fn refund() -> Release {
+        Release::Refund {
+            escrow: address::testing::established_address_1(),
+        }
+    }
+
+    /// Test that no-op transaction (i.e. no storage modifications) accepted.
+    #[test]
+    fn test_no_op_transaction() {
+        let tx_data: Vec<u8> = vec![];
+        let addr: Address = address::testing::established_address_1();
+        let keys_changed: BTreeSet<storage::Key> = BTreeSet::default();
+        let verifiers: BTreeSet<Address> = BTreeSet::default();
+
+        // The VP env must be initialized before calling `validate_tx`
+        vp_host_env::init();
+
+        assert!(
+            validate_tx(&CTX, tx_data, addr, keys_changed, verifiers).unwrap()
+        );
+    }
+
+    /// Test that a claim to someone other than the recipient is rejected.
+    #[test]
+    fn test_claim_to_other_rejected() {
+        let other = address::testing::established_address_2();
+        assert!(!release(claim(SECRET), other, 0));
+    }
+
+    /// Test that a refund to someone other than the owner is rejected.
+    #[test]
+    fn test_refund_to_other_rejected() {
+        let other = address::testing::established_address_3();
+        assert!(!release(refund(), other, TIMEOUT));
+    }
+
+    /// Test that a lock can't be written to an existing escrow, such as one
+    /// that has been released.
+    #[test]
+    fn test_lock_on_existing_escrow_rejected() {
+        // Initialize a tx environment
+        let mut tx_env = TestTxEnv::default();
+
+        let escrow = address::testing::established_address_1();
+        let lock = lock();
+
+        // Spawn the accounts to be able to modify their storage
+        tx_env.spawn_accounts([&escrow, &lock.token]);
+        tx_env.commit_genesis();
+
+        // Initialize VP environment from a transaction
+        vp_host_env::init_from_tx(escrow.clone(), tx_env, |address| {
+            // Write a lock in a transaction
+            tx::ctx().write(&htlc::lock_key(address), lock).unwrap();
+        });
+
+        let vp_env = vp_host_env::take();
+        let tx_data: Vec<u8> = vec![];
+        let keys_changed: BTreeSet<storage::Key> =
+            vp_env.all_touched_storage_keys();
+        let verifiers: BTreeSet<Address> = BTreeSet::default();
+        vp_host_env::set(vp_env);
+        assert!(
+            !validate_tx(&CTX, tx_data, escrow, keys_changed, verifiers)
+                .unwrap()
+        );
+    }
+
+    /// Test that a debit of the escrow without release data is rejected.
+    #[test]
+    fn test_debit_without_release_rejected() {
+        // Initialize a tx environment
+        let mut tx_env = TestTxEnv::default();
+
+        let escrow = address::testing::established_address_1();
+        let target = address::testing::established_address_2();
+        let lock = lock();
+
+        // Spawn the accounts to be able to modify their storage
+        tx_env.spawn_accounts([&escrow, &target, &lock.token]);
+        tx_env.credit_tokens(&escrow, &lock.token, None, lock.amount);
+        tx_env
+            .wl_storage
+            .write(&htlc::lock_key(&escrow), lock.clone())
+            .unwrap();
+        tx_env.commit_genesis();
+
+        // Initialize VP environment from a transaction
+        vp_host_env::init_from_tx(escrow.clone(), tx_env, |address| {
+            // Apply transfer in a transaction
+            tx_host_env::token::transfer(
+                tx::ctx(),
+                address,
+                &target,
+                &lock.token,
+                None,
+                lock.amount,
+                &None,
+                &None,
+            )
+            .unwrap();
+        });
+
+        let vp_env = vp_host_env::take();
+        let tx_data: Vec<u8> = vec![];
+        let keys_changed: BTreeSet<storage::Key> =
+            vp_env.all_touched_storage_keys();
+        let verifiers: BTreeSet<Address> = BTreeSet::default();
+        vp_host_env::set(vp_env);
+        assert!(
+            !validate_tx(&CTX, tx_data, escrow, keys_changed, verifiers)
+                .unwrap()
+        );
+    }
+
+    proptest! {
+        /// Test that a claim with the preimage is accepted if and only if it
+        /// is made before the timeout.
+        #[test]
+        fn test_claim(epoch in 0..2 * TIMEOUT) {
+            let recipient = lock().recipient;
+            prop_assert_eq!(
+                release(claim(SECRET), recipient, epoch),
+                epoch < TIMEOUT
+            );
+        }
+
+        /// Test that a claim with anything but the preimage is rejected.
+        #[test]
+        fn test_claim_with_wrong_preimage_rejected(
+            preimage in any::<Vec<u8>>()
+                .prop_filter("the preimage", |preimage| preimage != SECRET),
+            epoch in 0..TIMEOUT,
+        ) {
+            let recipient = lock().recipient;
+            prop_assert!(!release(claim(&preimage), recipient, epoch));
+        }
+
+        /// Test that a refund is accepted if and only if it is made from the
+        /// timeout on.
+        #[test]
+        fn test_refund(epoch in 0..2 * TIMEOUT) {
+            let owner = lock().owner;
+            prop_assert_eq!(release(refund(), owner, epoch), epoch >= TIMEOUT);
+        }
+    }
+}
@@ -7,23 +7,239 @@ use namada_vp_prelude::address::masp;
 use namada_vp_prelude::storage::Epoch;
 use namada_vp_prelude::*;
 
+use crate::masp_asset;
+
+/// Why a tx is rejected. The code is logged with the rejection.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Rejection {
+    /// The tx data can't be decoded as `SignedTxData`
+    InvalidSignedTxData = 1,
+    /// The signed tx data has no data
+    MissingData = 2,
+    /// The data can't be decoded as a `token::Transfer`
+    InvalidTransfer = 3,
+    /// The epoch of the block isn't available
+    MissingEpoch = 4,
+    /// The token can't be converted to a MASP asset type
+    InvalidAssetType = 5,
+    /// The amount is out of the range of MASP amounts
+    InvalidAmount = 6,
+    /// The value balance of an unshielding tx has no component of the token
+    MissingAssetType = 7,
+    /// The transparent tx value pool is negative
+    NegativeTransparentPool = 8,
+    /// The value balance of an unshielding tx has components of the token
+    /// from several epochs
+    AmbiguousAssetType = 9,
+    /// A transfer into or out of the MASP has no shielded part
+    MissingShieldedPart = 10,
+    /// An asset type registration doesn't hash to its key, changes an
+    /// existing one or can't be read
+    InvalidAssetRegistration = 11,
+}
+
+impl Rejection {
+    /// Log the reason and reject the tx
+    fn reject(self) -> VpResult {
+        log_string(format!(
+            "vp_masp rejected the tx with code {}: {:?}",
+            self as u8, self
+        ));
+        reject()
+    }
+}
+
+/// The MASP asset type of `token` timestamped with `epoch`
+fn asset_type(epoch: Epoch, token: &Address) -> Result<AssetType, Rejection> {
+    masp_asset::asset_type(epoch, token).ok_or(Rejection::InvalidAssetType)
+}
+
 /// Convert Namada amount and token type to MASP equivalents
 fn convert_amount(
     epoch: Epoch,
     token: &Address,
     val: token::Amount,
-) -> (AssetType, Amount) {
+) -> Result<(AssetType, Amount), Rejection> {
     // Timestamp the chosen token with the current epoch
-    let token_bytes = (token, epoch.0)
-        .try_to_vec()
-        .expect("token should serialize");
-    // Generate the unique asset identifier from the unique token address
-    let asset_type = AssetType::new(token_bytes.as_ref())
-        .expect("unable to create asset type");
+    let asset_type = asset_type(epoch, token)?;
     // Combine the value and unit into one amount
     let amount = Amount::from_nonnegative(asset_type, u64::from(val))
-        .expect("invalid value or asset type for amount");
-    (asset_type, amount)
+        .map_err(|_| Rejection::InvalidAmount)?;
+    Ok((asset_type, amount))
+}
+
+/// Find the asset type of `token` in the value balance of an unshielding
+/// tx. Notes of a token are timestamped with the epoch they were shielded
+/// in, so it can have any epoch up to the current one, but only one of
+/// them, otherwise the amount couldn't be attributed to an epoch. The
+/// components are looked up in the asset type registry, except for the
+/// current epoch's, which notes converted to it needn't be registered with.
+/// Notes shielded before the registry existed, or by txs that don't
+/// register them, are matched against the token's asset types of every
+/// past epoch instead.
+fn unshielded_asset_type<S>(
+    storage: &S,
+    epoch: Epoch,
+    token: &Address,
+    value_balance: &Amount,
+) -> Result<AssetType, Rejection>
+where
+    S: storage_api::StorageRead,
+{
+    let current = asset_type(epoch, token)?;
+    // Only hashed if a component isn't registered
+    let mut past: Option<Vec<AssetType>> = None;
+    let mut matching = vec![];
+    for (atype, _) in value_balance.components() {
+        let is_token = *atype == current
+            || match masp_asset::read(storage, atype)
+                .map_err(|_| Rejection::InvalidAssetRegistration)?
+            {
+                Some((registered, shielded)) => {
+                    &registered == token && shielded <= epoch
+                }
+                None => {
+                    if past.is_none() {
+                        past = Some(past_asset_types(epoch, token)?);
+                    }
+                    past.as_ref().map_or(false, |past| past.contains(atype))
+                }
+            };
+        if is_token {
+            matching.push(*atype);
+        }
+    }
+    match matching[..] {
+        [atype] => Ok(atype),
+        [] => Err(Rejection::MissingAssetType),
+        _ => Err(Rejection::AmbiguousAssetType),
+    }
+}
+
+/// The asset types of `token` timestamped with every epoch before `epoch`
+fn past_asset_types(
+    epoch: Epoch,
+    token: &Address,
+) -> Result<Vec<AssetType>, Rejection> {
+    (0..epoch.0)
+        .map(|past| asset_type(Epoch(past), token))
+        .collect()
+}
+
+/// Check that the transparent part of a shielded transfer leaves the
+/// transparent tx value pool nonnegative, given the value balance of its
+/// shielded part
+fn check_transparent_pool<S>(
+    storage: &S,
+    epoch: Epoch,
+    transfer: &token::Transfer,
+    value_balance: &Amount,
+) -> Result<(), Rejection>
+where
+    S: storage_api::StorageRead,
+{
+    let mut transparent_tx_pool = Amount::zero();
+    // The Sapling value balance adds to the transparent tx pool
+    transparent_tx_pool += value_balance.clone();
+
+    // Handle shielding/transparent input
+    if transfer.source != masp() {
+        // Note that the asset type is timestamped so shields
+        // where the shielded value has an incorrect timestamp
+        // are automatically rejected
+        let (_transp_asset, transp_amt) =
+            convert_amount(epoch, &transfer.token, transfer.amount)?;
+
+        // Non-masp sources add to transparent tx pool
+        transparent_tx_pool += transp_amt;
+    }
+
+    // Handle unshielding/transparent output
+    if transfer.target != masp() {
+        // Timestamp is derived to allow unshields for older tokens
+        let atype = unshielded_asset_type(
+            storage,
+            epoch,
+            &transfer.token,
+            value_balance,
+        )?;
+
+        let transp_amt =
+            Amount::from_nonnegative(atype, u64::from(transfer.amount))
+                .map_err(|_| Rejection::InvalidAmount)?;
+
+        // Non-masp destinations subtract from transparent tx pool
+        transparent_tx_pool -= transp_amt;
+    }
+
+    match transparent_tx_pool.partial_cmp(&Amount::zero()) {
+        None | Some(Ordering::Less) => {
+            debug_log!(
+                "Transparent transaction value pool must be nonnegative. \
+                 Violation may be caused by transaction being constructed in \
+                 previous epoch. Maybe try again."
+            );
+            // Section 3.4: The remaining value in the transparent
+            // transaction value pool MUST be nonnegative.
+            Err(Rejection::NegativeTransparentPool)
+        }
+        _ => Ok(()),
+    }
+}
+
+/// Check everything but the proofs of the tx, returning its data for their
+/// verification
+fn check_tx(ctx: &Ctx, tx_data: &[u8]) -> Result<Vec<u8>, Rejection> {
+    let signed = SignedTxData::try_from_slice(tx_data)
+        .map_err(|_| Rejection::InvalidSignedTxData)?;
+    // Also get the data as bytes for the VM.
+    let data = signed.data.ok_or(Rejection::MissingData)?;
+    let transfer = token::Transfer::try_from_slice(&data[..])
+        .map_err(|_| Rejection::InvalidTransfer)?;
+
+    let shielded_tx = transfer
+        .shielded
+        .as_ref()
+        .ok_or(Rejection::MissingShieldedPart)?;
+    let epoch = ctx.get_block_epoch().map_err(|_| Rejection::MissingEpoch)?;
+    check_transparent_pool(
+        &ctx.pre(),
+        epoch,
+        &transfer,
+        &shielded_tx.value_balance,
+    )?;
+    Ok(data)
+}
+
+/// Check that every asset type registered by the tx is new and hashes to
+/// its key
+fn check_asset_registrations(
+    ctx: &Ctx,
+    keys_changed: &BTreeSet<storage::Key>,
+) -> Result<(), Rejection> {
+    let registrations =
+        keys_changed.iter().filter(|key| masp_asset::is_asset_key(key));
+    for key in registrations {
+        let pre: Option<(Address, Epoch)> = ctx
+            .read_pre(key)
+            .map_err(|_| Rejection::InvalidAssetRegistration)?;
+        let post: Option<(Address, Epoch)> = ctx
+            .read_post(key)
+            .map_err(|_| Rejection::InvalidAssetRegistration)?;
+        // A registration can be added, but never changed
+        let valid = match (pre, post) {
+            (None, Some((token, epoch))) => {
+                masp_asset::asset_type(epoch, &token).map_or(false, |atype| {
+                    &masp_asset::asset_key(&atype) == key
+                })
+            }
+            _ => false,
+        };
+        if !valid {
+            return Err(Rejection::InvalidAssetRegistration);
+        }
+    }
+    Ok(())
 }
 
 #[validity_predicate]
@@ -43,61 +259,483 @@ fn validate_tx(
         verifiers,
     );
 
-    let signed = SignedTxData::try_from_slice(&tx_data[..]).unwrap();
-    // Also get the data as bytes for the VM.
-    let data = signed.data.as_ref().unwrap().clone();
-    let transfer =
-        token::Transfer::try_from_slice(&signed.data.unwrap()[..]).unwrap();
-
-    if let Some(shielded_tx) = transfer.shielded {
-        let mut transparent_tx_pool = Amount::zero();
-        // The Sapling value balance adds to the transparent tx pool
-        transparent_tx_pool += shielded_tx.value_balance.clone();
-
-        // Handle shielding/transparent input
-        if transfer.source != masp() {
-            // Note that the asset type is timestamped so shields
-            // where the shielded value has an incorrect timestamp
-            // are automatically rejected
-            let (_transp_asset, transp_amt) = convert_amount(
-                ctx.get_block_epoch().unwrap(),
-                &transfer.token,
-                transfer.amount,
-            );
+    match check_asset_registrations(ctx, &keys_changed)
+        .and_then(|()| check_tx(ctx, &tx_data))
+    {
+        // Do the expensive proof verification in the VM at the end.
+        Ok(data) => ctx.verify_masp(data),
+        Err(rejection) => rejection.reject(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    // Use this as `#[test]` annotation to enable logging
+    use namada_tests::log::test;
+    use namada_tests::tx::{self, TestTxEnv};
+    use namada_tests::vp::*;
+    use namada_tx_prelude::StorageWrite;
+    use namada_vp_prelude::key::SigScheme;
+    use proptest::prelude::*;
+
+    use super::*;
+
+    /// A shielding transfer with its epoch, written by `tx-bench corpus
+    /// masp-fixture`
+    const MASP_SHIELDING_FIXTURE: &str = "testdata/vp_masp_shielding.bin";
+
+    /// Sign `data` into tx data like a client would
+    fn signed(data: Option<Vec<u8>>) -> Vec<u8> {
+        let keypair = key::testing::keypair_1();
+        let sig = key::common::SigScheme::sign(
+            &keypair,
+            data.as_deref().unwrap_or_default(),
+        );
+        SignedTxData { data, sig }.try_to_vec().unwrap()
+    }
+
+    /// A transparent transfer of NAM
+    fn transfer(
+        source: Address,
+        target: Address,
+        amount: u64,
+    ) -> token::Transfer {
+        token::Transfer {
+            source,
+            target,
+            token: address::nam(),
+            sub_prefix: None,
+            amount: token::Amount::from(amount),
+            key: None,
+            shielded: None,
+        }
+    }
+
+    /// Run the VP on `tx_data` without any storage changes
+    fn validate(tx_data: Vec<u8>) -> bool {
+        // The VP env must be initialized before calling `validate_tx`
+        vp_host_env::init();
+        let keys_changed: BTreeSet<storage::Key> = BTreeSet::default();
+        let verifiers: BTreeSet<Address> = BTreeSet::default();
+        validate_tx(&CTX, tx_data, masp(), keys_changed, verifiers).unwrap()
+    }
+
+    /// Test that tx data that isn't signed is rejected.
+    #[test]
+    fn test_unsigned_tx_data_rejected() {
+        let tx_data = vec![1, 2, 3];
+        vp_host_env::init();
+        assert_eq!(
+            check_tx(&CTX, &tx_data),
+            Err(Rejection::InvalidSignedTxData)
+        );
+        assert!(!validate(tx_data));
+    }
+
+    /// Test that signed tx data without data is rejected.
+    #[test]
+    fn test_missing_data_rejected() {
+        let tx_data = signed(None);
+        vp_host_env::init();
+        assert_eq!(check_tx(&CTX, &tx_data), Err(Rejection::MissingData));
+        assert!(!validate(tx_data));
+    }
+
+    /// Test that an unshielding transfer without a value balance is
+    /// rejected.
+    #[test]
+    fn test_missing_asset_type_rejected() {
+        let target = address::testing::established_address_1();
+        let transfer = transfer(masp(), target, 1);
+        register_nam([]);
+        assert_eq!(
+            check_transparent_pool(
+                &CTX.pre(),
+                Epoch(0),
+                &transfer,
+                &Amount::zero()
+            ),
+            Err(Rejection::MissingAssetType)
+        );
+    }
+
+    /// Test that a transfer into the MASP without a shielded part is
+    /// rejected.
+    #[test]
+    fn test_missing_shielded_part_rejected() {
+        let source = address::testing::established_address_1();
+        let transfer = transfer(source, masp(), 1);
+        let tx_data = signed(Some(transfer.try_to_vec().unwrap()));
+        vp_host_env::init();
+        assert_eq!(
+            check_tx(&CTX, &tx_data),
+            Err(Rejection::MissingShieldedPart)
+        );
+        assert!(!validate(tx_data));
+    }
+
+    /// Test that a shielding transfer with valid proofs passes the whole
+    /// VP. The transfer is pre-built by `tx-bench corpus masp-fixture`, and
+    /// its proofs are verified with the MASP parameters in their default
+    /// location.
+    ///
+    /// Neither the fixture nor the parameters are in the repository, so the
+    /// test is ignored by default. To run it, write the fixture from
+    /// `wasm/wasm_source` with:
+    ///
+    /// ```text
+    /// tx-bench corpus masp-fixture testdata/vp_masp_shielding.bin
+    /// ```
+    ///
+    /// then run `cargo test -- --ignored`.
+    #[test]
+    #[ignore = "needs the fixture and the MASP parameters"]
+    fn test_shielding_fixture_accepted() {
+        let fixture = std::fs::read(MASP_SHIELDING_FIXTURE)
+            .expect("cannot load the fixture");
+        let (epoch, tx_data) =
+            <(u64, Vec<u8>)>::try_from_slice(&fixture[..]).unwrap();
+
+        // Validate the tx in the epoch it was built in
+        let mut tx_env = TestTxEnv::default();
+        tx_env.wl_storage.storage.block.epoch = Epoch(epoch);
+        vp_host_env::init_from_tx(masp(), tx_env, |_address| {});
+
+        let keys_changed: BTreeSet<storage::Key> = BTreeSet::default();
+        let verifiers: BTreeSet<Address> = BTreeSet::default();
+        assert_eq!(check_tx(&CTX, &tx_data).map(|_| ()), Ok(()));
+        assert!(
+            validate_tx(&CTX, tx_data, masp(), keys_changed, verifiers)
+                .unwrap()
+        );
+    }
+
+    /// Test that shielding exactly the transparent amount in the current
+    /// epoch is accepted.
+    #[test]
+    fn test_shielding_accepted() {
+        let source = address::testing::established_address_1();
+        let transfer = transfer(source, masp(), 100);
+        // Shielding takes value out of the transparent tx pool
+        let asset_type = asset_type(Epoch(3), &transfer.token).unwrap();
+        let value_balance = Amount::from_pair(asset_type, -100).unwrap();
+        assert_eq!(
+            check_transparent_pool(
+                &CTX.pre(),
+                Epoch(3),
+                &transfer,
+                &value_balance
+            ),
+            Ok(())
+        );
+    }
+
+    /// Test that unshielding notes from an earlier epoch is accepted.
+    #[test]
+    fn test_unshielding_accepted() {
+        let target = address::testing::established_address_1();
+        let transfer = transfer(masp(), target, 100);
+        let value_balance = token_component(Epoch(1), 100);
+        register_nam([Epoch(1)]);
+        assert_eq!(
+            check_transparent_pool(
+                &CTX.pre(),
+                Epoch(3),
+                &transfer,
+                &value_balance
+            ),
+            Ok(())
+        );
+    }
+
+    /// Test that unshielding notes from an earlier epoch without a
+    /// registered asset type, e.g. shielded before the registry existed, is
+    /// accepted.
+    #[test]
+    fn test_unregistered_unshielding_accepted() {
+        let target = address::testing::established_address_1();
+        let transfer = transfer(masp(), target, 100);
+        let value_balance = token_component(Epoch(1), 100);
+        register_nam([]);
+        assert_eq!(
+            check_transparent_pool(
+                &CTX.pre(),
+                Epoch(3),
+                &transfer,
+                &value_balance
+            ),
+            Ok(())
+        );
+    }
+
+    /// Test that unshielding more than the value balance is rejected.
+    #[test]
+    fn test_unshielding_over_value_balance_rejected() {
+        let target = address::testing::established_address_1();
+        let transfer = transfer(masp(), target, 101);
+        let value_balance = token_component(Epoch(3), 100);
+        register_nam([Epoch(3)]);
+        assert_eq!(
+            check_transparent_pool(
+                &CTX.pre(),
+                Epoch(3),
+                &transfer,
+                &value_balance
+            ),
+            Err(Rejection::NegativeTransparentPool)
+        );
+    }
 
-            // Non-masp sources add to transparent tx pool
-            transparent_tx_pool += transp_amt;
+    /// Test that an amount beyond the range of MASP amounts is rejected.
+    #[test]
+    fn test_invalid_amount_rejected() {
+        let source = address::testing::established_address_1();
+        let transfer = transfer(source, masp(), u64::MAX);
+        assert_eq!(
+            check_transparent_pool(
+                &CTX.pre(),
+                Epoch(0),
+                &transfer,
+                &Amount::zero()
+            ),
+            Err(Rejection::InvalidAmount)
+        );
+    }
+
+    /// Test that an asset type registration can't be changed.
+    #[test]
+    fn test_asset_registration_change_rejected() {
+        register_nam([Epoch(1)]);
+        let mut vp_env = vp_host_env::take();
+        let asset_type = asset_type(Epoch(1), &address::nam()).unwrap();
+        let key = masp_asset::asset_key(&asset_type);
+        vp_env.wl_storage.write(&key, (address::btc(), Epoch(1))).unwrap();
+        let keys_changed: BTreeSet<storage::Key> =
+            vp_env.all_touched_storage_keys();
+        vp_host_env::set(vp_env);
+        assert_eq!(
+            check_asset_registrations(&CTX, &keys_changed),
+            Err(Rejection::InvalidAssetRegistration)
+        );
+    }
+
+    proptest! {
+        /// Test that a new asset type registration is accepted if and only
+        /// if it hashes to its key.
+        #[test]
+        fn test_asset_registration(
+            epoch in 0..10_u64,
+            registered_epoch in 0..10_u64,
+        ) {
+            let mut tx_env = TestTxEnv::default();
+            tx_env.spawn_accounts([&masp()]);
+            tx_env.commit_genesis();
+            vp_host_env::init_from_tx(masp(), tx_env, |_address| {
+                // Register the asset type of one epoch as another's
+                let asset_type =
+                    asset_type(Epoch(epoch), &address::nam()).unwrap();
+                tx::ctx()
+                    .write(
+                        &masp_asset::asset_key(&asset_type),
+                        (address::nam(), Epoch(registered_epoch)),
+                    )
+                    .unwrap();
+            });
+            let vp_env = vp_host_env::take();
+            let keys_changed: BTreeSet<storage::Key> =
+                vp_env.all_touched_storage_keys();
+            vp_host_env::set(vp_env);
+            let result = check_asset_registrations(&CTX, &keys_changed);
+            if epoch == registered_epoch {
+                prop_assert_eq!(result, Ok(()));
+            } else {
+                prop_assert_eq!(
+                    result,
+                    Err(Rejection::InvalidAssetRegistration)
+                );
+            }
         }
 
-        // Handle unshielding/transparent output
-        if transfer.target != masp() {
-            // Timestamp is derived to allow unshields for older tokens
-            let atype =
-                shielded_tx.value_balance.components().next().unwrap().0;
+        /// Test that signed data that isn't a transfer is rejected.
+        #[test]
+        fn test_invalid_transfer_rejected(data in any::<Vec<u8>>()) {
+            prop_assume!(token::Transfer::try_from_slice(&data[..]).is_err());
+            let tx_data = signed(Some(data));
+            vp_host_env::init();
+            prop_assert_eq!(
+                check_tx(&CTX, &tx_data),
+                Err(Rejection::InvalidTransfer)
+            );
+            prop_assert!(!validate(tx_data));
+        }
 
-            let transp_amt =
-                Amount::from_nonnegative(*atype, u64::from(transfer.amount))
-                    .expect("invalid value or asset type for amount");
+        /// Test that arbitrary tx data doesn't make the VP panic.
+        #[test]
+        fn test_arbitrary_tx_data_rejected(tx_data in any::<Vec<u8>>()) {
+            prop_assume!(SignedTxData::try_from_slice(&tx_data[..]).is_err());
+            prop_assert!(!validate(tx_data));
+        }
 
-            // Non-masp destinations subtract from transparent tx pool
-            transparent_tx_pool -= transp_amt;
+        /// Test that shielding more than the transparent amount, or in
+        /// another epoch, is rejected.
+        #[test]
+        fn test_shielding_over_amount_rejected(
+            amount in 1..1_000_000_u64,
+            excess in 0..1_000_000_u64,
+            epoch in 0..10_u64,
+            shield_epoch in 0..10_u64,
+        ) {
+            let source = address::testing::established_address_1();
+            let transfer = transfer(source, masp(), amount);
+            let shielded = amount + excess;
+            let (asset_type, _) = convert_amount(
+                Epoch(shield_epoch),
+                &transfer.token,
+                token::Amount::from(shielded),
+            )
+            .unwrap();
+            // Shielding takes value out of the transparent tx pool
+            let value_balance =
+                Amount::from_pair(asset_type, -(shielded as i64)).unwrap();
+            let result = check_transparent_pool(
+                &CTX.pre(),
+                Epoch(epoch),
+                &transfer,
+                &value_balance,
+            );
+            if excess == 0 && epoch == shield_epoch {
+                prop_assert_eq!(result, Ok(()));
+            } else {
+                prop_assert_eq!(
+                    result,
+                    Err(Rejection::NegativeTransparentPool)
+                );
+            }
         }
 
-        match transparent_tx_pool.partial_cmp(&Amount::zero()) {
-            None | Some(Ordering::Less) => {
-                debug_log!(
-                    "Transparent transaction value pool must be nonnegative. \
-                     Violation may be caused by transaction being constructed \
-                     in previous epoch. Maybe try again."
+        /// Test that an unshielding transfer is checked against the
+        /// component of its token in a value balance with components of
+        /// other tokens, whichever epoch the token's notes are from and
+        /// whether or not their asset type is registered.
+        #[test]
+        fn test_multi_asset_unshielding(
+            epoch in 0..10_u64,
+            age in 0..10_u64,
+            value in 1..1_000_000_u64,
+            amount in 1..1_000_000_u64,
+            others in arb_other_components(),
+            registered in any::<bool>(),
+        ) {
+            let target = address::testing::established_address_1();
+            let transfer = transfer(masp(), target, amount);
+            let note_epoch = Epoch(epoch.saturating_sub(age));
+            let mut value_balance = others;
+            value_balance += token_component(note_epoch, value);
+            register_nam(registered.then_some(note_epoch));
+            let result = check_transparent_pool(
+                &CTX.pre(),
+                Epoch(epoch),
+                &transfer,
+                &value_balance,
+            );
+            if amount <= value {
+                prop_assert_eq!(result, Ok(()));
+            } else {
+                prop_assert_eq!(
+                    result,
+                    Err(Rejection::NegativeTransparentPool)
                 );
-                // Section 3.4: The remaining value in the transparent
-                // transaction value pool MUST be nonnegative.
-                return reject();
             }
-            _ => {}
+        }
+
+        /// Test that an unshielding transfer with notes of its token from
+        /// several epochs is rejected.
+        #[test]
+        fn test_ambiguous_unshielding_rejected(
+            epoch in 0..10_u64,
+            ages in (0..10_u64, 0..10_u64),
+            values in (1..1_000_000_u64, 1..1_000_000_u64),
+            amount in 1..1_000_000_u64,
+            others in arb_other_components(),
+        ) {
+            let note_epochs =
+                (epoch.saturating_sub(ages.0), epoch.saturating_sub(ages.1));
+            prop_assume!(note_epochs.0 != note_epochs.1);
+            let target = address::testing::established_address_1();
+            let transfer = transfer(masp(), target, amount);
+            let mut value_balance = others;
+            value_balance += token_component(Epoch(note_epochs.0), values.0);
+            value_balance += token_component(Epoch(note_epochs.1), values.1);
+            register_nam([Epoch(note_epochs.0), Epoch(note_epochs.1)]);
+            prop_assert_eq!(
+                check_transparent_pool(
+                    &CTX.pre(),
+                    Epoch(epoch),
+                    &transfer,
+                    &value_balance
+                ),
+                Err(Rejection::AmbiguousAssetType)
+            );
+        }
+
+        /// Test that an unshielding transfer with notes of its token from a
+        /// future epoch is rejected, registered or not.
+        #[test]
+        fn test_future_unshielding_rejected(
+            epoch in 0..10_u64,
+            ahead in 1..10_u64,
+            value in 1..1_000_000_u64,
+            amount in 1..1_000_000_u64,
+            others in arb_other_components(),
+            registered in any::<bool>(),
+        ) {
+            let target = address::testing::established_address_1();
+            let transfer = transfer(masp(), target, amount);
+            let mut value_balance = others;
+            value_balance += token_component(Epoch(epoch + ahead), value);
+            register_nam(registered.then_some(Epoch(epoch + ahead)));
+            prop_assert_eq!(
+                check_transparent_pool(
+                    &CTX.pre(),
+                    Epoch(epoch),
+                    &transfer,
+                    &value_balance
+                ),
+                Err(Rejection::MissingAssetType)
+            );
         }
     }
 
-    // Do the expensive proof verification in the VM at the end.
-    ctx.verify_masp(data)
+    /// Initialize the VP env with the asset types of NAM notes from
+    /// `epochs` registered
+    fn register_nam(epochs: impl IntoIterator<Item = Epoch>) {
+        let mut tx_env = TestTxEnv::default();
+        for epoch in epochs {
+            let asset_type = asset_type(epoch, &address::nam()).unwrap();
+            let key = masp_asset::asset_key(&asset_type);
+            tx_env.wl_storage.write(&key, (address::nam(), epoch)).unwrap();
+        }
+        tx_env.commit_genesis();
+        vp_host_env::init_from_tx(masp(), tx_env, |_address| {});
+    }
+
+    /// A component of `value` NAM from notes of `epoch`
+    fn token_component(epoch: Epoch, value: u64) -> Amount {
+        let asset_type = asset_type(epoch, &address::nam()).unwrap();
+        Amount::from_pair(asset_type, value as i64).unwrap()
+    }
+
+    /// Components of other tokens from arbitrary epochs
+    fn arb_other_components() -> impl Strategy<Value = Amount> {
+        let token = prop_oneof![Just(address::btc()), Just(address::eth())];
+        proptest::collection::vec((token, 0..10_u64, 1..1_000_000_i64), 0..4)
+            .prop_map(|components| {
+                let mut sum = Amount::zero();
+                for (token, epoch, value) in components {
+                    let asset_type = asset_type(Epoch(epoch), &token).unwrap();
+                    sum += Amount::from_pair(asset_type, value).unwrap();
+                }
+                sum
+            })
+    }
 }
@@ -0,0 +1,776 @@
+//! A multisignature user VP.
+//!
+//! This VP works like `vp_user`, except that instead of a signature against
+//! a single public key, the changes that need one have to be authorized by a
+//! threshold of the account's key set (see [`crate::multisig`]): the key
+//! that signed the tx and the keys that approved it beforehand with
+//! `tx_approve_multisig` count towards the threshold, as long as the tx
+//! consumes their approvals.
+//!
+//! An approval can only be written by a tx signed with the key it belongs
+//! to, and deleted by such a tx or by an authorized one. A new key set has
+//! to be valid, i.e. without duplicate keys.
+
+use namada_vp_prelude::address::masp;
+use namada_vp_prelude::storage::KeySeg;
+use namada_vp_prelude::*;
+use once_cell::unsync::Lazy;
+
+use crate::multisig::{self, KeySet};
+
+enum KeyType<'a> {
+    Token(&'a Address),
+    PoS,
+    Vp(&'a Address),
+    Masp,
+    GovernanceVote(&'a Address),
+    KeySet(&'a Address),
+    Approval(&'a Address, u8),
+    Unknown,
+}
+
+impl<'a> From<&'a storage::Key> for KeyType<'a> {
+    fn from(key: &'a storage::Key) -> KeyType<'a> {
+        if let Some(address) = token::is_any_token_balance_key(key) {
+            Self::Token(address)
+        } else if let Some((_, address)) =
+            token::is_any_multitoken_balance_key(key)
+        {
+            Self::Token(address)
+        } else if proof_of_stake::is_pos_key(key) {
+            Self::PoS
+        } else if gov_storage::is_vote_key(key) {
+            let voter_address = gov_storage::get_voter_address(key);
+            if let Some(address) = voter_address {
+                Self::GovernanceVote(address)
+            } else {
+                Self::Unknown
+            }
+        } else if let Some(address) = key.is_validity_predicate() {
+            Self::Vp(address)
+        } else if token::is_masp_key(key) {
+            Self::Masp
+        } else if let Some(address) = multisig::is_key_set_key(key) {
+            Self::KeySet(address)
+        } else if let Some((address, index)) = multisig::is_approval_key(key) {
+            Self::Approval(address, index)
+        } else {
+            Self::Unknown
+        }
+    }
+}
+
+#[validity_predicate]
+fn validate_tx(
+    ctx: &Ctx,
+    tx_data: Vec<u8>,
+    addr: Address,
+    keys_changed: BTreeSet<storage::Key>,
+    verifiers: BTreeSet<Address>,
+) -> VpResult {
+    debug_log!(
+        "vp_multisig called with user addr: {}, key_changed: {:?}, \
+         verifiers: {:?}",
+        addr,
+        keys_changed,
+        verifiers
+    );
+
+    let signed_tx_data =
+        Lazy::new(|| SignedTxData::try_from_slice(&tx_data[..]));
+
+    // The key set before the tx, so that changing it has to be authorized
+    // by the keys it replaces
+    let key_set = Lazy::new(|| {
+        ctx.read_pre::<KeySet>(&multisig::key_set_key(&addr))
+            .ok()
+            .flatten()
+    });
+
+    // Indices of the keys that signed the tx
+    let signers = Lazy::new(|| match (&*signed_tx_data, &*key_set) {
+        (Ok(signed_tx_data), Some(key_set)) => key_set
+            .public_keys
+            .iter()
+            .enumerate()
+            .filter(|(_, pk)| {
+                matches!(
+                    ctx.verify_tx_signature(pk, &signed_tx_data.sig),
+                    Ok(true)
+                )
+            })
+            .map(|(index, _)| index as u8)
+            .collect(),
+        _ => BTreeSet::new(),
+    });
+
+    let authorized = Lazy::new(|| match (&*signed_tx_data, &*key_set) {
+        (Ok(signed_tx_data), Some(key_set)) => {
+            let code_hash = match ctx.get_tx_code_hash() {
+                Ok(code_hash) => code_hash,
+                Err(_) => return false,
+            };
+            let data_hash = multisig::data_hash(
+                signed_tx_data.data.as_deref().unwrap_or_default(),
+            );
+            // An approval only counts if it's for this tx's code and the tx
+            // consumes it
+            let consumed = |index: u8| {
+                let key = multisig::approval_key(&addr, &data_hash, index);
+                let approved = ctx.read_pre::<hash::Hash>(&key);
+                matches!(approved, Ok(Some(approved)) if approved == code_hash)
+                    && matches!(ctx.has_key_post(&key), Ok(false))
+            };
+            let approvals = (0..key_set.public_keys.len() as u8)
+                .filter(|index| signers.contains(index) || consumed(*index))
+                .count();
+            debug_log!(
+                "{} of {} required approvals",
+                approvals,
+                key_set.threshold
+            );
+            approvals >= key_set.threshold as usize
+        }
+        _ => false,
+    });
+
+    if !is_valid_tx(ctx, &tx_data)? {
+        return reject();
+    }
+
+    for key in keys_changed.iter() {
+        let key_type: KeyType = key.into();
+        let is_valid = match key_type {
+            KeyType::Token(owner) => {
+                if owner == &addr {
+                    let pre: token::Amount =
+                        ctx.read_pre(key)?.unwrap_or_default();
+                    let post: token::Amount =
+                        ctx.read_post(key)?.unwrap_or_default();
+                    let change = post.change() - pre.change();
+                    // debit has to be authorized, credit doesn't
+                    let valid = change >= 0 || addr == masp() || *authorized;
+                    debug_log!(
+                        "token key: {}, change: {}, authorized: {}, valid \
+                         modification: {}",
+                        key,
+                        change,
+                        *authorized,
+                        valid
+                    );
+                    valid
+                } else {
+                    debug_log!(
+                        "This address ({}) is not of owner ({}) of token key: \
+                         {}",
+                        addr,
+                        owner,
+                        key
+                    );
+                    // If this is not the owner, allow any change
+                    true
+                }
+            }
+            KeyType::PoS => {
+                // Allow the account to be used in PoS
+                let bond_id = proof_of_stake::is_bond_key(key)
+                    .map(|(bond_id, _)| bond_id)
+                    .or_else(|| {
+                        proof_of_stake::is_unbond_key(key)
+                            .map(|(bond_id, _, _)| bond_id)
+                    });
+                let valid = match bond_id {
+                    Some(bond_id) => {
+                        // Bonds and unbonds changes for this address
+                        // must be authorized
+                        bond_id.source != addr || *authorized
+                    }
+                    None => {
+                        // Any other PoS changes are allowed without signature
+                        true
+                    }
+                };
+                debug_log!(
+                    "PoS key {} {}",
+                    key,
+                    if valid { "accepted" } else { "rejected" }
+                );
+                valid
+            }
+            KeyType::GovernanceVote(voter) => {
+                if voter == &addr {
+                    *authorized
+                } else {
+                    true
+                }
+            }
+            KeyType::Vp(owner) => {
+                let has_post: bool = ctx.has_key_post(key)?;
+                if owner == &addr {
+                    if has_post {
+                        let vp: Vec<u8> = ctx.read_bytes_post(key)?.unwrap();
+                        *authorized && is_vp_whitelisted(ctx, &vp)?
+                    } else {
+                        false
+                    }
+                } else {
+                    let vp: Vec<u8> = ctx.read_bytes_post(key)?.unwrap();
+                    is_vp_whitelisted(ctx, &vp)?
+                }
+            }
+            KeyType::Masp => true,
+            KeyType::KeySet(owner) => {
+                if owner == &addr {
+                    let key_set: Option<KeySet> = ctx.read_post(key)?;
+                    *authorized
+                        && key_set
+                            .map(|key_set| key_set.is_valid())
+                            .unwrap_or(false)
+                } else {
+                    true
+                }
+            }
+            KeyType::Approval(owner, index) => {
+                if owner == &addr {
+                    // Only the approving key can give or take back its
+                    // approval, but an authorized tx consumes the approvals
+                    // it counts
+                    signers.contains(&index)
+                        || (!ctx.has_key_post(key)? && *authorized)
+                } else {
+                    true
+                }
+            }
+            KeyType::Unknown => {
+                if key.segments.get(0) == Some(&addr.to_db_key()) {
+                    // Unknown changes to this address space, including the
+                    // key set, have to be authorized
+                    *authorized
+                } else {
+                    // Unknown changes anywhere else are permitted
+                    true
+                }
+            }
+        };
+        if !is_valid {
+            debug_log!("key {} modification failed vp", key);
+            return reject();
+        }
+    }
+
+    accept()
+}
+
+#[cfg(test)]
+mod tests {
+    use address::testing::arb_non_internal_address;
+    use namada::proto::Tx;
+    use namada::types::chain::ChainId;
+    // Use this as `#[test]` annotation to enable logging
+    use namada_tests::log::test;
+    use namada_tests::tx::{self, tx_host_env, TestTxEnv};
+    use namada_tests::vp::vp_host_env::storage::Key;
+    use namada_tests::vp::*;
+    use namada_tx_prelude::{StorageWrite, TxEnv};
+    use namada_vp_prelude::key::RefTo;
+    use proptest::prelude::*;
+    use storage::testing::arb_account_storage_key_no_vp;
+
+    use super::*;
+
+    /// The data of the txs in the tests, which the approvals are for
+    const TX_DATA: &[u8] = b"multisig";
+
+    /// A tx with [`TX_DATA`] and its code hash
+    fn tx_to_approve() -> (Tx, hash::Hash) {
+        let tx =
+            Tx::new(vec![], Some(TX_DATA.to_vec()), ChainId::default(), None);
+        let code_hash = hash::Hash::sha256(&tx.code);
+        (tx, code_hash)
+    }
+
+    /// Give `owner` the key set of the `keypairs` with the `threshold` and
+    /// the approvals of the keys with the `approvals` indices for the tx
+    /// with `code_hash` and `data`
+    fn init_account(
+        tx_env: &mut TestTxEnv,
+        owner: &Address,
+        keypairs: &[key::common::SecretKey],
+        threshold: u8,
+        code_hash: &hash::Hash,
+        data: &[u8],
+        approvals: &[u8],
+    ) {
+        let key_set = KeySet {
+            public_keys: keypairs.iter().map(|sk| sk.ref_to()).collect(),
+            threshold,
+        };
+        tx_env
+            .wl_storage
+            .write(&multisig::key_set_key(owner), key_set)
+            .unwrap();
+        let data_hash = multisig::data_hash(data);
+        for index in approvals {
+            tx_env
+                .wl_storage
+                .write(
+                    &multisig::approval_key(owner, &data_hash, *index),
+                    code_hash,
+                )
+                .unwrap();
+        }
+    }
+
+    /// Transfer the whole `amount` from the VP owner to `target` in a tx
+    /// signed with `keypair`, consuming the approvals of the tx if
+    /// `consume`, and run the VP on it
+    fn signed_debit_transfer(
+        mut tx_env: TestTxEnv,
+        vp_owner: &Address,
+        keypair: &key::common::SecretKey,
+        amount: token::Amount,
+        consume: bool,
+    ) -> bool {
+        let target = address::testing::established_address_2();
+        let token = address::nam();
+
+        // Spawn the accounts to be able to modify their storage
+        tx_env.spawn_accounts([vp_owner, &target, &token]);
+
+        // Credit the tokens to the VP owner before running the transaction to
+        // be able to transfer from it
+        tx_env.credit_tokens(vp_owner, &token, None, amount);
+        tx_env.commit_genesis();
+
+        // Initialize VP environment from a transaction
+        vp_host_env::init_from_tx(vp_owner.clone(), tx_env, |address| {
+            // Apply transfer in a transaction
+            tx_host_env::token::transfer(
+                tx::ctx(),
+                address,
+                &target,
+                &token,
+                None,
+                amount,
+                &None,
+                &None,
+            )
+            .unwrap();
+            if consume {
+                multisig::consume_approvals(tx::ctx(), address, TX_DATA)
+                    .unwrap();
+            }
+        });
+
+        let mut vp_env = vp_host_env::take();
+        let tx = vp_env.tx.clone();
+        let signed_tx = tx.sign(keypair);
+        let tx_data: Vec<u8> = signed_tx.data.as_ref().cloned().unwrap();
+        vp_env.tx = signed_tx;
+        let keys_changed: BTreeSet<storage::Key> =
+            vp_env.all_touched_storage_keys();
+        let verifiers: BTreeSet<Address> = BTreeSet::default();
+        vp_host_env::set(vp_env);
+        validate_tx(&CTX, tx_data, vp_owner.clone(), keys_changed, verifiers)
+            .unwrap()
+    }
+
+    /// Test that no-op transaction (i.e. no storage modifications) accepted.
+    #[test]
+    fn test_no_op_transaction() {
+        let tx_data: Vec<u8> = vec![];
+        let addr: Address = address::testing::established_address_1();
+        let keys_changed: BTreeSet<storage::Key> = BTreeSet::default();
+        let verifiers: BTreeSet<Address> = BTreeSet::default();
+
+        // The VP env must be initialized before calling `validate_tx`
+        vp_host_env::init();
+
+        assert!(
+            validate_tx(&CTX, tx_data, addr, keys_changed, verifiers).unwrap()
+        );
+    }
+
+    /// Test that a debit transfer signed by a single key of a 2-of-2 account
+    /// is rejected.
+    #[test]
+    fn test_debit_transfer_below_threshold_rejected() {
+        let mut tx_env = TestTxEnv::default();
+        let vp_owner = address::testing::established_address_1();
+        let keypairs = [key::testing::keypair_1(), key::testing::keypair_2()];
+        let (tx, code_hash) = tx_to_approve();
+        tx_env.tx = tx;
+        init_account(
+            &mut tx_env,
+            &vp_owner,
+            &keypairs,
+            2,
+            &code_hash,
+            TX_DATA,
+            &[],
+        );
+
+        assert!(!signed_debit_transfer(
+            tx_env,
+            &vp_owner,
+            &keypairs[0],
+            token::Amount::from(10_098_123),
+            true,
+        ));
+    }
+
+    /// Test that a debit transfer signed by one key of a 2-of-2 account and
+    /// approved by the other is accepted.
+    #[test]
+    fn test_approved_debit_transfer_accepted() {
+        let mut tx_env = TestTxEnv::default();
+        let vp_owner = address::testing::established_address_1();
+        let keypairs = [key::testing::keypair_1(), key::testing::keypair_2()];
+        let (tx, code_hash) = tx_to_approve();
+        tx_env.tx = tx;
+        init_account(
+            &mut tx_env,
+            &vp_owner,
+            &keypairs,
+            2,
+            &code_hash,
+            TX_DATA,
+            &[1],
+        );
+
+        assert!(signed_debit_transfer(
+            tx_env,
+            &vp_owner,
+            &keypairs[0],
+            token::Amount::from(10_098_123),
+            true,
+        ));
+    }
+
+    /// Test that an approval only counts if the tx consumes it, so that it
+    /// can't be used again.
+    #[test]
+    fn test_approved_debit_transfer_without_consuming_rejected() {
+        let mut tx_env = TestTxEnv::default();
+        let vp_owner = address::testing::established_address_1();
+        let keypairs = [key::testing::keypair_1(), key::testing::keypair_2()];
+        let (tx, code_hash) = tx_to_approve();
+        tx_env.tx = tx;
+        init_account(
+            &mut tx_env,
+            &vp_owner,
+            &keypairs,
+            2,
+            &code_hash,
+            TX_DATA,
+            &[1],
+        );
+
+        assert!(!signed_debit_transfer(
+            tx_env,
+            &vp_owner,
+            &keypairs[0],
+            token::Amount::from(10_098_123),
+            false,
+        ));
+    }
+
+    /// Test that an approval of the same data with another code doesn't
+    /// count towards the threshold.
+    #[test]
+    fn test_debit_transfer_approved_for_other_code_rejected() {
+        let mut tx_env = TestTxEnv::default();
+        let vp_owner = address::testing::established_address_1();
+        let keypairs = [key::testing::keypair_1(), key::testing::keypair_2()];
+        let (tx, _code_hash) = tx_to_approve();
+        tx_env.tx = tx;
+        let other_code_hash = hash::Hash::sha256(b"other code");
+        init_account(
+            &mut tx_env,
+            &vp_owner,
+            &keypairs,
+            2,
+            &other_code_hash,
+            TX_DATA,
+            &[1],
+        );
+
+        assert!(!signed_debit_transfer(
+            tx_env,
+            &vp_owner,
+            &keypairs[0],
+            token::Amount::from(10_098_123),
+            true,
+        ));
+    }
+
+    /// Test that an approval of another tx doesn't count towards the
+    /// threshold.
+    #[test]
+    fn test_debit_transfer_approved_for_other_tx_rejected() {
+        let mut tx_env = TestTxEnv::default();
+        let vp_owner = address::testing::established_address_1();
+        let keypairs = [key::testing::keypair_1(), key::testing::keypair_2()];
+        let (tx, code_hash) = tx_to_approve();
+        tx_env.tx = tx;
+        init_account(
+            &mut tx_env,
+            &vp_owner,
+            &keypairs,
+            2,
+            &code_hash,
+            b"other tx",
+            &[1],
+        );
+
+        assert!(!signed_debit_transfer(
+            tx_env,
+            &vp_owner,
+            &keypairs[0],
+            token::Amount::from(10_098_123),
+            true,
+        ));
+    }
+
+    /// Test that a key can only write its own approval.
+    #[test]
+    fn test_approval_by_other_key_rejected() {
+        let vp_owner = address::testing::established_address_1();
+        let keypairs = [key::testing::keypair_1(), key::testing::keypair_2()];
+        let code_hash = hash::Hash::sha256(b"approved code");
+        let data_hash = multisig::data_hash(b"approved data");
+
+        for (signer, accepted) in [(1, true), (0, false)] {
+            let mut tx_env = TestTxEnv::default();
+            init_account(
+                &mut tx_env,
+                &vp_owner,
+                &keypairs,
+                2,
+                &code_hash,
+                TX_DATA,
+                &[],
+            );
+            tx_env.spawn_accounts([&vp_owner]);
+            tx_env.commit_genesis();
+
+            // Initialize VP environment from a transaction
+            vp_host_env::init_from_tx(vp_owner.clone(), tx_env, |address| {
+                // Approve with the second key in a transaction
+                tx::ctx()
+                    .write(
+                        &multisig::approval_key(address, &data_hash, 1),
+                        code_hash,
+                    )
+                    .unwrap();
+            });
+
+            let mut vp_env = vp_host_env::take();
+            let tx = vp_env.tx.clone();
+            let signed_tx = tx.sign(&keypairs[signer]);
+            let tx_data: Vec<u8> = signed_tx.data.as_ref().cloned().unwrap();
+            vp_env.tx = signed_tx;
+            let keys_changed: BTreeSet<storage::Key> =
+                vp_env.all_touched_storage_keys();
+            let verifiers: BTreeSet<Address> = BTreeSet::default();
+            vp_host_env::set(vp_env);
+            assert_eq!(
+                validate_tx(
+                    &CTX,
+                    tx_data,
+                    vp_owner.clone(),
+                    keys_changed,
+                    verifiers
+                )
+                .unwrap(),
+                accepted
+            );
+        }
+    }
+
+    /// Test that an authorized tx can't give the account a key set with
+    /// duplicate keys.
+    #[test]
+    fn test_key_set_with_duplicate_keys_rejected() {
+        let vp_owner = address::testing::established_address_1();
+        let keypair = key::testing::keypair_1();
+        let other_pk = key::testing::keypair_2().ref_to();
+
+        for (public_keys, accepted) in [
+            (vec![keypair.ref_to(), other_pk.clone()], true),
+            (vec![other_pk.clone(), other_pk.clone()], false),
+        ] {
+            let mut tx_env = TestTxEnv::default();
+            let (tx, code_hash) = tx_to_approve();
+            tx_env.tx = tx;
+            init_account(
+                &mut tx_env,
+                &vp_owner,
+                &[keypair.clone()],
+                1,
+                &code_hash,
+                TX_DATA,
+                &[],
+            );
+            tx_env.spawn_accounts([&vp_owner]);
+            tx_env.commit_genesis();
+
+            // Initialize VP environment from a transaction
+            vp_host_env::init_from_tx(vp_owner.clone(), tx_env, |address| {
+                // Replace the key set in a transaction
+                let key_set = KeySet {
+                    public_keys,
+                    threshold: 2,
+                };
+                tx::ctx()
+                    .write(&multisig::key_set_key(address), key_set)
+                    .unwrap();
+            });
+
+            let mut vp_env = vp_host_env::take();
+            let tx = vp_env.tx.clone();
+            let signed_tx = tx.sign(&keypair);
+            let tx_data: Vec<u8> = signed_tx.data.as_ref().cloned().unwrap();
+            vp_env.tx = signed_tx;
+            let keys_changed: BTreeSet<storage::Key> =
+                vp_env.all_touched_storage_keys();
+            let verifiers: BTreeSet<Address> = BTreeSet::default();
+            vp_host_env::set(vp_env);
+            assert_eq!(
+                validate_tx(
+                    &CTX,
+                    tx_data,
+                    vp_owner.clone(),
+                    keys_changed,
+                    verifiers
+                )
+                .unwrap(),
+                accepted
+            );
+        }
+    }
+
+    prop_compose! {
+        /// Generates a key set of up to 5 keys with a valid threshold and
+        /// the indices of the keys that approve the tx, other than the first
+        /// one that signs it.
+        fn arb_key_set_with_approvals()
+            (keypairs in proptest::collection::vec(
+                key::testing::arb_common_keypair(),
+                1..=5,
+            ))
+            (threshold in 1..=keypairs.len() as u8,
+            approvals in proptest::sample::subsequence(
+                (1..keypairs.len() as u8).collect::<Vec<_>>(),
+                0..keypairs.len(),
+            ),
+            keypairs in Just(keypairs))
+        -> (Vec<key::common::SecretKey>, u8, Vec<u8>) {
+            (keypairs, threshold, approvals)
+        }
+    }
+
+    proptest! {
+        /// Test that a signed debit transfer is accepted if and only if the
+        /// signature and the approvals reach the threshold.
+        #[test]
+        fn test_debit_transfer_threshold(
+            (keypairs, threshold, approvals) in arb_key_set_with_approvals(),
+        ) {
+            let mut tx_env = TestTxEnv::default();
+            let vp_owner = address::testing::established_address_1();
+            let (tx, code_hash) = tx_to_approve();
+            tx_env.tx = tx;
+            init_account(
+                &mut tx_env,
+                &vp_owner,
+                &keypairs,
+                threshold,
+                &code_hash,
+                TX_DATA,
+                &approvals,
+            );
+
+            let accepted = signed_debit_transfer(
+                tx_env,
+                &vp_owner,
+                &keypairs[0],
+                token::Amount::from(10_098_123),
+                true,
+            );
+            prop_assert_eq!(accepted, 1 + approvals.len() >= threshold as usize);
+        }
+    }
+
+    prop_compose! {
+        /// Generates an account address and a storage key inside its storage.
+        fn arb_account_storage_subspace_key()
+            // Generate an address
+            (address in arb_non_internal_address())
+            // Generate a storage key other than its VP key (VP cannot be
+            // modified directly via `write`, it has to be modified via
+            // `tx::update_validity_predicate`.
+            (storage_key in arb_account_storage_key_no_vp(address.clone()),
+            // Use the generated address too
+            address in Just(address))
+        -> (Address, Key) {
+            (address, storage_key)
+        }
+    }
+
+    proptest! {
+        /// Test that a tx signed by a single key of a 2-of-2 account that
+        /// performs arbitrary storage writes or deletes to the account is
+        /// rejected.
+        #[test]
+        fn test_below_threshold_arb_storage_write_rejected(
+            (vp_owner, storage_key) in arb_account_storage_subspace_key(),
+            // Generate bytes to write. If `None`, delete from the key instead
+            storage_value in any::<Option<Vec<u8>>>(),
+        ) {
+            // Initialize a tx environment
+            let mut tx_env = TestTxEnv::default();
+
+            let keypairs =
+                [key::testing::keypair_1(), key::testing::keypair_2()];
+            let (tx, code_hash) = tx_to_approve();
+            tx_env.tx = tx;
+            init_account(
+                &mut tx_env,
+                &vp_owner,
+                &keypairs,
+                2,
+                &code_hash,
+                TX_DATA,
+                &[],
+            );
+
+            // Spawn all the accounts in the storage key to be able to modify
+            // their storage
+            let storage_key_addresses = storage_key.find_addresses();
+            tx_env.spawn_accounts(storage_key_addresses);
+            tx_env.commit_genesis();
+
+            // Initialize VP environment from a transaction
+            vp_host_env::init_from_tx(vp_owner.clone(), tx_env, |_address| {
+                // Write or delete some data in the transaction
+                if let Some(value) = &storage_value {
+                    tx::ctx().write(&storage_key, value).unwrap();
+                } else {
+                    tx::ctx().delete(&storage_key).unwrap();
+                }
+            });
+
+            let mut vp_env = vp_host_env::take();
+            let tx = vp_env.tx.clone();
+            let signed_tx = tx.sign(&keypairs[0]);
+            let tx_data: Vec<u8> = signed_tx.data.as_ref().cloned().unwrap();
+            vp_env.tx = signed_tx;
+            let keys_changed: BTreeSet<storage::Key> =
+                vp_env.all_touched_storage_keys();
+            let verifiers: BTreeSet<Address> = BTreeSet::default();
+            vp_host_env::set(vp_env);
+            assert!(!validate_tx(&CTX, tx_data, vp_owner, keys_changed, verifiers).unwrap());
+        }
+    }
+}
@@ -1,7 +1,17 @@
 //! A VP for a fungible token. Enforces that the total supply is unchanged in a
 //! transaction that moves balance(s).
+//!
+//! A token that tracks its supply (see [`crate::supply`]) can also be minted
+//! and burned by txs that update the supply by the same amount. Minting has
+//! to be signed by the mint authority and can't raise the supply above the
+//! cap. The supply, the cap and the authority are set when the token is
+//! initialized, and only the authority can hand its role over later.
 
+use namada_vp_prelude::storage::KeySeg;
 use namada_vp_prelude::*;
+use once_cell::unsync::Lazy;
+
+use crate::supply;
 
 #[validity_predicate]
 fn validate_tx(
@@ -32,5 +42,375 @@ fn validate_tx(
         }
     }
 
-    token::vp(ctx, &addr, &keys_changed, &verifiers)
+    let supply_key = supply::supply_key(&addr);
+    let supply_cap_key = supply::supply_cap_key(&addr);
+    let mint_authority_key = supply::mint_authority_key(&addr);
+    if ![&supply_key, &supply_cap_key, &mint_authority_key]
+        .into_iter()
+        .any(|key| keys_changed.contains(key))
+    {
+        return token::vp(ctx, &addr, &keys_changed, &verifiers);
+    }
+
+    // The supply keys can only be set up when the token is initialized
+    let is_new = !ctx.has_key_pre(&storage::Key::validity_predicate(&addr))?;
+    let mint_authority: Option<Address> = if is_new {
+        ctx.read_post(&mint_authority_key)?
+    } else {
+        ctx.read_pre(&mint_authority_key)?
+    };
+    let signed_by_authority = Lazy::new(|| {
+        match (&mint_authority, SignedTxData::try_from_slice(&tx_data[..])) {
+            (Some(authority), Ok(signed_tx_data)) => {
+                let pk = key::get(ctx, authority);
+                match pk {
+                    Ok(Some(pk)) => {
+                        matches!(
+                            ctx.verify_tx_signature(&pk, &signed_tx_data.sig),
+                            Ok(true)
+                        )
+                    }
+                    _ => false,
+                }
+            }
+            _ => false,
+        }
+    });
+
+    let mut change: token::Change = 0;
+    for key in keys_changed.iter() {
+        let owner = token::is_balance_key(&addr, key).or_else(|| {
+            token::is_multitoken_balance_key(&addr, key).map(|(_, owner)| owner)
+        });
+        let is_valid = if let Some(owner) = owner {
+            let pre: token::Amount = ctx.read_pre(key)?.unwrap_or_default();
+            let post: token::Amount = ctx.read_post(key)?.unwrap_or_default();
+            let this_change = post.change() - pre.change();
+            change += this_change;
+            // make sure that the spender approved the transaction
+            !matches!(owner, Address::Internal(_))
+                && (this_change >= 0 || verifiers.contains(owner))
+        } else if key == &supply_key || key.is_validity_predicate().is_some() {
+            // The supply is checked against the balances below
+            true
+        } else if key == &supply_cap_key {
+            is_new
+        } else if key == &mint_authority_key {
+            is_new || *signed_by_authority
+        } else {
+            // Unknown changes to this address space are disallowed, but
+            // unknown changes anywhere else are permitted
+            key.segments.get(0) != Some(&addr.to_db_key())
+        };
+        if !is_valid {
+            debug_log!("key {} modification failed vp", key);
+            return reject();
+        }
+    }
+
+    let pre_supply: Option<token::Amount> = ctx.read_pre(&supply_key)?;
+    let post_supply: Option<token::Amount> = ctx.read_post(&supply_key)?;
+    let supply_cap: Option<token::Amount> = ctx.read_post(&supply_cap_key)?;
+    let (pre_supply, post_supply) = match (pre_supply, post_supply) {
+        (Some(pre), Some(post)) => (pre, post),
+        (None, Some(post)) if is_new => (token::Amount::default(), post),
+        _ => {
+            debug_log!("token {} doesn't track its supply", addr);
+            return reject();
+        }
+    };
+    let valid = post_supply.change() - pre_supply.change() == change
+        && (change <= 0 || *signed_by_authority)
+        && supply_cap.map_or(true, |cap| post_supply <= cap);
+    debug_log!(
+        "supply: {} -> {}, balance change: {}, cap: {:?}, valid: {}",
+        pre_supply,
+        post_supply,
+        change,
+        supply_cap,
+        valid
+    );
+    Ok(valid)
+}
+
+#[cfg(test)]
+mod tests {
+    // Use this as `#[test]` annotation to enable logging
+    use namada_tests::log::test;
+    use namada_tests::tx::{self, tx_host_env, TestTxEnv};
+    use namada_tests::vp::*;
+    use namada_tx_prelude::{StorageRead, StorageWrite};
+    use namada_vp_prelude::key::RefTo;
+    use proptest::prelude::*;
+
+    use super::*;
+
+    /// The supply the test token is initialized with
+    const SUPPLY: u64 = 1_000;
+    /// The supply cap of the test token
+    const CAP: u64 = 10_000;
+
+    fn token() -> Address {
+        address::testing::established_address_1()
+    }
+
+    fn authority() -> Address {
+        address::testing::established_address_2()
+    }
+
+    fn holder() -> Address {
+        address::testing::established_address_3()
+    }
+
+    /// Initialize a token with [`authority`] as the mint authority and a
+    /// supply of [`SUPPLY`] held by [`holder`], and spawn the accounts of
+    /// the `owners`
+    fn init_token(owners: &[Address]) -> TestTxEnv {
+        let mut tx_env = TestTxEnv::default();
+        let token = token();
+        let supply = token::Amount::whole(SUPPLY);
+
+        // Spawn the accounts to be able to modify their storage
+        tx_env.spawn_accounts([&token, &authority(), &holder()]);
+        tx_env.spawn_accounts(owners);
+        tx_env.write_public_key(
+            &authority(),
+            &key::testing::keypair_1().ref_to(),
+        );
+        tx_env.credit_tokens(&holder(), &token, None, supply);
+        let storage = &mut tx_env.wl_storage;
+        storage.write(&supply::supply_key(&token), supply).unwrap();
+        storage
+            .write(&supply::supply_cap_key(&token), token::Amount::whole(CAP))
+            .unwrap();
+        storage
+            .write(&supply::mint_authority_key(&token), authority())
+            .unwrap();
+        tx_env.commit_genesis();
+        tx_env
+    }
+
+    /// Add `change` to the amount under `key` in the tx
+    fn add(key: &storage::Key, change: token::Change) {
+        let amount: token::Amount =
+            tx::ctx().read(key).unwrap().unwrap_or_default();
+        let amount = token::Amount::from((amount.change() + change) as u64);
+        tx::ctx().write(key, amount).unwrap();
+    }
+
+    /// Set up the VP environment of the token with the changes of `apply`,
+    /// in a tx signed with `signer`, returning the tx data and the changed
+    /// keys
+    fn init_vp_env(
+        tx_env: TestTxEnv,
+        signer: Option<key::common::SecretKey>,
+        apply: impl FnOnce(&Address),
+    ) -> (Vec<u8>, BTreeSet<storage::Key>) {
+        // Initialize VP environment from a transaction
+        vp_host_env::init_from_tx(token(), tx_env, apply);
+
+        let mut vp_env = vp_host_env::take();
+        let tx_data: Vec<u8> = match signer {
+            Some(signer) => {
+                let signed_tx = vp_env.tx.clone().sign(&signer);
+                let tx_data = signed_tx.data.as_ref().cloned().unwrap();
+                vp_env.tx = signed_tx;
+                tx_data
+            }
+            None => vec![],
+        };
+        let keys_changed: BTreeSet<storage::Key> =
+            vp_env.all_touched_storage_keys();
+        vp_host_env::set(vp_env);
+        (tx_data, keys_changed)
+    }
+
+    /// Run the VP of the token on the changes of `apply`, in a tx signed
+    /// with `signer` and verified by every address whose storage it changes
+    fn validate(
+        tx_env: TestTxEnv,
+        signer: Option<key::common::SecretKey>,
+        apply: impl FnOnce(&Address),
+    ) -> bool {
+        let (tx_data, keys_changed) = init_vp_env(tx_env, signer, apply);
+        let verifiers: BTreeSet<Address> = keys_changed
+            .iter()
+            .flat_map(|key| key.find_addresses())
+            .collect();
+        validate_tx(&CTX, tx_data, token(), keys_changed, verifiers).unwrap()
+    }
+
+    /// Test that no-op transaction (i.e. no storage modifications) accepted.
+    #[test]
+    fn test_no_op_transaction() {
+        let tx_data: Vec<u8> = vec![];
+        let addr: Address = address::testing::established_address_1();
+        let keys_changed: BTreeSet<storage::Key> = BTreeSet::default();
+        let verifiers: BTreeSet<Address> = BTreeSet::default();
+
+        // The VP env must be initialized before calling `validate_tx`
+        vp_host_env::init();
+
+        assert!(
+            validate_tx(&CTX, tx_data, addr, keys_changed, verifiers).unwrap()
+        );
+    }
+
+    /// Test that minting without the authority's signature is rejected.
+    #[test]
+    fn test_unsigned_mint_rejected() {
+        let tx_env = init_token(&[]);
+        let signer = key::testing::keypair_2();
+        let amount = token::Amount::whole(1).change();
+        assert!(!validate(tx_env, Some(signer), |token| {
+            add(&token::balance_key(token, &holder()), amount);
+            add(&supply::supply_key(token), amount);
+        }));
+    }
+
+    /// Test that minting without updating the supply is rejected.
+    #[test]
+    fn test_mint_without_supply_rejected() {
+        let tx_env = init_token(&[]);
+        let signer = key::testing::keypair_1();
+        let amount = token::Amount::whole(1).change();
+        assert!(!validate(tx_env, Some(signer), |token| {
+            add(&token::balance_key(token, &holder()), amount);
+        }));
+    }
+
+    /// Test that the holder can burn tokens without the authority.
+    #[test]
+    fn test_burn_accepted() {
+        let tx_env = init_token(&[]);
+        let amount = token::Amount::whole(SUPPLY).change();
+        assert!(validate(tx_env, None, |token| {
+            add(&token::balance_key(token, &holder()), -amount);
+            add(&supply::supply_key(token), -amount);
+        }));
+    }
+
+    /// Test that the cap can't be changed, even by the authority.
+    #[test]
+    fn test_cap_change_rejected() {
+        let tx_env = init_token(&[]);
+        let signer = key::testing::keypair_1();
+        let amount = token::Amount::whole(1).change();
+        assert!(!validate(tx_env, Some(signer), |token| {
+            add(&supply::supply_cap_key(token), amount);
+        }));
+    }
+
+    /// Test that a transfer between two owners is accepted.
+    #[test]
+    fn test_transfer_accepted() {
+        let target = address::testing::gen_established_address();
+        let tx_env = init_token(&[target.clone()]);
+        let amount = token::Amount::whole(SUPPLY);
+        assert!(validate(tx_env, None, |token| {
+            tx_host_env::token::transfer(
+                tx::ctx(),
+                &holder(),
+                &target,
+                token,
+                None,
+                amount,
+                &None,
+                &None,
+            )
+            .unwrap();
+        }));
+    }
+
+    /// Test that a debit that the owner didn't verify is rejected.
+    #[test]
+    fn test_unverified_debit_rejected() {
+        let target = address::testing::gen_established_address();
+        let tx_env = init_token(&[target.clone()]);
+        let amount = token::Amount::whole(1);
+        let (tx_data, keys_changed) = init_vp_env(tx_env, None, |token| {
+            tx_host_env::token::transfer(
+                tx::ctx(),
+                &holder(),
+                &target,
+                token,
+                None,
+                amount,
+                &None,
+                &None,
+            )
+            .unwrap();
+        });
+        let verifiers: BTreeSet<Address> = [target].into_iter().collect();
+        assert!(
+            !validate_tx(&CTX, tx_data, token(), keys_changed, verifiers)
+                .unwrap()
+        );
+    }
+
+    /// Test that an unknown change in the token's address space is
+    /// rejected.
+    #[test]
+    fn test_unknown_token_key_rejected() {
+        let tx_env = init_token(&[]);
+        assert!(!validate(tx_env, None, |token| {
+            let key = storage::Key::from(token.to_db_key())
+                .push(&"unknown".to_owned())
+                .unwrap();
+            tx::ctx().write(&key, 1_u64).unwrap();
+        }));
+    }
+
+    proptest! {
+        /// Test that minting signed by the authority is accepted if and
+        /// only if it keeps the supply within the cap.
+        #[test]
+        fn test_signed_mint(amount in 1..=2 * CAP) {
+            let tx_env = init_token(&[]);
+            let signer = key::testing::keypair_1();
+            let change = token::Amount::whole(amount).change();
+            let accepted = validate(tx_env, Some(signer), |token| {
+                add(&token::balance_key(token, &holder()), change);
+                add(&supply::supply_key(token), change);
+            });
+            prop_assert_eq!(accepted, SUPPLY + amount <= CAP);
+        }
+
+        /// Test that a burn is accepted if and only if the supply is
+        /// lowered by the burned amount.
+        #[test]
+        fn test_burn(burned in 1..=SUPPLY, lowered in 0..=SUPPLY) {
+            let tx_env = init_token(&[]);
+            let burned = token::Amount::whole(burned).change();
+            let lowered = token::Amount::whole(lowered).change();
+            let accepted = validate(tx_env, None, |token| {
+                add(&token::balance_key(token, &holder()), -burned);
+                add(&supply::supply_key(token), -lowered);
+            });
+            prop_assert_eq!(accepted, burned == lowered);
+        }
+
+        /// Test that balance changes of several owners are accepted if and
+        /// only if they add up to zero, as there's no supply change.
+        #[test]
+        fn test_balance_changes(
+            credits in proptest::collection::vec(1..=SUPPLY / 4, 1..4),
+            debit in 0..=SUPPLY,
+        ) {
+            let owners: Vec<Address> = credits
+                .iter()
+                .map(|_| address::testing::gen_established_address())
+                .collect();
+            let tx_env = init_token(&owners);
+            let accepted = validate(tx_env, None, |token| {
+                let debit = token::Amount::whole(debit).change();
+                add(&token::balance_key(token, &holder()), -debit);
+                for (owner, credit) in owners.iter().zip(&credits) {
+                    let credit = token::Amount::whole(*credit).change();
+                    add(&token::balance_key(token, owner), credit);
+                }
+            });
+            prop_assert_eq!(accepted, credits.iter().sum::<u64>() == debit);
+        }
+    }
 }
@@ -7,18 +7,24 @@
 //! valid signature.
 //!
 //! Any other storage key changes are allowed only with a valid signature.
+//!
+//! Debits of a token the account has a spending limit for must also be
+//! recorded and stay within the limit, see [`crate::spending_limit`].
 
 use namada_vp_prelude::address::masp;
 use namada_vp_prelude::storage::KeySeg;
 use namada_vp_prelude::*;
 use once_cell::unsync::Lazy;
 
+use crate::spending_limit::{self, LimitKey, PendingLimit, Spent};
+
 enum KeyType<'a> {
     Token(&'a Address),
     PoS,
     Vp(&'a Address),
     Masp,
     GovernanceVote(&'a Address),
+    SpendingLimit(&'a Address, &'a Address, LimitKey),
     Unknown,
 }
 
@@ -43,6 +49,10 @@ impl<'a> From<&'a storage::Key> for KeyType<'a> {
             Self::Vp(address)
         } else if token::is_masp_key(key) {
             Self::Masp
+        } else if let Some((owner, token, limit_key)) =
+            spending_limit::is_spending_limit_key(key)
+        {
+            Self::SpendingLimit(owner, token, limit_key)
         } else {
             Self::Unknown
         }
@@ -97,8 +107,17 @@ fn validate_tx(
                     let post: token::Amount =
                         ctx.read_post(key)?.unwrap_or_default();
                     let change = post.change() - pre.change();
-                    // debit has to signed, credit doesn't
-                    let valid = change >= 0 || addr == masp() || *valid_sig;
+                    // debit has to signed and within the spending limit,
+                    // credit doesn't
+                    let valid = change >= 0
+                        || addr == masp()
+                        || (*valid_sig
+                            && is_debit_recorded(
+                                ctx,
+                                &addr,
+                                key,
+                                &keys_changed,
+                            )?);
                     debug_log!(
                         "token key: {}, change: {}, valid_sig: {}, valid \
                          modification: {}",
@@ -168,6 +187,20 @@ fn validate_tx(
                 }
             }
             KeyType::Masp => true,
+            KeyType::SpendingLimit(owner, token, limit_key) => {
+                if owner == &addr {
+                    *valid_sig
+                        && is_valid_limit_change(
+                            ctx,
+                            &addr,
+                            token,
+                            limit_key,
+                            &keys_changed,
+                        )?
+                } else {
+                    true
+                }
+            }
             KeyType::Unknown => {
                 if key.segments.get(0) == Some(&addr.to_db_key()) {
                     // Unknown changes to this address space require a valid
@@ -188,6 +221,128 @@ fn validate_tx(
     accept()
 }
 
+/// The token of a balance key
+fn balance_token(key: &storage::Key) -> Option<&Address> {
+    match key.segments.first() {
+        Some(storage::DbKeySeg::AddressSeg(token)) => Some(token),
+        _ => None,
+    }
+}
+
+/// Net amount of `token` debited from `owner` by the tx, over all of its
+/// balances
+fn debited(
+    ctx: &Ctx,
+    owner: &Address,
+    token: &Address,
+    keys_changed: &BTreeSet<storage::Key>,
+) -> EnvResult<token::Amount> {
+    let mut change: token::Change = 0;
+    for key in keys_changed {
+        let key_owner = token::is_any_token_balance_key(key).or_else(|| {
+            token::is_any_multitoken_balance_key(key).map(|(_, owner)| owner)
+        });
+        if key_owner == Some(owner) && balance_token(key) == Some(token) {
+            let pre: token::Amount = ctx.read_pre(key)?.unwrap_or_default();
+            let post: token::Amount = ctx.read_post(key)?.unwrap_or_default();
+            change += post.change() - pre.change();
+        }
+    }
+    Ok(if change < 0 {
+        token::Amount::from((-change) as u64)
+    } else {
+        token::Amount::default()
+    })
+}
+
+/// Whether the debit of the balance `key` of `owner` has been added to the
+/// spending record, if the token has a limit. The record itself is checked
+/// against the debits with the other spending limit keys.
+fn is_debit_recorded(
+    ctx: &Ctx,
+    owner: &Address,
+    key: &storage::Key,
+    keys_changed: &BTreeSet<storage::Key>,
+) -> EnvResult<bool> {
+    let token = match balance_token(key) {
+        Some(token) => token,
+        None => return Ok(false),
+    };
+    let epoch = ctx.get_block_epoch()?;
+    let limit = spending_limit::read_limit(&ctx.pre(), owner, token, epoch)?;
+    let spent_key = spending_limit::spent_key(owner, token);
+    let recorded = limit.is_none() || keys_changed.contains(&spent_key);
+    debug_log!(
+        "spending limit of {} for {}: {:?}, debit recorded: {}",
+        owner,
+        token,
+        limit,
+        recorded
+    );
+    Ok(recorded)
+}
+
+/// Whether a change of a spending limit key of `owner` for `token` keeps
+/// to the limit: it can only be lowered right away, a raise has to wait for
+/// the cool-down and the debits must add up
+fn is_valid_limit_change(
+    ctx: &Ctx,
+    owner: &Address,
+    token: &Address,
+    limit_key: LimitKey,
+    keys_changed: &BTreeSet<storage::Key>,
+) -> EnvResult<bool> {
+    let epoch = ctx.get_block_epoch()?;
+    let limit = spending_limit::read_limit(&ctx.pre(), owner, token, epoch)?;
+    let valid = match limit_key {
+        LimitKey::Limit => {
+            let key = spending_limit::limit_key(owner, token);
+            let post: Option<token::Amount> = ctx.read_post(&key)?;
+            match (limit, post) {
+                (Some(limit), Some(post)) => post <= limit,
+                // The limit can't be removed
+                (Some(_), None) => false,
+                (None, _) => true,
+            }
+        }
+        LimitKey::Pending => {
+            let key = spending_limit::pending_limit_key(owner, token);
+            let post: Option<PendingLimit> = ctx.read_post(&key)?;
+            match post {
+                Some(pending) => {
+                    pending.effective
+                        >= epoch + spending_limit::COOLDOWN_EPOCHS
+                }
+                // Cancelling a raise is always fine
+                None => true,
+            }
+        }
+        LimitKey::Spent => {
+            let key = spending_limit::spent_key(owner, token);
+            let post: Option<Spent> = ctx.read_post(&key)?;
+            let pre_spent =
+                spending_limit::read_spent(&ctx.pre(), owner, token, epoch)?;
+            let debited = debited(ctx, owner, token, keys_changed)?;
+            match post {
+                Some(post) => {
+                    post.epoch == epoch
+                        && post.amount == pre_spent + debited
+                        && limit.map_or(true, |limit| post.amount <= limit)
+                }
+                None => limit.is_none(),
+            }
+        }
+    };
+    debug_log!(
+        "spending limit key {:?} of {} for {} {}",
+        limit_key,
+        owner,
+        token,
+        if valid { "accepted" } else { "rejected" }
+    );
+    Ok(valid)
+}
+
 #[cfg(test)]
 mod tests {
     use address::testing::arb_non_internal_address;
@@ -897,4 +1052,161 @@ mod tests {
                 .unwrap()
         );
     }
+
+    /// Transfer `amount` from the VP owner with a spending limit of `limit`
+    /// in a signed tx, recording the debit if `record`, and run the VP on it
+    fn limited_debit_transfer(limit: u64, amount: u64, record: bool) -> bool {
+        // Initialize a tx environment
+        let mut tx_env = TestTxEnv::default();
+
+        let vp_owner = address::testing::established_address_1();
+        let keypair = key::testing::keypair_1();
+        let public_key = keypair.ref_to();
+        let target = address::testing::established_address_2();
+        let token = address::nam();
+        let amount = token::Amount::whole(amount);
+
+        // Spawn the accounts to be able to modify their storage
+        tx_env.spawn_accounts([&vp_owner, &target, &token]);
+
+        // Credit the tokens to the VP owner before running the transaction to
+        // be able to transfer from it
+        tx_env.credit_tokens(&vp_owner, &token, None, amount);
+
+        tx_env.write_public_key(&vp_owner, &public_key);
+        tx_env
+            .wl_storage
+            .write(
+                &spending_limit::limit_key(&vp_owner, &token),
+                token::Amount::whole(limit),
+            )
+            .unwrap();
+        tx_env.commit_genesis();
+
+        // Initialize VP environment from a transaction
+        vp_host_env::init_from_tx(vp_owner.clone(), tx_env, |address| {
+            if record {
+                spending_limit::record_debit(tx::ctx(), address, &token, amount)
+                    .unwrap();
+            }
+            // Apply transfer in a transaction
+            tx_host_env::token::transfer(
+                tx::ctx(),
+                address,
+                &target,
+                &token,
+                None,
+                amount,
+                &None,
+                &None,
+            )
+            .unwrap();
+        });
+
+        let mut vp_env = vp_host_env::take();
+        let tx = vp_env.tx.clone();
+        let signed_tx = tx.sign(&keypair);
+        let tx_data: Vec<u8> = signed_tx.data.as_ref().cloned().unwrap();
+        vp_env.tx = signed_tx;
+        let keys_changed: BTreeSet<storage::Key> =
+            vp_env.all_touched_storage_keys();
+        let verifiers: BTreeSet<Address> = BTreeSet::default();
+        vp_host_env::set(vp_env);
+        validate_tx(&CTX, tx_data, vp_owner, keys_changed, verifiers).unwrap()
+    }
+
+    /// Test that a signed and recorded debit within the spending limit is
+    /// accepted.
+    #[test]
+    fn test_debit_within_spending_limit_accepted() {
+        assert!(limited_debit_transfer(100, 60, true));
+    }
+
+    /// Test that a signed and recorded debit over the spending limit is
+    /// rejected.
+    #[test]
+    fn test_debit_over_spending_limit_rejected() {
+        assert!(!limited_debit_transfer(50, 60, true));
+    }
+
+    /// Test that a signed debit of a token with a spending limit that isn't
+    /// recorded is rejected.
+    #[test]
+    fn test_unrecorded_debit_rejected() {
+        assert!(!limited_debit_transfer(100, 60, false));
+    }
+
+    proptest! {
+        /// Test that a signed tx can lower the spending limit right away,
+        /// but can only raise it with a pending limit that waits for the
+        /// cool-down.
+        #[test]
+        fn test_spending_limit_change(
+            limit in 1..1_000_u64,
+            new_limit in 1..1_000_u64,
+            // Epochs until the new limit takes effect. If `None`, the limit
+            // is changed right away instead
+            delay in proptest::option::of(0..2 * spending_limit::COOLDOWN_EPOCHS),
+        ) {
+            // Initialize a tx environment
+            let mut tx_env = TestTxEnv::default();
+
+            let vp_owner = address::testing::established_address_1();
+            let keypair = key::testing::keypair_1();
+            let public_key = keypair.ref_to();
+            let token = address::nam();
+
+            // Spawn the accounts to be able to modify their storage
+            tx_env.spawn_accounts([&vp_owner, &token]);
+            tx_env.write_public_key(&vp_owner, &public_key);
+            tx_env
+                .wl_storage
+                .write(
+                    &spending_limit::limit_key(&vp_owner, &token),
+                    token::Amount::whole(limit),
+                )
+                .unwrap();
+            tx_env.commit_genesis();
+
+            // Initialize VP environment from a transaction
+            vp_host_env::init_from_tx(vp_owner.clone(), tx_env, |address| {
+                let new_limit = token::Amount::whole(new_limit);
+                match delay {
+                    Some(delay) => {
+                        let epoch = tx::ctx().get_block_epoch().unwrap();
+                        let pending = PendingLimit {
+                            amount: new_limit,
+                            effective: epoch + delay,
+                        };
+                        let key =
+                            spending_limit::pending_limit_key(address, &token);
+                        tx::ctx().write(&key, pending).unwrap();
+                    }
+                    None => {
+                        let key = spending_limit::limit_key(address, &token);
+                        tx::ctx().write(&key, new_limit).unwrap();
+                    }
+                }
+            });
+
+            let mut vp_env = vp_host_env::take();
+            let tx = vp_env.tx.clone();
+            let signed_tx = tx.sign(&keypair);
+            let tx_data: Vec<u8> = signed_tx.data.as_ref().cloned().unwrap();
+            vp_env.tx = signed_tx;
+            let keys_changed: BTreeSet<storage::Key> =
+                vp_env.all_touched_storage_keys();
+            let verifiers: BTreeSet<Address> = BTreeSet::default();
+            vp_host_env::set(vp_env);
+            let expected = match delay {
+                Some(delay) => delay >= spending_limit::COOLDOWN_EPOCHS,
+                None => new_limit <= limit,
+            };
+            prop_assert_eq!(
+                validate_tx(&CTX, tx_data, vp_owner, keys_changed, verifiers)
+                    .unwrap(),
+                expected
+            );
+        }
+    }
 }
@@ -0,0 +1,576 @@
+//! A vesting account VP.
+//!
+//! This VP works like `vp_user`, except that the account can also hold a
+//! vesting schedule of a token under [`schedule_key`], which unlocks the
+//! amounts of its tranches in their epochs. A debit of the token must leave
+//! at least the amount that hasn't vested yet by the current epoch on the
+//! account, unless it is bonded. Note that the tokens bonded out of the
+//! unvested amount aren't counted as still held by the account, so vested
+//! tokens can only be spent once the bonded ones have been withdrawn.
+//!
+//! The schedule can only be written by `tx_init_vesting_account` when the
+//! account is initialized, and is fixed from then on. The VP can't be
+//! replaced until everything has vested.
+
+use namada_vp_prelude::address::masp;
+use namada_vp_prelude::storage::KeySeg;
+use namada_vp_prelude::*;
+use once_cell::unsync::Lazy;
+
+use crate::vesting::{schedule_key, VestingSchedule};
+
+enum KeyType<'a> {
+    Token(&'a Address),
+    PoS,
+    Vp(&'a Address),
+    Masp,
+    GovernanceVote(&'a Address),
+    VestingSchedule,
+    Unknown,
+}
+
+impl<'a> KeyType<'a> {
+    fn new(key: &'a storage::Key, owner: &Address) -> KeyType<'a> {
+        if let Some(address) = token::is_any_token_balance_key(key) {
+            Self::Token(address)
+        } else if let Some((_, address)) =
+            token::is_any_multitoken_balance_key(key)
+        {
+            Self::Token(address)
+        } else if proof_of_stake::is_pos_key(key) {
+            Self::PoS
+        } else if gov_storage::is_vote_key(key) {
+            let voter_address = gov_storage::get_voter_address(key);
+            if let Some(address) = voter_address {
+                Self::GovernanceVote(address)
+            } else {
+                Self::Unknown
+            }
+        } else if let Some(address) = key.is_validity_predicate() {
+            Self::Vp(address)
+        } else if token::is_masp_key(key) {
+            Self::Masp
+        } else if key == &schedule_key(owner) {
+            Self::VestingSchedule
+        } else {
+            Self::Unknown
+        }
+    }
+}
+
+#[validity_predicate]
+fn validate_tx(
+    ctx: &Ctx,
+    tx_data: Vec<u8>,
+    addr: Address,
+    keys_changed: BTreeSet<storage::Key>,
+    verifiers: BTreeSet<Address>,
+) -> VpResult {
+    debug_log!(
+        "vp_vesting called with user addr: {}, key_changed: {:?}, \
+         verifiers: {:?}",
+        addr,
+        keys_changed,
+        verifiers
+    );
+
+    let signed_tx_data =
+        Lazy::new(|| SignedTxData::try_from_slice(&tx_data[..]));
+
+    let valid_sig = Lazy::new(|| match &*signed_tx_data {
+        Ok(signed_tx_data) => {
+            let pk = key::get(ctx, &addr);
+            match pk {
+                Ok(Some(pk)) => {
+                    matches!(
+                        ctx.verify_tx_signature(&pk, &signed_tx_data.sig),
+                        Ok(true)
+                    )
+                }
+                _ => false,
+            }
+        }
+        _ => false,
+    });
+
+    let schedule: Option<VestingSchedule> =
+        ctx.read_pre(&schedule_key(&addr))?;
+    let unvested = match &schedule {
+        Some(schedule) => schedule.unvested(ctx.get_block_epoch()?),
+        None => token::Amount::default(),
+    };
+
+    if !is_valid_tx(ctx, &tx_data)? {
+        return reject();
+    }
+
+    for key in keys_changed.iter() {
+        let key_type = KeyType::new(key, &addr);
+        let is_valid = match key_type {
+            KeyType::Token(owner) => {
+                if owner == &addr {
+                    let pre: token::Amount =
+                        ctx.read_pre(key)?.unwrap_or_default();
+                    let post: token::Amount =
+                        ctx.read_post(key)?.unwrap_or_default();
+                    let change = post.change() - pre.change();
+                    let is_vesting = schedule.as_ref().map_or(false, |s| {
+                        key == &token::balance_key(&s.token, &addr)
+                    });
+                    // debit has to signed and leave the unvested amount on
+                    // the account, credit doesn't
+                    let valid = change >= 0
+                        || addr == masp()
+                        || (*valid_sig
+                            && (!is_vesting
+                                || post + bonded(ctx, &addr, &keys_changed)?
+                                    >= unvested));
+                    debug_log!(
+                        "token key: {}, change: {}, valid_sig: {}, unvested: \
+                         {}, valid modification: {}",
+                        key,
+                        change,
+                        *valid_sig,
+                        unvested,
+                        valid
+                    );
+                    valid
+                } else {
+                    debug_log!(
+                        "This address ({}) is not of owner ({}) of token key: \
+                         {}",
+                        addr,
+                        owner,
+                        key
+                    );
+                    // If this is not the owner, allow any change
+                    true
+                }
+            }
+            KeyType::PoS => {
+                // Allow the account to be used in PoS, including bonding the
+                // unvested tokens
+                let bond_id = proof_of_stake::is_bond_key(key)
+                    .map(|(bond_id, _)| bond_id)
+                    .or_else(|| {
+                        proof_of_stake::is_unbond_key(key)
+                            .map(|(bond_id, _, _)| bond_id)
+                    });
+                let valid = match bond_id {
+                    Some(bond_id) => {
+                        // Bonds and unbonds changes for this address
+                        // must be signed
+                        bond_id.source != addr || *valid_sig
+                    }
+                    None => {
+                        // Any other PoS changes are allowed without signature
+                        true
+                    }
+                };
+                debug_log!(
+                    "PoS key {} {}",
+                    key,
+                    if valid { "accepted" } else { "rejected" }
+                );
+                valid
+            }
+            KeyType::GovernanceVote(voter) => {
+                if voter == &addr {
+                    *valid_sig
+                } else {
+                    true
+                }
+            }
+            KeyType::Vp(owner) => {
+                let has_post: bool = ctx.has_key_post(key)?;
+                if owner == &addr {
+                    if has_post {
+                        // The VP can't be replaced while tokens are locked
+                        let vp: Vec<u8> = ctx.read_bytes_post(key)?.unwrap();
+                        *valid_sig
+                            && unvested == token::Amount::default()
+                            && is_vp_whitelisted(ctx, &vp)?
+                    } else {
+                        false
+                    }
+                } else {
+                    let vp: Vec<u8> = ctx.read_bytes_post(key)?.unwrap();
+                    is_vp_whitelisted(ctx, &vp)?
+                }
+            }
+            KeyType::Masp => true,
+            KeyType::VestingSchedule => {
+                // The schedule can only be set together with the account's
+                // VP, when the account is initialized
+                !ctx.has_key_pre(&storage::Key::validity_predicate(&addr))?
+            }
+            KeyType::Unknown => {
+                if key.segments.get(0) == Some(&addr.to_db_key()) {
+                    // Unknown changes to this address space require a valid
+                    // signature
+                    *valid_sig
+                } else {
+                    // Unknown changes anywhere else are permitted
+                    true
+                }
+            }
+        };
+        if !is_valid {
+            debug_log!("key {} modification failed vp", key);
+            return reject();
+        }
+    }
+
+    accept()
+}
+
+/// The amount the tx bonds from `owner`, which stays locked in PoS
+fn bonded(
+    ctx: &Ctx,
+    owner: &Address,
+    keys_changed: &BTreeSet<storage::Key>,
+) -> EnvResult<token::Amount> {
+    let mut change: token::Change = 0;
+    for key in keys_changed {
+        match proof_of_stake::is_bond_key(key) {
+            Some((bond_id, _)) if &bond_id.source == owner => {
+                let pre: token::Amount =
+                    ctx.read_pre(key)?.unwrap_or_default();
+                let post: token::Amount =
+                    ctx.read_post(key)?.unwrap_or_default();
+                change += post.change() - pre.change();
+            }
+            _ => {}
+        }
+    }
+    Ok(if change > 0 {
+        token::Amount::from(change as u64)
+    } else {
+        token::Amount::default()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use namada::ledger::pos::{GenesisValidator, PosParams};
+    use namada::types::storage::Epoch;
+    // Use this as `#[test]` annotation to enable logging
+    use namada_tests::log::test;
+    use namada_tests::native_vp::pos::init_pos;
+    use namada_tests::tx::{self, tx_host_env, TestTxEnv};
+    use namada_tests::vp::*;
+    use namada_tx_prelude::{StorageWrite, TxEnv};
+    use namada_vp_prelude::key::RefTo;
+    use proptest::prelude::*;
+
+    use super::*;
+    use crate::vesting::Tranche;
+
+    const VP_ALWAYS_TRUE_WASM: &str =
+        "../../wasm_for_tests/vp_always_true.wasm";
+
+    /// A schedule of NAM that vests 100 tokens in each of the epochs 1 to 5
+    fn schedule() -> VestingSchedule {
+        VestingSchedule {
+            token: address::nam(),
+            tranches: (1..=5)
+                .map(|epoch| Tranche {
+                    epoch: Epoch(epoch),
+                    amount: token::Amount::whole(100),
+                })
+                .collect(),
+        }
+    }
+
+    /// Run the VP on a signed tx that transfers `amount` of the vesting
+    /// token out of an account with [`schedule`] and a balance of 500
+    /// tokens, in `epoch`
+    fn signed_vesting_debit(epoch: Epoch, amount: token::Amount) -> bool {
+        // Initialize a tx environment
+        let mut tx_env = TestTxEnv::default();
+
+        let vp_owner = address::testing::established_address_1();
+        let keypair = key::testing::keypair_1();
+        let public_key = keypair.ref_to();
+        let target = address::testing::established_address_2();
+        let token = address::nam();
+
+        // Spawn the accounts to be able to modify their storage
+        tx_env.spawn_accounts([&vp_owner, &target, &token]);
+
+        // Credit the tokens to the VP owner before running the transaction to
+        // be able to transfer from it
+        tx_env.credit_tokens(
+            &vp_owner,
+            &token,
+            None,
+            token::Amount::whole(500),
+        );
+        tx_env.write_public_key(&vp_owner, &public_key);
+        tx_env
+            .wl_storage
+            .write(&schedule_key(&vp_owner), schedule())
+            .unwrap();
+        tx_env.wl_storage.storage.block.epoch = epoch;
+        tx_env.commit_genesis();
+
+        // Initialize VP environment from a transaction
+        vp_host_env::init_from_tx(vp_owner.clone(), tx_env, |address| {
+            // Apply transfer in a transaction
+            tx_host_env::token::transfer(
+                tx::ctx(),
+                address,
+                &target,
+                &token,
+                None,
+                amount,
+                &None,
+                &None,
+            )
+            .unwrap();
+        });
+
+        let mut vp_env = vp_host_env::take();
+        let tx = vp_env.tx.clone();
+        let signed_tx = tx.sign(&keypair);
+        let tx_data: Vec<u8> = signed_tx.data.as_ref().cloned().unwrap();
+        vp_env.tx = signed_tx;
+        let keys_changed: BTreeSet<storage::Key> =
+            vp_env.all_touched_storage_keys();
+        let verifiers: BTreeSet<Address> = BTreeSet::default();
+        vp_host_env::set(vp_env);
+        validate_tx(&CTX, tx_data, vp_owner, keys_changed, verifiers).unwrap()
+    }
+
+    /// Test that no-op transaction (i.e. no storage modifications) accepted.
+    #[test]
+    fn test_no_op_transaction() {
+        let tx_data: Vec<u8> = vec![];
+        let addr: Address = address::testing::established_address_1();
+        let keys_changed: BTreeSet<storage::Key> = BTreeSet::default();
+        let verifiers: BTreeSet<Address> = BTreeSet::default();
+
+        // The VP env must be initialized before calling `validate_tx`
+        vp_host_env::init();
+
+        assert!(
+            validate_tx(&CTX, tx_data, addr, keys_changed, verifiers).unwrap()
+        );
+    }
+
+    proptest! {
+        /// Test that a signed debit of the vesting token is accepted if and
+        /// only if it leaves the unvested amount on the account.
+        #[test]
+        fn test_signed_vesting_debit(
+            epoch in 0..7_u64,
+            amount in 1..=500_u64,
+        ) {
+            let epoch = Epoch(epoch);
+            let amount = token::Amount::whole(amount);
+            let vested =
+                token::Amount::whole(500) - schedule().unvested(epoch);
+            prop_assert_eq!(
+                signed_vesting_debit(epoch, amount),
+                amount <= vested
+            );
+        }
+    }
+
+    /// Test that the unvested tokens can be bonded.
+    #[test]
+    fn test_bond_unvested_accepted() {
+        // Init PoS genesis
+        let pos_params = PosParams::default();
+        let validator = address::testing::established_address_3();
+        let initial_stake = token::Amount::from(10_098_123);
+        let consensus_key = key::testing::keypair_2().ref_to();
+        let commission_rate = rust_decimal::Decimal::new(5, 2);
+        let max_commission_rate_change = rust_decimal::Decimal::new(1, 2);
+
+        let genesis_validators = [GenesisValidator {
+            address: validator.clone(),
+            tokens: initial_stake,
+            consensus_key,
+            commission_rate,
+            max_commission_rate_change,
+        }];
+
+        init_pos(&genesis_validators[..], &pos_params, Epoch(0));
+
+        // Initialize a tx environment
+        let mut tx_env = tx_host_env::take();
+
+        let secret_key = key::testing::keypair_1();
+        let public_key = secret_key.ref_to();
+        let vp_owner: Address = address::testing::established_address_2();
+        let token = address::nam();
+
+        // Spawn the accounts to be able to modify their storage
+        tx_env.spawn_accounts([&vp_owner, &token]);
+
+        // Credit all the tokens of the schedule, none of which have vested
+        tx_env.credit_tokens(
+            &vp_owner,
+            &token,
+            None,
+            token::Amount::whole(500),
+        );
+        tx_env.write_public_key(&vp_owner, &public_key);
+        tx_env
+            .wl_storage
+            .write(&schedule_key(&vp_owner), schedule())
+            .unwrap();
+        tx_env.commit_genesis();
+
+        // Initialize VP environment from a transaction
+        vp_host_env::init_from_tx(vp_owner.clone(), tx_env, |_address| {
+            // Bond all the tokens
+            tx::ctx()
+                .bond_tokens(
+                    Some(&vp_owner),
+                    &validator,
+                    token::Amount::whole(500),
+                )
+                .unwrap();
+        });
+
+        let mut vp_env = vp_host_env::take();
+        let tx = vp_env.tx.clone();
+        let signed_tx = tx.sign(&secret_key);
+        let tx_data: Vec<u8> = signed_tx.data.as_ref().cloned().unwrap();
+        vp_env.tx = signed_tx;
+        let keys_changed: BTreeSet<storage::Key> =
+            vp_env.all_touched_storage_keys();
+        let verifiers: BTreeSet<Address> = BTreeSet::default();
+        vp_host_env::set(vp_env);
+        assert!(
+            validate_tx(&CTX, tx_data, vp_owner, keys_changed, verifiers)
+                .unwrap()
+        );
+    }
+
+    /// Test that a signed tx can't change the vesting schedule once it's
+    /// set.
+    #[test]
+    fn test_schedule_change_rejected() {
+        // Initialize a tx environment
+        let mut tx_env = TestTxEnv::default();
+
+        let vp_owner = address::testing::established_address_1();
+        let keypair = key::testing::keypair_1();
+        let public_key = keypair.ref_to();
+
+        // Spawn the accounts to be able to modify their storage
+        tx_env.spawn_accounts([&vp_owner]);
+        tx_env.write_public_key(&vp_owner, &public_key);
+        tx_env
+            .wl_storage
+            .write(&schedule_key(&vp_owner), schedule())
+            .unwrap();
+        tx_env.commit_genesis();
+
+        // Initialize VP environment from a transaction
+        vp_host_env::init_from_tx(vp_owner.clone(), tx_env, |address| {
+            // Remove the schedule in a transaction
+            tx::ctx().delete(&schedule_key(address)).unwrap();
+        });
+
+        let mut vp_env = vp_host_env::take();
+        let tx = vp_env.tx.clone();
+        let signed_tx = tx.sign(&keypair);
+        let tx_data: Vec<u8> = signed_tx.data.as_ref().cloned().unwrap();
+        vp_env.tx = signed_tx;
+        let keys_changed: BTreeSet<storage::Key> =
+            vp_env.all_touched_storage_keys();
+        let verifiers: BTreeSet<Address> = BTreeSet::default();
+        vp_host_env::set(vp_env);
+        assert!(
+            !validate_tx(&CTX, tx_data, vp_owner, keys_changed, verifiers)
+                .unwrap()
+        );
+    }
+
+    /// Test that a signed tx can't add a vesting schedule to an existing
+    /// account.
+    #[test]
+    fn test_schedule_on_existing_account_rejected() {
+        // Initialize a tx environment
+        let mut tx_env = TestTxEnv::default();
+
+        let vp_owner = address::testing::established_address_1();
+        let keypair = key::testing::keypair_1();
+        let public_key = keypair.ref_to();
+
+        // Spawn the accounts to be able to modify their storage
+        tx_env.spawn_accounts([&vp_owner]);
+        tx_env.write_public_key(&vp_owner, &public_key);
+        tx_env.commit_genesis();
+
+        // Initialize VP environment from a transaction
+        vp_host_env::init_from_tx(vp_owner.clone(), tx_env, |address| {
+            // Set a schedule in a transaction
+            tx::ctx().write(&schedule_key(address), schedule()).unwrap();
+        });
+
+        let mut vp_env = vp_host_env::take();
+        let tx = vp_env.tx.clone();
+        let signed_tx = tx.sign(&keypair);
+        let tx_data: Vec<u8> = signed_tx.data.as_ref().cloned().unwrap();
+        vp_env.tx = signed_tx;
+        let keys_changed: BTreeSet<storage::Key> =
+            vp_env.all_touched_storage_keys();
+        let verifiers: BTreeSet<Address> = BTreeSet::default();
+        vp_host_env::set(vp_env);
+        assert!(
+            !validate_tx(&CTX, tx_data, vp_owner, keys_changed, verifiers)
+                .unwrap()
+        );
+    }
+
+    /// Test that a signed validity predicate update is rejected while there
+    /// are unvested tokens.
+    #[test]
+    fn test_vp_update_before_vesting_rejected() {
+        // Initialize a tx environment
+        let mut tx_env = TestTxEnv::default();
+        tx_env.init_parameters(None, None, None);
+
+        let vp_owner = address::testing::established_address_1();
+        let keypair = key::testing::keypair_1();
+        let public_key = keypair.ref_to();
+        let vp_code =
+            std::fs::read(VP_ALWAYS_TRUE_WASM).expect("cannot load wasm");
+
+        // Spawn the accounts to be able to modify their storage
+        tx_env.spawn_accounts([&vp_owner]);
+        tx_env.write_public_key(&vp_owner, &public_key);
+        tx_env
+            .wl_storage
+            .write(&schedule_key(&vp_owner), schedule())
+            .unwrap();
+        tx_env.commit_genesis();
+
+        // Initialize VP environment from a transaction
+        vp_host_env::init_from_tx(vp_owner.clone(), tx_env, |address| {
+            // Update VP in a transaction
+            tx::ctx()
+                .update_validity_predicate(address, &vp_code)
+                .unwrap();
+        });
+
+        let mut vp_env = vp_host_env::take();
+        let tx = vp_env.tx.clone();
+        let signed_tx = tx.sign(&keypair);
+        let tx_data: Vec<u8> = signed_tx.data.as_ref().cloned().unwrap();
+        vp_env.tx = signed_tx;
+        let keys_changed: BTreeSet<storage::Key> =
+            vp_env.all_touched_storage_keys();
+        let verifiers: BTreeSet<Address> = BTreeSet::default();
+        vp_host_env::set(vp_env);
+        assert!(
+            !validate_tx(&CTX, tx_data, vp_owner, keys_changed, verifiers)
+                .unwrap()
+        );
+    }
+}